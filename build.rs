@@ -0,0 +1,53 @@
+//! Builds the eBPF program when the `ebpf` feature is enabled. That needs clang and bpftool, or
+//! `TRACER_EBPF_OBJECT` pointing to a prebuilt object.
+use std::{env, fs, path::PathBuf, process::Command};
+
+const PROGRAM_SOURCE: &str = "src/extracts/ebpf/process_events.bpf.c";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EBPF").is_none() {
+        return;
+    }
+    println!("cargo:rerun-if-changed={PROGRAM_SOURCE}");
+    println!("cargo:rerun-if-env-changed=TRACER_EBPF_OBJECT");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let object = out_dir.join("process_events.bpf.o");
+
+    if let Some(prebuilt) = env::var_os("TRACER_EBPF_OBJECT") {
+        fs::copy(prebuilt, &object).expect("Failed to copy TRACER_EBPF_OBJECT");
+        return;
+    }
+
+    // the program is compiled once against the running kernel's types and relocated on load
+    let vmlinux = Command::new("bpftool")
+        .args([
+            "btf",
+            "dump",
+            "file",
+            "/sys/kernel/btf/vmlinux",
+            "format",
+            "c",
+        ])
+        .output()
+        .expect("bpftool is needed to build the ebpf feature");
+    assert!(vmlinux.status.success(), "Failed to dump the kernel BTF");
+    fs::write(out_dir.join("vmlinux.h"), vmlinux.stdout).unwrap();
+
+    let arch = match env::var("CARGO_CFG_TARGET_ARCH").unwrap().as_str() {
+        "x86_64" => "x86".to_string(),
+        "aarch64" => "arm64".to_string(),
+        arch => arch.to_string(),
+    };
+    let status = Command::new("clang")
+        .args(["-O2", "-g", "-target", "bpf"])
+        .arg(format!("-D__TARGET_ARCH_{arch}"))
+        .arg("-I")
+        .arg(&out_dir)
+        .args(["-c", PROGRAM_SOURCE, "-o"])
+        .arg(&object)
+        .status()
+        .expect("clang is needed to build the ebpf feature");
+    assert!(status.success(), "Failed to compile {PROGRAM_SOURCE}");
+}
@@ -15,7 +15,7 @@ async fn main() {
 
     tokio::spawn(loki_task);
 
-    let collector = SystemMetricsCollector::new();
+    let mut collector = SystemMetricsCollector::new();
     let pipeline_name = "otel_pipeline".to_string();
     let run_name = "local_otel_compliance_123".to_string();
     let mut recorder =
@@ -0,0 +1,112 @@
+-- Typed tables next to the raw batch_jobs_logs, one row per run, tool execution and sample
+CREATE TABLE IF NOT EXISTS runs (
+    run_id TEXT PRIMARY KEY,
+    run_name TEXT,
+    pipeline_name TEXT,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    hostname TEXT,
+    os TEXT,
+    os_version TEXT,
+    kernel_version TEXT,
+    arch TEXT,
+    num_cpus INTEGER,
+    total_memory BIGINT,
+    total_swap BIGINT,
+    is_aws_instance BOOLEAN,
+    ec2_cost_per_hour DOUBLE PRECISION,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS runs_pipeline_name_idx ON runs (pipeline_name);
+CREATE INDEX IF NOT EXISTS runs_started_at_idx ON runs (started_at);
+
+CREATE TABLE IF NOT EXISTS tool_executions (
+    id BIGSERIAL PRIMARY KEY,
+    run_id TEXT NOT NULL REFERENCES runs (run_id) ON DELETE CASCADE,
+    pipeline_name TEXT,
+    tool_name TEXT NOT NULL,
+    tool_pid TEXT NOT NULL,
+    tool_parent_pid TEXT,
+    tool_binary_path TEXT,
+    tool_cmd TEXT,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ,
+    duration_sec BIGINT,
+    UNIQUE (run_id, tool_pid, started_at)
+);
+
+CREATE INDEX IF NOT EXISTS tool_executions_run_id_idx ON tool_executions (run_id);
+CREATE INDEX IF NOT EXISTS tool_executions_pipeline_tool_idx ON tool_executions (pipeline_name, tool_name, started_at);
+CREATE INDEX IF NOT EXISTS tool_executions_started_at_idx ON tool_executions (started_at);
+
+CREATE TABLE IF NOT EXISTS process_samples (
+    id BIGSERIAL PRIMARY KEY,
+    run_id TEXT NOT NULL REFERENCES runs (run_id) ON DELETE CASCADE,
+    tool_execution_id BIGINT REFERENCES tool_executions (id) ON DELETE CASCADE,
+    tool_name TEXT NOT NULL,
+    tool_pid TEXT NOT NULL,
+    sampled_at TIMESTAMPTZ NOT NULL,
+    cpu_utilization REAL NOT NULL,
+    memory_usage BIGINT NOT NULL,
+    memory_virtual BIGINT NOT NULL,
+    run_time_sec BIGINT NOT NULL,
+    disk_read_bytes_last_interval BIGINT NOT NULL,
+    disk_write_bytes_last_interval BIGINT NOT NULL,
+    disk_read_bytes_total BIGINT NOT NULL,
+    disk_write_bytes_total BIGINT NOT NULL,
+    status TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS process_samples_run_id_idx ON process_samples (run_id);
+CREATE INDEX IF NOT EXISTS process_samples_tool_execution_id_idx ON process_samples (tool_execution_id);
+CREATE INDEX IF NOT EXISTS process_samples_sampled_at_idx ON process_samples (sampled_at);
+
+CREATE TABLE IF NOT EXISTS system_samples (
+    id BIGSERIAL PRIMARY KEY,
+    run_id TEXT NOT NULL REFERENCES runs (run_id) ON DELETE CASCADE,
+    sampled_at TIMESTAMPTZ NOT NULL,
+    memory_total BIGINT NOT NULL,
+    memory_used BIGINT NOT NULL,
+    memory_available BIGINT NOT NULL,
+    memory_utilization DOUBLE PRECISION NOT NULL,
+    swap_total BIGINT NOT NULL,
+    swap_used BIGINT NOT NULL,
+    cpu_utilization REAL NOT NULL,
+    disks JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS system_samples_run_id_idx ON system_samples (run_id);
+CREATE INDEX IF NOT EXISTS system_samples_sampled_at_idx ON system_samples (sampled_at);
+
+CREATE TABLE IF NOT EXISTS syslog_errors (
+    id BIGSERIAL PRIMARY KEY,
+    run_id TEXT NOT NULL REFERENCES runs (run_id) ON DELETE CASCADE,
+    logged_at TIMESTAMPTZ NOT NULL,
+    error_id TEXT NOT NULL,
+    error_display_name TEXT NOT NULL,
+    error_line TEXT NOT NULL,
+    file_line_number BIGINT NOT NULL,
+    previous_lines TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS syslog_errors_run_id_idx ON syslog_errors (run_id);
+CREATE INDEX IF NOT EXISTS syslog_errors_logged_at_idx ON syslog_errors (logged_at);
+
+CREATE TABLE IF NOT EXISTS files (
+    id BIGSERIAL PRIMARY KEY,
+    run_id TEXT NOT NULL REFERENCES runs (run_id) ON DELETE CASCADE,
+    tool_execution_id BIGINT REFERENCES tool_executions (id) ON DELETE CASCADE,
+    tool_pid TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    file_directory TEXT NOT NULL,
+    file_size BIGINT NOT NULL,
+    file_updated_at TEXT NOT NULL,
+    seen_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (run_id, tool_pid, file_path)
+);
+
+CREATE INDEX IF NOT EXISTS files_run_id_idx ON files (run_id);
+CREATE INDEX IF NOT EXISTS files_file_path_idx ON files (file_path);
@@ -0,0 +1,8 @@
+-- How a tool execution ended, NULL when the exit status could not be captured
+ALTER TABLE tool_executions
+    ADD COLUMN IF NOT EXISTS exit_code INTEGER,
+    ADD COLUMN IF NOT EXISTS signal INTEGER,
+    ADD COLUMN IF NOT EXISTS oom_killed BOOLEAN;
+
+CREATE INDEX IF NOT EXISTS tool_executions_failed_idx ON tool_executions (run_id)
+    WHERE exit_code <> 0 OR signal IS NOT NULL OR oom_killed;
@@ -0,0 +1,9 @@
+-- Resource usage over the lifetime of a tool execution, NULL for executions recorded before it
+-- was tracked
+ALTER TABLE tool_executions
+    ADD COLUMN IF NOT EXISTS peak_memory_usage BIGINT,
+    ADD COLUMN IF NOT EXISTS peak_memory_virtual BIGINT,
+    ADD COLUMN IF NOT EXISTS cpu_time_sec DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS average_cpu_utilization DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS disk_read_bytes BIGINT,
+    ADD COLUMN IF NOT EXISTS disk_write_bytes BIGINT;
@@ -15,7 +15,8 @@ use crate::{
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use nondaemon_commands::{
-    clean_up_after_daemon, print_config_info_sync, setup_config, update_tracer,
+    clean_up_after_daemon, print_config_info_sync, print_lineage_sync, print_status_sync,
+    setup_config, update_tracer,
 };
 
 use std::{env, fs::canonicalize};
@@ -69,6 +70,24 @@ pub enum Commands {
     /// Shows the current configuration and the daemon status
     Info,
 
+    /// Shows live daemon health: uptime, current run, tracked processes, buffers and exports
+    Status {
+        /// Print the status as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Print the lineage graph of a run, linking tools through the files they read and wrote, as
+    /// JSON
+    Lineage {
+        /// ID of the run, defaults to the current run. The daemon keeps the last ended runs.
+        #[clap(long)]
+        run: Option<String>,
+        /// Print Graphviz DOT instead
+        #[clap(long)]
+        dot: bool,
+    },
+
     /// Update the daemon to the latest version
     Update,
 
@@ -141,6 +160,8 @@ pub fn process_cli() -> Result<()> {
         }
         Commands::ApplyBashrc => ConfigManager::setup_aliases(),
         Commands::Info => print_config_info_sync(),
+        Commands::Status { json } => print_status_sync(json),
+        Commands::Lineage { run, dot } => print_lineage_sync(run, dot),
         _ => run_async_command(cli.command),
     }
 }
@@ -191,10 +212,12 @@ pub async fn run_async_command(commands: Commands) -> Result<()> {
         }
     };
 
-    if result.is_err() {
-        println!("Failed to send command to the daemon. Maybe the daemon is not running? If it's not, run `tracer init` to start the daemon.");
-    } else {
-        println!("Command sent successfully.")
+    match result {
+        Ok(()) => println!("Command sent successfully."),
+        Err(err) if err.downcast_ref::<std::io::Error>().is_some() => {
+            println!("Failed to send command to the daemon. Maybe the daemon is not running? If it's not, run `tracer init` to start the daemon.");
+        }
+        Err(err) => println!("Command failed: {err:#}"),
     }
 
     Ok(())
@@ -5,7 +5,15 @@ use std::result::Result::Ok;
 
 use crate::{
     config_manager::{ConfigManager, INTERCEPTOR_STDOUT_FILE},
-    daemon_communication::client::{send_info_request, send_refresh_config_request},
+    daemon_communication::{
+        client::{
+            send_info_request, send_lineage_request, send_refresh_config_request,
+            send_status_request,
+        },
+        structs::{ExportAttempt, StatusResponse},
+    },
+    events::lineage::to_dot,
+    extracts::file_watcher::remove_cached_files,
     FILE_CACHE_DIR, PID_FILE, REPO_NAME, REPO_OWNER, SOCKET_PATH, STDERR_FILE, STDOUT_FILE,
 };
 
@@ -14,7 +22,7 @@ pub fn clean_up_after_daemon() -> Result<()> {
     std::fs::remove_file(STDOUT_FILE).context("Failed to remove stdout file")?;
     std::fs::remove_file(STDERR_FILE).context("Failed to remove stderr file")?;
     let _ = std::fs::remove_file(INTERCEPTOR_STDOUT_FILE).context("Failed to remove stdout file");
-    std::fs::remove_dir_all(FILE_CACHE_DIR).context("Failed to remove cache directory")?;
+    remove_cached_files(FILE_CACHE_DIR).context("Failed to clear cache directory")?;
     Ok(())
 }
 
@@ -51,6 +59,125 @@ pub fn print_config_info_sync() -> Result<()> {
     Ok(())
 }
 
+fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    format!("{hours}h {minutes}m {seconds}s")
+}
+
+fn format_export_attempt(attempt: &Option<ExportAttempt>) -> String {
+    match attempt {
+        Some(attempt) => {
+            let mut out = format!("{} ({} events)", attempt.timestamp, attempt.events);
+            if let Some(error) = &attempt.error {
+                out.push_str(&format!(": {error}"));
+            }
+            out
+        }
+        None => "never".to_string(),
+    }
+}
+
+fn print_status_human(status: &StatusResponse) {
+    println!("Daemon status: Running");
+    println!("Daemon version: {}", status.daemon_version);
+    println!(
+        "Uptime: {} (since {})",
+        format_duration(status.uptime_sec),
+        status.started_at
+    );
+    match &status.run {
+        Some(run) => {
+            println!("Run name: {}", run.run_name);
+            println!("Run ID: {}", run.run_id);
+            println!("Pipeline name: {}", run.pipeline_name);
+        }
+        None => println!("Run: none"),
+    }
+    println!("Tracked processes: {}", status.tracked_processes);
+    println!(
+        "Events since last flush: {}",
+        status.pending_events.values().sum::<u64>()
+    );
+    for (event_type, count) in &status.pending_events {
+        println!("  {event_type}: {count}");
+    }
+    println!(
+        "Last successful export: {}",
+        format_export_attempt(&status.last_export_success)
+    );
+    println!(
+        "Last failed export: {}",
+        format_export_attempt(&status.last_export_failure)
+    );
+    println!("Spooled batches: {}", status.spooled_batches);
+    println!(
+        "Buffered lines: syslog {}, stdout {}, stderr {}",
+        status.syslog_buffer_depth, status.stdout_buffer_depth, status.stderr_buffer_depth
+    );
+    match &status.last_file_poll {
+        Some(poll) => {
+            print!(
+                "Last file poll: {} ({} files found, {} watched)",
+                poll.timestamp, poll.files_found, poll.files_watched
+            );
+            match &poll.error {
+                Some(error) => println!(": {error}"),
+                None => println!(),
+            }
+        }
+        None => println!("Last file poll: never"),
+    }
+}
+
+pub async fn print_status(json: bool) -> Result<()> {
+    let status = send_status_request(SOCKET_PATH).await;
+
+    match (status, json) {
+        (Ok(status), true) => println!("{}", serde_json::to_string_pretty(&status)?),
+        (Ok(status), false) => print_status_human(&status),
+        (Err(err), true) => println!(
+            "{}",
+            serde_json::json!({ "daemon_status": "stopped", "error": format!("{err:#}") })
+        ),
+        (Err(err), false) => {
+            println!("Daemon status: Stopped");
+            println!("Error: {err:#}");
+        }
+    }
+    Ok(())
+}
+
+pub fn print_status_sync(json: bool) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(print_status(json))
+}
+
+pub async fn print_lineage(run_id: Option<String>, dot: bool) -> Result<()> {
+    let lineage = match send_lineage_request(SOCKET_PATH, run_id).await {
+        Ok(lineage) => lineage,
+        Err(err) if err.downcast_ref::<std::io::Error>().is_some() => {
+            println!("Failed to reach the daemon. Maybe the daemon is not running? If it's not, run `tracer init` to start the daemon.");
+            return Ok(());
+        }
+        Err(err) => {
+            println!("Failed to get the lineage: {err:#}");
+            return Ok(());
+        }
+    };
+
+    if dot {
+        print!("{}", to_dot(&lineage));
+    } else {
+        println!("{}", serde_json::to_string_pretty(&lineage)?);
+    }
+    Ok(())
+}
+
+pub fn print_lineage_sync(run_id: Option<String>, dot: bool) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(print_lineage(run_id, dot))
+}
+
 pub async fn setup_config(
     api_key: &Option<String>,
     service_url: &Option<String>,
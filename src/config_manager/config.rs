@@ -13,10 +13,14 @@ use crate::{
         target_process::target_matching::TargetMatch,
     },
     events::send_daemon_start_event,
-    types::{aws::aws_region::AwsRegion, config::AwsConfig},
+    types::{
+        aws::aws_region::AwsRegion,
+        config::{AwsConfig, ExporterConfig},
+    },
 };
 
 use crate::config_manager::target_process::Target;
+use crate::FILE_CACHE_DIR;
 
 use super::target_process::targets_list;
 
@@ -28,6 +32,21 @@ const BATCH_SUBMISSION_INTERVAL_MS: u64 = 10000;
 const NEW_RUN_PAUSE_MS: u64 = 10 * 60 * 1000;
 const PROCESS_METRICS_SEND_INTERVAL_MS: u64 = 10000;
 const FILE_SIZE_NOT_CHANGING_PERIOD_MS: u64 = 1000 * 60;
+const SPOOL_MAX_SIZE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Inside the cache directory, which is the daemon's scratch space.
+fn default_spool_dir() -> String {
+    format!("{FILE_CACHE_DIR}/spool")
+}
+
+fn default_exporters() -> Vec<ExporterConfig> {
+    vec![ExporterConfig::Postgres {
+        db_url: None,
+        pool_size: None,
+        schema: None,
+        insert_chunk_size: None,
+    }]
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ConfigFile {
@@ -43,6 +62,13 @@ pub struct ConfigFile {
     pub aws_role_arn: Option<String>,
     pub aws_profile: Option<String>,
     pub db_url: Option<String>,
+    pub spool_dir: Option<String>,
+    pub spool_max_size_bytes: Option<u64>,
+    pub exporters: Option<Vec<ExporterConfig>>,
+    pub metrics_listen_addr: Option<String>,
+    pub process_events_replay_file: Option<String>,
+    pub container_runtime_socket: Option<String>,
+    pub collect_process_details: Option<bool>,
 }
 
 #[derive(Clone, Debug)]
@@ -58,6 +84,21 @@ pub struct Config {
     pub aws_init_type: AwsConfig,
     pub aws_region: AwsRegion,
     pub db_url: String,
+    /// Where batches that could not be exported are kept until they can be replayed
+    pub spool_dir: String,
+    pub spool_max_size_bytes: u64,
+    /// Sinks every batch of events is sent to
+    pub exporters: Vec<ExporterConfig>,
+    /// Address of the Prometheus `/metrics` endpoint, e.g. `0.0.0.0:9464`. Disabled when unset
+    pub metrics_listen_addr: Option<String>,
+    /// JSON lines of recorded exec and exit events, replayed instead of tracing the kernel
+    pub process_events_replay_file: Option<String>,
+    /// Docker-compatible API socket to look up container images and labels, e.g.
+    /// `/var/run/docker.sock` or `/run/podman/podman.sock`. Not looked up when unset
+    pub container_runtime_socket: Option<String>,
+    /// Read thread and fd counts, context switches, IO wait and IO syscalls of tracked tools
+    /// from `/proc`. Off by default, it costs a few syscalls per tool and sample
+    pub collect_process_details: bool,
 }
 
 pub struct ConfigManager;
@@ -113,6 +154,13 @@ impl ConfigManager {
             aws_region: AwsRegion::UsEast2,
 
             db_url: config.db_url.unwrap_or(db_url),
+            spool_dir: config.spool_dir.unwrap_or_else(default_spool_dir),
+            spool_max_size_bytes: config.spool_max_size_bytes.unwrap_or(SPOOL_MAX_SIZE_BYTES),
+            exporters: config.exporters.unwrap_or_else(default_exporters),
+            metrics_listen_addr: config.metrics_listen_addr,
+            process_events_replay_file: config.process_events_replay_file,
+            container_runtime_socket: config.container_runtime_socket,
+            collect_process_details: config.collect_process_details.unwrap_or(false),
         })
     }
 
@@ -142,6 +190,13 @@ impl ConfigManager {
             aws_region: "us-east-2".into(),
 
             db_url: db_url.to_string(),
+            spool_dir: default_spool_dir(),
+            spool_max_size_bytes: SPOOL_MAX_SIZE_BYTES,
+            exporters: default_exporters(),
+            metrics_listen_addr: None,
+            process_events_replay_file: None,
+            container_runtime_socket: None,
+            collect_process_details: false,
         }
     }
 
@@ -222,6 +277,13 @@ impl ConfigManager {
             aws_profile,
             aws_region: Some(config.aws_region.as_str().to_string()),
             db_url: Some(config.db_url.clone()),
+            spool_dir: Some(config.spool_dir.clone()),
+            spool_max_size_bytes: Some(config.spool_max_size_bytes),
+            exporters: Some(config.exporters.clone()),
+            metrics_listen_addr: config.metrics_listen_addr.clone(),
+            process_events_replay_file: config.process_events_replay_file.clone(),
+            container_runtime_socket: config.container_runtime_socket.clone(),
+            collect_process_details: Some(config.collect_process_details),
         };
         let config = toml::to_string(&config_out)?;
         std::fs::write(config_file_location, config)?;
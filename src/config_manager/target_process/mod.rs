@@ -58,7 +58,18 @@ pub struct Target {
 }
 
 pub trait TargetMatchable {
-    fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool;
+    fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool {
+        self.matches_in_image(process_name, command, bin_path, None)
+    }
+
+    /// Like `matches`, for a process running in a container of `image`.
+    fn matches_in_image(
+        &self,
+        process_name: &str,
+        command: &str,
+        bin_path: &str,
+        image: Option<&str>,
+    ) -> bool;
 }
 
 impl Target {
@@ -105,26 +116,45 @@ impl Target {
         self.force_ancestor_to_match
     }
 
+    /// Whether matching needs the image of the process' container.
+    pub fn needs_image(&self) -> bool {
+        let is_image_match = |target: &TargetMatch| matches!(target, TargetMatch::ImageContains(_));
+        is_image_match(&self.match_type) || self.filter_out.iter().flatten().any(is_image_match)
+    }
+
     pub fn get_display_name_object(&self) -> DisplayName {
         self.display_name.clone()
     }
 }
 
 impl TargetMatchable for Target {
-    fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool {
-        matches_target(&self.match_type, process_name, command, bin_path)
+    fn matches_in_image(
+        &self,
+        process_name: &str,
+        command: &str,
+        bin_path: &str,
+        image: Option<&str>,
+    ) -> bool {
+        matches_target(&self.match_type, process_name, command, bin_path, image)
             && (self.filter_out.is_none()
-                || !self
-                    .filter_out
-                    .as_ref()
-                    .unwrap()
-                    .matches(process_name, command, bin_path))
+                || !self.filter_out.as_ref().unwrap().matches_in_image(
+                    process_name,
+                    command,
+                    bin_path,
+                    image,
+                ))
     }
 }
 
 impl TargetMatchable for Vec<TargetMatch> {
-    fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool {
+    fn matches_in_image(
+        &self,
+        process_name: &str,
+        command: &str,
+        bin_path: &str,
+        image: Option<&str>,
+    ) -> bool {
         self.iter()
-            .any(|target| matches_target(target, process_name, command, bin_path))
+            .any(|target| matches_target(target, process_name, command, bin_path, image))
     }
 }
@@ -15,6 +15,9 @@ pub enum TargetMatch {
     CommandContains(CommandContainsStruct),
     BinPathStartsWith(String),
     BinPathLastComponent(String),
+    /// Matches processes in containers whose image contains the string, e.g.
+    /// `biocontainers/star`
+    ImageContains(String),
 }
 
 pub fn to_lowercase(s: &str) -> Cow<str> {
@@ -55,6 +58,7 @@ pub fn matches_target(
     process_name: &str,
     command: &str,
     bin_path: &str,
+    image: Option<&str>,
 ) -> bool {
     match target {
         TargetMatch::ProcessName(name) => process_name_matches(name, process_name),
@@ -68,6 +72,9 @@ pub fn matches_target(
         TargetMatch::BinPathLastComponent(expected_name) => {
             bin_path_last_component_matches(expected_name, bin_path)
         }
+        TargetMatch::ImageContains(content) => {
+            image.is_some_and(|image| command_contains(image, content))
+        }
     }
 }
 
@@ -263,6 +270,28 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_image_contains() {
+        let target = Target::new(TargetMatch::ImageContains("biocontainers/star".to_string()));
+        let command = "STAR --runThreadN 8 --genomeDir index";
+
+        assert!(target.needs_image());
+        assert!(target.matches_in_image(
+            "STAR",
+            command,
+            "/usr/local/bin/STAR",
+            Some("quay.io/biocontainers/star:2.7.10b--h9ee0642_0")
+        ));
+        assert!(!target.matches_in_image(
+            "STAR",
+            command,
+            "/usr/local/bin/STAR",
+            Some("quay.io/biocontainers/salmon:1.10.1")
+        ));
+        // outside of a container
+        assert!(!target.matches("STAR", command, "/usr/local/bin/STAR"));
+    }
+
     #[test]
     fn test_display_name() {
         let target = Target::new(TargetMatch::ProcessName("specific_process".to_string()))
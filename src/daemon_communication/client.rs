@@ -1,83 +1,62 @@
 // src/cli.rs
-use anyhow::Result;
-use serde::Deserialize;
-use serde_json::{from_str, json};
+use anyhow::{bail, Result};
+use serde_json::json;
 use std::path::PathBuf;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::UnixStream,
-};
+use tokio::{io::AsyncWriteExt, net::UnixStream};
 
 use crate::extracts::process_watcher::ShortLivedProcessLog;
+use crate::types::event::attributes::lineage::RunLineage;
 use crate::utils::debug_log::Logger;
 
-use super::structs::InfoResponse;
+use super::protocol::{
+    decode_response, read_frame, write_frame, ProtocolError, Request, RequestEnvelope, Response,
+};
+use super::structs::{InfoResponse, StatusResponse};
 
-pub async fn send_log_request(socket_path: &str, message: String) -> Result<()> {
+/// Sends a single request to the daemon and waits for its response. Error responses from the
+/// daemon are turned into errors.
+pub async fn send_request(socket_path: &str, request: Request) -> Result<Response> {
     let mut socket = UnixStream::connect(socket_path).await?;
 
-    let log_request = json!({
-            "command": "log",
-            "message": message
-    });
-    let log_request_json =
-        serde_json::to_string(&log_request).expect("Failed to serialize log request");
-    socket.write_all(log_request_json.as_bytes()).await?;
+    write_frame(&mut socket, &RequestEnvelope::new(request)).await?;
+
+    // Daemons from before the framed protocol read until EOF before looking at the request
+    socket.shutdown().await?;
+
+    let Some(frame) = read_frame(&mut socket).await? else {
+        bail!(ProtocolError::NoResponse);
+    };
+
+    match decode_response(&frame)?.response {
+        Response::Error(err) => Err(err.into()),
+        response => Ok(response),
+    }
+}
 
+async fn send_run_request(socket_path: &str, request: Request) -> Result<InfoResponse> {
+    match send_request(socket_path, request).await? {
+        Response::Run(info) => Ok(info),
+        response => bail!("Unexpected response from the daemon: {:?}", response),
+    }
+}
+
+pub async fn send_log_request(socket_path: &str, message: String) -> Result<()> {
+    send_request(socket_path, Request::Log { message }).await?;
     Ok(())
 }
 
 pub async fn send_alert_request(socket_path: &str, message: String) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-    let alert_request: serde_json::Value = json!({
-            "command": "alert",
-            "message": message
-    });
-    let alert_request_json =
-        serde_json::to_string(&alert_request).expect("Failed to serialize alrt request");
-    socket.write_all(alert_request_json.as_bytes()).await?;
-
+    send_request(socket_path, Request::Alert { message }).await?;
     Ok(())
 }
 
 pub async fn send_terminate_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let terminate_request = json!({
-            "command": "terminate"
-    });
-
-    let terminate_request_json =
-        serde_json::to_string(&terminate_request).expect("Failed to serialize terminate request");
-
-    socket.write_all(terminate_request_json.as_bytes()).await?;
-
+    send_request(socket_path, Request::Terminate).await?;
     Ok(())
 }
 
 pub async fn send_start_run_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let start_request = json!({
-            "command": "start"
-    });
-
-    let start_request_json =
-        serde_json::to_string(&start_request).expect("Failed to serialize start request");
-
-    socket.write_all(start_request_json.as_bytes()).await?;
-
-    socket.shutdown().await?;
-
-    #[derive(Deserialize)]
-    struct StartRunResponse {
-        run_name: String,
-    }
-
-    let mut buffer = [0; 1024];
-    let n = socket.read(&mut buffer).await?;
-    let response = std::str::from_utf8(&buffer[..n])?;
-    let response: StartRunResponse = from_str(response)?;
+    let response = send_run_request(socket_path, Request::Start).await?;
 
     println!("Started a new run with name: {}", response.run_name);
 
@@ -85,70 +64,41 @@ pub async fn send_start_run_request(socket_path: &str) -> Result<()> {
 }
 
 pub async fn send_end_run_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let end_request = json!({
-            "command": "end"
-    });
-
-    let end_request_json =
-        serde_json::to_string(&end_request).expect("Failed to serialize start request");
-
-    socket.write_all(end_request_json.as_bytes()).await?;
-
+    send_request(socket_path, Request::End).await?;
     Ok(())
 }
 
 pub async fn send_info_request(socket_path: &str) -> Result<InfoResponse> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let ping_request = json!({
-            "command": "info"
-    });
-
-    let info_request_json =
-        serde_json::to_string(&ping_request).expect("Failed to serialize info request");
-
-    socket.write_all(info_request_json.as_bytes()).await?;
-
-    socket.shutdown().await?;
+    send_run_request(socket_path, Request::Info).await
+}
 
-    let mut buffer = [0; 1024];
-    let n = socket.read(&mut buffer).await?;
-    let response = std::str::from_utf8(&buffer[..n])?;
-    let response: InfoResponse = from_str(response)?;
+pub async fn send_status_request(socket_path: &str) -> Result<StatusResponse> {
+    match send_request(socket_path, Request::Status).await? {
+        Response::Status(status) => Ok(*status),
+        response => bail!("Unexpected response from the daemon: {:?}", response),
+    }
+}
 
-    Ok(response)
+pub async fn send_lineage_request(socket_path: &str, run_id: Option<String>) -> Result<RunLineage> {
+    match send_request(socket_path, Request::Lineage { run_id }).await? {
+        Response::Lineage(lineage) => Ok(*lineage),
+        response => bail!("Unexpected response from the daemon: {:?}", response),
+    }
 }
 
 pub async fn send_refresh_config_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let setup_request = json!({
-            "command": "refresh_config"
-    });
-
-    let setup_request_json =
-        serde_json::to_string(&setup_request).expect("Failed to serialize setup request");
-
-    socket.write_all(setup_request_json.as_bytes()).await?;
-
+    send_request(socket_path, Request::RefreshConfig).await?;
     Ok(())
 }
 
-pub async fn send_update_tags_request(socket_path: &str, tags: &Vec<String>) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let tag_request = json!({
-            "command": "tag",
-            "tags": tags
-    });
-
-    let tag_request_json =
-        serde_json::to_string(&tag_request).expect("Failed to serialize tag request");
-
-    socket.write_all(tag_request_json.as_bytes()).await?;
-
+pub async fn send_update_tags_request(socket_path: &str, tags: &[String]) -> Result<()> {
+    send_request(
+        socket_path,
+        Request::Tag {
+            tags: tags.to_vec(),
+        },
+    )
+    .await?;
     Ok(())
 }
 
@@ -156,18 +106,11 @@ pub async fn send_log_short_lived_process_request(
     socket_path: &str,
     log: ShortLivedProcessLog,
 ) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let log_request = json!({
-            "command": "log_short_lived_process",
-            "log": log
-    });
-
-    let log_request_json =
-        serde_json::to_string(&log_request).expect("Failed to serialize log request");
-
-    socket.write_all(log_request_json.as_bytes()).await?;
-
+    send_request(
+        socket_path,
+        Request::LogShortLivedProcess { log: Box::new(log) },
+    )
+    .await?;
     Ok(())
 }
 
@@ -184,22 +127,21 @@ pub async fn send_upload_file_request(socket_path: &str, file_path: &PathBuf) ->
         )
         .await;
 
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let upload_request = json!({
-        "command": "upload",
-        "file_path": file_path
-    });
-
-    let upload_request_json =
-        serde_json::to_string(&upload_request).expect("Failed to serialize upload request");
-
-    socket.write_all(upload_request_json.as_bytes()).await?;
+    send_request(
+        socket_path,
+        Request::Upload {
+            file_path: file_path.clone(),
+        },
+    )
+    .await?;
 
     logger
         .log(
-            "send_upload_file_request//socket.write_all",
-            Some(&upload_request),
+            "send_upload_file_request//send_request",
+            Some(&json!({
+                "command": "upload",
+                "file_path": file_path
+            })),
         )
         .await;
 
@@ -209,9 +151,11 @@ pub async fn send_upload_file_request(socket_path: &str, file_path: &PathBuf) ->
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::daemon_communication::protocol::{decode_request, ResponseEnvelope};
     use crate::SOCKET_PATH;
+    use serde_json::Value;
     use serial_test::serial;
-    use tokio::{io::AsyncReadExt, net::UnixListener};
+    use tokio::{net::UnixListener, task::JoinHandle};
 
     fn setup_test_unix_listener() -> UnixListener {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -222,12 +166,22 @@ mod tests {
         UnixListener::bind(SOCKET_PATH).expect("Failed to bind to unix socket")
     }
 
-    async fn check_listener_value(listener: &UnixListener, expected_value: &str) {
-        let (mut stream, _) = listener.accept().await.unwrap();
-        let mut buffer = [0; 1024];
-        let n = stream.read(&mut buffer).await.unwrap();
-        let received = std::str::from_utf8(&buffer[..n]).unwrap();
-        assert_eq!(received, expected_value);
+    /// Accepts one connection, checks the decoded request against `expected_value` and answers
+    /// it like the daemon would.
+    fn check_listener_value(listener: UnixListener, expected_value: Value) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let frame = read_frame(&mut stream).await.unwrap().unwrap();
+            let envelope = decode_request(&frame).unwrap();
+            assert_eq!(
+                serde_json::to_value(&envelope.request).unwrap(),
+                expected_value
+            );
+
+            write_frame(&mut stream, &ResponseEnvelope::new(Response::Ok))
+                .await
+                .unwrap();
+        })
     }
 
     #[tokio::test]
@@ -236,18 +190,17 @@ mod tests {
         let listener = setup_test_unix_listener();
         let message = "Test Message".to_string();
 
-        send_log_request(SOCKET_PATH, message.clone()).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "log",
                 "message": message
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_log_request(SOCKET_PATH, message.clone()).await?;
+
+        check.await?;
 
         Ok(())
     }
@@ -258,18 +211,17 @@ mod tests {
         let listener = setup_test_unix_listener();
         let message = "Test Message".to_string();
 
-        send_alert_request(SOCKET_PATH, message.clone()).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "alert",
                 "message": message
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_alert_request(SOCKET_PATH, message.clone()).await?;
+
+        check.await?;
 
         Ok(())
     }
@@ -279,17 +231,16 @@ mod tests {
     async fn test_send_terminate_request() -> Result<()> {
         let listener = setup_test_unix_listener();
 
-        send_terminate_request(SOCKET_PATH).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "terminate"
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_terminate_request(SOCKET_PATH).await?;
+
+        check.await?;
 
         Ok(())
     }
@@ -299,17 +250,16 @@ mod tests {
     async fn test_send_end_run_request() -> Result<()> {
         let listener = setup_test_unix_listener();
 
-        send_end_run_request(SOCKET_PATH).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "end"
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_end_run_request(SOCKET_PATH).await?;
+
+        check.await?;
 
         Ok(())
     }
@@ -319,17 +269,16 @@ mod tests {
     async fn test_send_refresh_config_request() -> Result<()> {
         let listener = setup_test_unix_listener();
 
-        send_refresh_config_request(SOCKET_PATH).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "refresh_config"
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_refresh_config_request(SOCKET_PATH).await?;
+
+        check.await?;
 
         Ok(())
     }
@@ -340,18 +289,17 @@ mod tests {
         let listener = setup_test_unix_listener();
         let tags = vec!["tag1".to_string(), "tag2".to_string(), "tag3".to_string()];
 
-        send_update_tags_request(SOCKET_PATH, &tags).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "tag",
                 "tags": tags
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_update_tags_request(SOCKET_PATH, &tags).await?;
+
+        check.await?;
 
         Ok(())
     }
@@ -362,18 +310,70 @@ mod tests {
         let listener = setup_test_unix_listener();
         let file_path = PathBuf::from("log_outgoing_http_calls.txt".to_string());
 
-        send_upload_file_request(SOCKET_PATH, &file_path).await?;
-
-        check_listener_value(
-            &listener,
+        let check = check_listener_value(
+            listener,
             json!({
                 "command": "upload",
                 "file_path": file_path.clone()
-            })
-            .to_string()
-            .as_str(),
-        )
-        .await;
+            }),
+        );
+
+        send_upload_file_request(SOCKET_PATH, &file_path).await?;
+
+        check.await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_daemon_error_is_returned_to_caller() -> Result<()> {
+        let listener = setup_test_unix_listener();
+
+        let daemon = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_frame(&mut stream).await.unwrap().unwrap();
+            write_frame(
+                &mut stream,
+                &ResponseEnvelope::new(Response::error(
+                    crate::daemon_communication::structs::ErrorKind::CommandFailed,
+                    "upload failed",
+                )),
+            )
+            .await
+            .unwrap();
+        });
+
+        let err = send_end_run_request(SOCKET_PATH).await.unwrap_err();
+        daemon.await?;
+
+        let err = err
+            .downcast_ref::<crate::daemon_communication::structs::ErrorResponse>()
+            .expect("expected an error response");
+        assert_eq!(err.message, "upload failed");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_legacy_daemon_is_reported_as_incompatible() -> Result<()> {
+        let listener = setup_test_unix_listener();
+
+        // pre-protocol daemons read the whole message and hang up without answering
+        let daemon = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut message = String::new();
+            let _ = tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut message).await;
+        });
+
+        let err = send_info_request(SOCKET_PATH).await.unwrap_err();
+        daemon.await?;
+
+        assert!(matches!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::NoResponse)
+        ));
 
         Ok(())
     }
@@ -0,0 +1,140 @@
+// src/daemon_communication/metrics_server.rs
+//! Minimal HTTP listener serving `/metrics` for Prometheus scrapes.
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::exporters::prometheus::{PrometheusMetrics, CONTENT_TYPE};
+
+const METRICS_PATH: &str = "/metrics";
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Serves `metrics` on `listen_addr` until `cancellation_token` is cancelled.
+pub async fn run_metrics_server(
+    metrics: PrometheusMetrics,
+    listen_addr: String,
+    cancellation_token: CancellationToken,
+) -> Result<()> {
+    let listener = TcpListener::bind(&listen_addr)
+        .await
+        .with_context(|| format!("Failed to bind metrics endpoint to {listen_addr}"))?;
+    serve_metrics(listener, metrics, cancellation_token).await
+}
+
+pub async fn serve_metrics(
+    listener: TcpListener,
+    metrics: PrometheusMetrics,
+    cancellation_token: CancellationToken,
+) -> Result<()> {
+    loop {
+        let accepted = tokio::select! {
+            _ = cancellation_token.cancelled() => return Ok(()),
+            accepted = listener.accept() => accepted,
+        };
+
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept metrics connection: {err}");
+                sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, &metrics).await {
+                eprintln!("Error handling metrics request: {err:#}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: TcpStream, metrics: &PrometheusMetrics) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let request_line = timeout(REQUEST_READ_TIMEOUT, read_request_head(&mut stream))
+        .await
+        .context("Timed out reading metrics request")??;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    // scrapers may add query parameters, they don't change the response
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", METRICS_PATH) => ("200 OK", CONTENT_TYPE, metrics.render()),
+        (_, METRICS_PATH) => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET is supported\n".to_string(),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        body.len()
+    );
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Returns the request line, skipping over the headers. Scrapes have no body.
+async fn read_request_head(stream: &mut BufReader<TcpStream>) -> Result<String> {
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+
+    loop {
+        let mut header = String::new();
+        if stream.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    Ok(request_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_metrics_over_http() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let cancellation_token = CancellationToken::new();
+        let server = tokio::spawn(serve_metrics(
+            listener,
+            PrometheusMetrics::new(),
+            cancellation_token.clone(),
+        ));
+
+        let response = reqwest::get(format!("http://{addr}/metrics")).await?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()[reqwest::header::CONTENT_TYPE],
+            CONTENT_TYPE
+        );
+        assert!(response
+            .text()
+            .await?
+            .contains("# TYPE tracer_events_emitted_total counter"));
+
+        let response = reqwest::get(format!("http://{addr}/other")).await?;
+        assert_eq!(response.status(), 404);
+
+        cancellation_token.cancel();
+        server.await??;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
 pub mod client;
+pub mod metrics_server;
+pub mod protocol;
 pub mod server;
 pub mod structs;
@@ -0,0 +1,380 @@
+// src/daemon_communication/protocol.rs
+//! Wire protocol spoken over the daemon unix socket.
+//!
+//! Every message is a single frame: a 4 byte big-endian length followed by a JSON document.
+//! Requests carry the protocol version of the CLI that sent them, responses carry the protocol
+//! version of the daemon, so either side can refuse to talk to an incompatible binary.
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::extracts::process_watcher::ShortLivedProcessLog;
+use crate::types::event::attributes::lineage::RunLineage;
+
+use super::structs::{ErrorKind, ErrorResponse, InfoResponse, StatusResponse};
+
+/// Bump whenever `Request` or `Response` change in a way older binaries can't understand.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Upper bound for a single frame, protects the daemon from garbage length prefixes.
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+const FRAME_HEADER_LEN: usize = 4;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Terminate,
+    Log {
+        message: String,
+    },
+    Alert {
+        message: String,
+    },
+    Start,
+    End,
+    RefreshConfig,
+    Tag {
+        tags: Vec<String>,
+    },
+    LogShortLivedProcess {
+        log: Box<ShortLivedProcessLog>,
+    },
+    Info,
+    Upload {
+        file_path: PathBuf,
+    },
+    Status,
+    /// `run_id` defaults to the current run
+    Lineage {
+        run_id: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Run(InfoResponse),
+    Status(Box<StatusResponse>),
+    Lineage(Box<RunLineage>),
+    Error(ErrorResponse),
+}
+
+impl Response {
+    pub fn error(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Response::Error(ErrorResponse {
+            kind,
+            message: message.into(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RequestEnvelope {
+    pub protocol_version: u32,
+    pub client_version: String,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+impl RequestEnvelope {
+    pub fn new(request: Request) -> Self {
+        RequestEnvelope {
+            protocol_version: PROTOCOL_VERSION,
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            request,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseEnvelope {
+    pub protocol_version: u32,
+    pub daemon_version: String,
+    #[serde(flatten)]
+    pub response: Response,
+}
+
+impl ResponseEnvelope {
+    pub fn new(response: Response) -> Self {
+        ResponseEnvelope {
+            protocol_version: PROTOCOL_VERSION,
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            response,
+        }
+    }
+}
+
+/// Only the versioning part of a request, decoded before the request itself so that a request
+/// from a newer CLI is reported as a version mismatch rather than as an unknown command.
+#[derive(Deserialize)]
+struct VersionProbe {
+    protocol_version: Option<u32>,
+    #[serde(alias = "client_version", alias = "daemon_version")]
+    binary_version: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The peer sent an unframed JSON document, which is what pre-protocol binaries do.
+    LegacyPeer,
+    FrameTooLarge(u32),
+    /// The peer speaks a different protocol version.
+    IncompatibleVersion {
+        local: u32,
+        remote: Option<u32>,
+        remote_binary: Option<String>,
+    },
+    /// The daemon hung up without answering, which is what pre-protocol daemons do.
+    NoResponse,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::LegacyPeer => write!(
+                f,
+                "received an unframed message, the peer is running a tracer version without the versioned socket protocol"
+            ),
+            ProtocolError::FrameTooLarge(len) => write!(
+                f,
+                "frame of {len} bytes exceeds the maximum of {MAX_FRAME_LEN} bytes"
+            ),
+            ProtocolError::IncompatibleVersion {
+                local,
+                remote,
+                remote_binary,
+            } => write!(
+                f,
+                "incompatible protocol version: this binary speaks v{local}, the peer speaks {} (tracer {}). Restart the daemon with `tracer terminate` and `tracer init` using the same tracer binary as the CLI",
+                remote.map_or("an unknown version".to_string(), |v| format!("v{v}")),
+                remote_binary.as_deref().unwrap_or("unknown")
+            ),
+            ProtocolError::NoResponse => write!(
+                f,
+                "the daemon closed the connection without replying, it is probably running an older tracer version. Stop it and start it again with `tracer init` using this binary"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+pub async fn write_frame<W, T>(writer: &mut W, message: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let body = serde_json::to_vec(message)?;
+    let len = u32::try_from(body.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or(ProtocolError::FrameTooLarge(u32::MAX))?;
+
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a single frame. Returns `Ok(None)` if the peer closed the connection before sending
+/// anything.
+pub async fn read_frame<R>(reader: &mut R) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    let mut filled = 0;
+    while filled < FRAME_HEADER_LEN {
+        let n = reader.read(&mut header[filled..]).await?;
+        if n == 0 {
+            if filled == 0 {
+                return Ok(None);
+            }
+            if header[0] == b'{' {
+                bail!(ProtocolError::LegacyPeer);
+            }
+            bail!("connection closed in the middle of a frame header");
+        }
+        filled += n;
+    }
+
+    // Legacy binaries write the JSON document directly, so the "length" starts with a brace.
+    if header[0] == b'{' {
+        bail!(ProtocolError::LegacyPeer);
+    }
+
+    let len = u32::from_be_bytes(header);
+    if len > MAX_FRAME_LEN {
+        bail!(ProtocolError::FrameTooLarge(len));
+    }
+
+    let mut body = vec![0u8; len as usize];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+/// Decodes a request frame, checking the protocol version before the request itself.
+pub fn decode_request(frame: &[u8]) -> Result<RequestEnvelope> {
+    decode_versioned(frame)
+}
+
+pub fn decode_response(frame: &[u8]) -> Result<ResponseEnvelope> {
+    decode_versioned(frame)
+}
+
+fn decode_versioned<T: DeserializeOwned>(frame: &[u8]) -> Result<T> {
+    let probe: VersionProbe = serde_json::from_slice(frame)?;
+    if probe.protocol_version != Some(PROTOCOL_VERSION) {
+        bail!(ProtocolError::IncompatibleVersion {
+            local: PROTOCOL_VERSION,
+            remote: probe.protocol_version,
+            remote_binary: probe.binary_version,
+        });
+    }
+    Ok(serde_json::from_slice(frame)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon_communication::structs::ExportAttempt;
+    use chrono::Utc;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_frame_round_trip() -> Result<()> {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        write_frame(
+            &mut client,
+            &RequestEnvelope::new(Request::Log {
+                message: "hello".to_string(),
+            }),
+        )
+        .await?;
+
+        let frame = read_frame(&mut server).await?.expect("missing frame");
+        let envelope = decode_request(&frame)?;
+
+        assert_eq!(envelope.protocol_version, PROTOCOL_VERSION);
+        assert!(matches!(envelope.request, Request::Log { message } if message == "hello"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_legacy_client_is_detected() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client
+            .write_all(json!({"command": "info"}).to_string().as_bytes())
+            .await
+            .unwrap();
+        drop(client);
+
+        let err = read_frame(&mut server).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::LegacyPeer)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_closed_connection_returns_none() -> Result<()> {
+        let (client, mut server) = tokio::io::duplex(1024);
+        drop(client);
+
+        assert!(read_frame(&mut server).await?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_mismatch_is_reported_before_parsing_request() {
+        let frame = json!({
+            "protocol_version": PROTOCOL_VERSION + 1,
+            "client_version": "9999.1.1",
+            "command": "some_future_command",
+        })
+        .to_string();
+
+        let err = decode_request(frame.as_bytes()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::IncompatibleVersion {
+                remote: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_response_round_trip() -> Result<()> {
+        let envelope = ResponseEnvelope::new(Response::error(ErrorKind::CommandFailed, "boom"));
+        let frame = serde_json::to_vec(&envelope)?;
+
+        let decoded = decode_response(&frame)?;
+        match decoded.response {
+            Response::Error(err) => {
+                assert_eq!(err.kind, ErrorKind::CommandFailed);
+                assert_eq!(err.message, "boom");
+            }
+            other => panic!("unexpected response: {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_status_round_trip() -> Result<()> {
+        let status = StatusResponse {
+            daemon_version: "2025.2.27".to_string(),
+            started_at: Utc::now(),
+            uptime_sec: 42,
+            run: Some(InfoResponse {
+                run_name: "brave-falcon".to_string(),
+                run_id: "run-1".to_string(),
+                pipeline_name: "rnaseq".to_string(),
+            }),
+            tracked_processes: 3,
+            pending_events: BTreeMap::from([("tool_execution".to_string(), 2)]),
+            last_export_success: None,
+            last_export_failure: Some(ExportAttempt {
+                timestamp: Utc::now(),
+                events: 7,
+                error: Some("postgres: connection refused".to_string()),
+            }),
+            spooled_batches: 1,
+            syslog_buffer_depth: 0,
+            stdout_buffer_depth: 4,
+            stderr_buffer_depth: 0,
+            last_file_poll: None,
+        };
+        let (mut daemon, mut client) = tokio::io::duplex(4096);
+        write_frame(
+            &mut daemon,
+            &ResponseEnvelope::new(Response::Status(Box::new(status.clone()))),
+        )
+        .await?;
+
+        let frame = read_frame(&mut client).await?.expect("missing frame");
+        let Response::Status(decoded) = decode_response(&frame)?.response else {
+            panic!("expected a status response");
+        };
+        assert_eq!(decoded.daemon_version, "2025.2.27");
+        assert_eq!(decoded.started_at, status.started_at);
+        assert_eq!(decoded.run.unwrap().run_id, "run-1");
+        assert_eq!(decoded.tracked_processes, 3);
+        assert_eq!(decoded.pending_events, status.pending_events);
+        let failure = decoded.last_export_failure.unwrap();
+        assert_eq!(failure.events, 7);
+        assert_eq!(
+            failure.error.as_deref(),
+            Some("postgres: connection refused")
+        );
+        assert!(decoded.last_export_success.is_none());
+        assert_eq!(decoded.spooled_batches, 1);
+        assert_eq!(decoded.stdout_buffer_depth, 4);
+        Ok(())
+    }
+}
@@ -1,12 +1,12 @@
-use anyhow::{Ok, Result};
+use anyhow::{bail, Context, Result};
 use chrono::Utc;
-use core::panic;
-use serde_json::{json, Value};
-use std::{future::Future, pin::Pin, sync::Arc};
+use serde_json::json;
+use std::{future::Future, path::Path, sync::Arc, time::Duration};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::AsyncWriteExt,
     net::{UnixListener, UnixStream},
     sync::{Mutex, RwLock},
+    time::{sleep, timeout},
 };
 use tokio_util::sync::CancellationToken;
 
@@ -18,240 +18,300 @@ use crate::{
     utils::{debug_log::Logger, upload::upload_from_file_path},
 };
 
-type ProcessOutput<'a> =
-    Option<Pin<Box<dyn Future<Output = Result<String, anyhow::Error>> + 'a + Send>>>;
-
-pub fn process_log_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &serde_json::Map<String, serde_json::Value>,
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("message") {
-        return None;
-    };
-    let message = object.get("message").unwrap().as_str().unwrap().to_string();
-
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        service_url: &'a str,
-        api_key: &'a str,
-        message: String,
-    ) -> Result<String, anyhow::Error> {
-        let event_recorder = &mut tracer_client.lock().await.logs;
-
-        event_recorder.record_event(
-            EventType::RunStatusMessage,
-            message.clone(),
-            None,
-            Some(Utc::now()),
-        );
-
-        // TODO: remove
-        send_log_event(service_url, api_key, message).await
-    }
+use super::{
+    protocol::{
+        decode_request, read_frame, write_frame, ProtocolError, Request, Response, ResponseEnvelope,
+    },
+    structs::{ErrorKind, InfoResponse},
+};
 
-    Some(Box::pin(fun(tracer_client, api_key, service_url, message)))
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(30);
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+pub async fn process_log_command(
+    service_url: &str,
+    api_key: &str,
+    message: String,
+    tracer_client: &Arc<Mutex<TracerClient>>,
+) -> Result<Response> {
+    tracer_client.lock().await.logs.record_event(
+        EventType::RunStatusMessage,
+        message.clone(),
+        None,
+        Some(Utc::now()),
+    );
+
+    // TODO: remove
+    send_log_event(service_url, api_key, message)
+        .await
+        .context("Message was recorded but forwarding it to the service failed")?;
+
+    Ok(Response::Ok)
 }
 
-pub fn process_alert_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &serde_json::Map<String, serde_json::Value>,
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("message") {
-        return None;
-    };
-
-    let message = object.get("message").unwrap().as_str().unwrap().to_string();
-
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        service_url: &'a str,
-        api_key: &'a str,
-        message: String,
-    ) -> Result<String, anyhow::Error> {
-        let event_recorder = &mut tracer_client.lock().await.logs;
+pub async fn process_alert_command(
+    service_url: &str,
+    api_key: &str,
+    message: String,
+    tracer_client: &Arc<Mutex<TracerClient>>,
+) -> Result<Response> {
+    tracer_client.lock().await.logs.record_event(
+        EventType::Alert,
+        message.clone(),
+        None,
+        Some(Utc::now()),
+    );
+
+    // TODO: remove
+    send_alert_event(service_url, api_key, message)
+        .await
+        .context("Alert was recorded but forwarding it to the service failed")?;
+
+    Ok(Response::Ok)
+}
 
-        event_recorder.record_event(EventType::Alert, message.clone(), None, Some(Utc::now()));
-        // TODO: remove
-        send_alert_event(service_url, api_key, message).await
+fn run_info(tracer_client: &TracerClient) -> InfoResponse {
+    match tracer_client.get_run_metadata() {
+        Some(run) => InfoResponse {
+            run_name: run.name,
+            run_id: run.id,
+            pipeline_name: tracer_client.get_pipeline_name().to_string(),
+        },
+        None => InfoResponse::default(),
     }
-    Some(Box::pin(fun(tracer_client, service_url, api_key, message)))
 }
 
-pub fn process_start_run_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    stream: &'a mut UnixStream,
-) -> ProcessOutput<'a> {
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        stream: &'a mut UnixStream,
-    ) -> Result<String, anyhow::Error> {
-        tracer_client.lock().await.start_new_run(None).await?;
-
-        let guard = tracer_client.lock().await;
-
-        let info = guard.get_run_metadata();
-
-        let output = if let Some(info) = info {
-            json!({
-                "run_name": info.name,
-                "run_id": info.id,
-                "pipeline_name": guard.get_pipeline_name(),
-            })
-        } else {
-            json!({
-                "run_name": "",
-                "run_id": "",
-                "pipeline_name": "",
-            })
-        };
+pub async fn process_start_run_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+) -> Result<Response> {
+    let mut guard = tracer_client.lock().await;
+    guard.start_new_run(None).await?;
 
-        stream
-            .write_all(serde_json::to_string(&output)?.as_bytes())
-            .await?;
-
-        stream.flush().await?;
+    Ok(Response::Run(run_info(&guard)))
+}
 
-        Ok("".to_string())
-    }
+pub async fn process_info_command(tracer_client: &Arc<Mutex<TracerClient>>) -> Result<Response> {
+    let guard = tracer_client.lock().await;
 
-    Some(Box::pin(fun(tracer_client, stream)))
+    Ok(Response::Run(run_info(&guard)))
 }
 
-pub fn process_info_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    stream: &'a mut UnixStream,
-) -> ProcessOutput<'a> {
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        stream: &'a mut UnixStream,
-    ) -> Result<String, anyhow::Error> {
-        let guard = tracer_client.lock().await;
-
-        let out = guard.get_run_metadata();
-
-        let output = if let Some(out) = out {
-            json!({
-                "run_name": out.name,
-                "run_id": out.id,
-                "pipeline_name": guard.get_pipeline_name(),
-            })
-        } else {
-            json!({
-                "run_name": "",
-                "run_id": "",
-                "pipeline_name": "",
-            })
-        };
-
-        stream
-            .write_all(serde_json::to_string(&output)?.as_bytes())
-            .await?;
+pub async fn process_status_command(tracer_client: &Arc<Mutex<TracerClient>>) -> Result<Response> {
+    let status = tracer_client.lock().await.get_status().await;
 
-        stream.flush().await?;
+    Ok(Response::Status(Box::new(status)))
+}
 
-        Ok("".to_string())
-    }
+pub async fn process_lineage_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    run_id: Option<String>,
+) -> Result<Response> {
+    let lineage = tracer_client.lock().await.get_lineage(run_id.as_deref())?;
 
-    Some(Box::pin(fun(tracer_client, stream)))
+    Ok(Response::Lineage(Box::new(lineage)))
 }
 
 // NOTE: outputs data
-pub fn process_end_run_command(tracer_client: &Arc<Mutex<TracerClient>>) -> ProcessOutput<'_> {
-    Some(Box::pin(async move {
-        let mut tracer_client = tracer_client.lock().await;
-        tracer_client.stop_run().await?;
-        Ok("".to_string())
-    }))
+pub async fn process_end_run_command(tracer_client: &Arc<Mutex<TracerClient>>) -> Result<Response> {
+    tracer_client.lock().await.stop_run().await?;
+    Ok(Response::Ok)
 }
 
-pub fn process_refresh_config_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    config: &'a Arc<RwLock<Config>>,
-) -> ProcessOutput<'a> {
+pub async fn process_refresh_config_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    config: &Arc<RwLock<Config>>,
+) -> Result<Response> {
     let config_file = ConfigManager::load_config();
 
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        config: &'a Arc<RwLock<Config>>,
-        config_file: crate::config_manager::Config,
-    ) -> Result<String, anyhow::Error> {
-        tracer_client.lock().await.reload_config_file(&config_file);
-        config.write().await.clone_from(&config_file);
-        Ok("".to_string())
-    }
-
-    Some(Box::pin(fun(tracer_client, config, config_file)))
+    tracer_client.lock().await.reload_config_file(&config_file);
+    config.write().await.clone_from(&config_file);
+    Ok(Response::Ok)
 }
 
 // TODO: should this be an event ?
-pub fn process_tag_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &serde_json::Map<String, serde_json::Value>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("tags") {
-        return None;
-    };
+pub async fn process_tag_command(
+    service_url: &str,
+    api_key: &str,
+    tags: Vec<String>,
+) -> Result<Response> {
+    send_update_tags_event(service_url, api_key, tags).await?;
+    Ok(Response::Ok)
+}
+
+pub async fn process_log_short_lived_process_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    log: Box<ShortLivedProcessLog>,
+) -> Result<Response> {
+    tracer_client
+        .lock()
+        .await
+        .fill_logs_with_short_lived_process(*log)?;
+    Ok(Response::Ok)
+}
+
+pub async fn process_upload_command(
+    service_url: &str,
+    api_key: &str,
+    file_path: &Path,
+) -> Result<Response> {
+    let logger = Logger::new();
+
+    logger.log("server.rs//process_upload_command", None).await;
 
-    let tags_json = object.get("tags").unwrap().as_array().unwrap();
+    let file_path = file_path
+        .to_str()
+        .context("Upload file path is not valid UTF-8")?;
 
-    let tags: Vec<String> = tags_json
-        .iter()
-        .map(|tag| tag.as_str().unwrap().to_string())
-        .collect();
+    upload_from_file_path(service_url, api_key, file_path, None).await?;
 
-    Some(Box::pin(send_update_tags_event(service_url, api_key, tags)))
+    logger.log("process_upload_command completed", None).await;
+    Ok(Response::Ok)
 }
 
-pub fn process_log_short_lived_process_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    object: &serde_json::Map<String, serde_json::Value>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("log") {
-        return None;
+async fn dispatch_request(
+    request: Request,
+    tracer_client: Arc<Mutex<TracerClient>>,
+    config: Arc<RwLock<Config>>,
+) -> Result<Response> {
+    let (service_url, api_key) = {
+        let tracer_client = tracer_client.lock().await;
+        let service_url = tracer_client.get_service_url().to_owned();
+        let api_key = tracer_client.get_api_key().to_owned();
+        (service_url, api_key)
     };
 
-    let log: ShortLivedProcessLog =
-        serde_json::from_value(object.get("log").unwrap().clone()).unwrap();
+    match request {
+        // handled by the accept loop, which has to stop after answering
+        Request::Terminate => Ok(Response::Ok),
+        Request::Log { message } => {
+            process_log_command(&service_url, &api_key, message, &tracer_client).await
+        }
+        Request::Alert { message } => {
+            process_alert_command(&service_url, &api_key, message, &tracer_client).await
+        }
+        Request::Start => process_start_run_command(&tracer_client).await,
+        Request::End => process_end_run_command(&tracer_client).await,
+        Request::RefreshConfig => process_refresh_config_command(&tracer_client, &config).await,
+        Request::Tag { tags } => process_tag_command(&service_url, &api_key, tags).await,
+        Request::LogShortLivedProcess { log } => {
+            process_log_short_lived_process_command(&tracer_client, log).await
+        }
+        Request::Info => process_info_command(&tracer_client).await,
+        Request::Status => process_status_command(&tracer_client).await,
+        Request::Lineage { run_id } => process_lineage_command(&tracer_client, run_id).await,
+        Request::Upload { file_path } => {
+            process_upload_command(&service_url, &api_key, &file_path).await
+        }
+    }
+}
 
-    Some(Box::pin(async move {
-        let mut tracer_client = tracer_client.lock().await;
-        tracer_client.fill_logs_with_short_lived_process(log)?;
-        Ok("".to_string())
-    }))
+async fn send_response(stream: &mut UnixStream, response: Response) -> Result<()> {
+    write_frame(stream, &ResponseEnvelope::new(response)).await
 }
 
-pub fn process_upload_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &'a serde_json::Map<String, serde_json::Value>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("file_path") {
-        return None;
-    };
+/// Answers a pre-protocol client in the only format it could possibly display.
+async fn reject_legacy_client(stream: &mut UnixStream) -> Result<()> {
+    let message = json!({
+        "error": format!(
+            "This daemon runs tracer {} and speaks a newer socket protocol, please use the matching tracer CLI",
+            env!("CARGO_PKG_VERSION")
+        )
+    });
+    stream.write_all(message.to_string().as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
 
-    Some(Box::pin(async move {
-        let logger = Logger::new();
+/// Reads one request from `stream` and always answers it with a response. Returns `true` if the
+/// daemon was asked to terminate.
+async fn handle_connection<F>(
+    stream: &mut UnixStream,
+    dispatch: impl FnOnce(Request) -> F,
+) -> Result<bool>
+where
+    F: Future<Output = Result<Response>> + Send + 'static,
+{
+    let logger = Logger::new();
+
+    let frame = match timeout(REQUEST_READ_TIMEOUT, read_frame(stream)).await {
+        Ok(Ok(Some(frame))) => frame,
+        Ok(Ok(None)) => return Ok(false),
+        Ok(Err(err)) => {
+            if let Some(ProtocolError::LegacyPeer) = err.downcast_ref::<ProtocolError>() {
+                reject_legacy_client(stream).await?;
+                return Err(err.context("Rejected request from an incompatible tracer CLI"));
+            }
+            send_response(
+                stream,
+                Response::error(ErrorKind::InvalidRequest, err.to_string()),
+            )
+            .await?;
+            return Err(err.context("Failed to read request"));
+        }
+        Err(_) => bail!(
+            "Client did not send a request within {} seconds",
+            REQUEST_READ_TIMEOUT.as_secs()
+        ),
+    };
 
-        logger.log("server.rs//process_upload_command", None).await;
+    let envelope = match decode_request(&frame) {
+        Ok(envelope) => envelope,
+        Err(err) => {
+            let kind = if err.downcast_ref::<ProtocolError>().is_some() {
+                ErrorKind::IncompatibleVersion
+            } else {
+                ErrorKind::InvalidRequest
+            };
+            send_response(stream, Response::error(kind, err.to_string())).await?;
+            return Err(err.context("Failed to decode request"));
+        }
+    };
 
-        upload_from_file_path(
-            service_url,
-            api_key,
-            object.get("file_path").unwrap().as_str().unwrap(),
+    logger
+        .log(
+            &format!(
+                "Received command from tracer {}: {:?}",
+                envelope.client_version, envelope.request
+            ),
             None,
         )
-        .await?;
+        .await;
+
+    let terminate = matches!(envelope.request, Request::Terminate);
+
+    // Run the command in its own task so that a panic inside a handler still gets an answer
+    let command = tokio::spawn(dispatch(envelope.request));
+
+    let response = match command.await {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => {
+            report_error("Failed to process command", &err).await;
+            Response::error(ErrorKind::CommandFailed, format!("{err:#}"))
+        }
+        Err(err) => {
+            let err = anyhow::anyhow!("Command handler crashed: {err}");
+            report_error("Failed to process command", &err).await;
+            Response::error(ErrorKind::CommandFailed, err.to_string())
+        }
+    };
+
+    send_response(stream, response)
+        .await
+        .context("Failed to send response")?;
+
+    Ok(terminate)
+}
 
-        logger.log("process_upload_command completed", None).await;
-        Ok("Upload command processed".to_string())
-    }))
+/// Failures on the socket never stop the daemon, they end up on stderr and in the debug log.
+async fn report_error(message: &str, err: &anyhow::Error) {
+    eprintln!("[{}] {message}: {err:?}", Utc::now());
+    Logger::new()
+        .log(&format!("{message}: {err:#}"), None)
+        .await;
 }
 
+/// Serves the daemon socket until `cancellation_token` is cancelled. Each connection is handled
+/// in its own task, a failing or misbehaving client only affects its own connection.
 pub async fn run_server(
     tracer_client: Arc<Mutex<TracerClient>>,
     socket_path: &str,
@@ -259,82 +319,159 @@ pub async fn run_server(
     config: Arc<RwLock<Config>>,
 ) -> Result<(), anyhow::Error> {
     if std::fs::metadata(socket_path).is_ok() {
-        std::fs::remove_file(socket_path)
-            .unwrap_or_else(|_| panic!("Failed to remove existing socket file"));
+        std::fs::remove_file(socket_path).context("Failed to remove existing socket file")?;
     }
-    let listener = UnixListener::bind(socket_path).expect("Failed to bind to unix socket");
+    let listener = UnixListener::bind(socket_path).context("Failed to bind to unix socket")?;
+
+    serve(listener, cancellation_token, move |request| {
+        dispatch_request(request, tracer_client.clone(), config.clone())
+    })
+    .await;
+    Ok(())
+}
+
+/// Accepts connections until `cancellation_token` is cancelled and hands their requests to
+/// `dispatch`.
+async fn serve<D, F>(listener: UnixListener, cancellation_token: CancellationToken, dispatch: D)
+where
+    D: Fn(Request) -> F + Clone + Send + 'static,
+    F: Future<Output = Result<Response>> + Send + 'static,
+{
     loop {
-        let (mut stream, _) = listener.accept().await.unwrap();
+        let accepted = tokio::select! {
+            _ = cancellation_token.cancelled() => return,
+            accepted = listener.accept() => accepted,
+        };
+
+        let mut stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                // e.g. EMFILE, back off instead of spinning on the error
+                report_error("Failed to accept socket connection", &err.into()).await;
+                sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
 
-        let mut message = String::new();
+        let dispatch = dispatch.clone();
+        let cancellation_token = cancellation_token.clone();
 
-        let logger = Logger::new();
+        tokio::spawn(async move {
+            match handle_connection(&mut stream, dispatch).await {
+                Ok(true) => cancellation_token.cancel(),
+                Ok(false) => {}
+                Err(err) => report_error("Error handling socket request", &err).await,
+            }
+        });
+    }
+}
 
-        let result = stream.read_to_string(&mut message).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon_communication::{
+        client::send_request,
+        protocol::{decode_response, MAX_FRAME_LEN},
+        structs::ErrorResponse,
+    };
+    use tempfile::{tempdir, TempDir};
 
-        if result.is_err() {
-            eprintln!("Error reading from socket: {}", result.err().unwrap());
-            continue;
+    async fn dispatch(request: Request) -> Result<Response> {
+        match request {
+            Request::Log { message } if message == "fail" => bail!("log rejected"),
+            Request::Log { message } if message == "panic" => panic!("log handler bug"),
+            _ => Ok(Response::Ok),
         }
+    }
 
-        let json_parse_result = serde_json::from_str(&message);
+    fn start_server() -> Result<(TempDir, String, CancellationToken)> {
+        let dir = tempdir()?;
+        let socket_path = dir
+            .path()
+            .join("tracerd.sock")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let listener = UnixListener::bind(&socket_path)?;
+        let cancellation_token = CancellationToken::new();
+        tokio::spawn(serve(listener, cancellation_token.clone(), dispatch));
+        Ok((dir, socket_path, cancellation_token))
+    }
 
-        if json_parse_result.is_err() {
-            eprintln!("Error parsing JSON: {}", json_parse_result.err().unwrap());
-            continue;
+    fn log(message: &str) -> Request {
+        Request::Log {
+            message: message.to_string(),
         }
+    }
 
-        let parsed: Value = json_parse_result.unwrap();
+    fn error_kind(err: anyhow::Error) -> ErrorKind {
+        err.downcast_ref::<ErrorResponse>()
+            .expect("expected an error response")
+            .kind
+    }
 
-        if !parsed.is_object() {
-            eprintln!("Invalid JSON received: {}", message);
-            continue;
-        }
+    /// Sends `bytes` as they are and decodes the answer.
+    async fn send_raw(socket_path: &str, bytes: &[u8]) -> Result<Response> {
+        let mut stream = UnixStream::connect(socket_path).await?;
+        stream.write_all(bytes).await?;
+        let frame = read_frame(&mut stream).await?.context("no response")?;
+        Ok(decode_response(&frame)?.response)
+    }
 
-        let object = parsed.as_object().unwrap();
+    #[tokio::test]
+    async fn test_failing_commands_are_answered() -> Result<()> {
+        let (_dir, socket_path, cancellation_token) = start_server()?;
 
-        if !object.contains_key("command") {
-            eprintln!("Invalid JSON, no command field, received: {}", message);
-            continue;
-        }
+        let err = send_request(&socket_path, log("fail")).await.unwrap_err();
+        assert_eq!(error_kind(err), ErrorKind::CommandFailed);
 
-        let command = object.get("command").unwrap().as_str().unwrap();
+        let err = send_request(&socket_path, log("panic")).await.unwrap_err();
+        assert!(err.to_string().contains("crashed"));
+        assert_eq!(error_kind(err), ErrorKind::CommandFailed);
 
-        let (service_url, api_key) = {
-            let tracer_client = tracer_client.lock().await;
-            let service_url = tracer_client.get_service_url().to_owned();
-            let api_key = tracer_client.get_api_key().to_owned();
-            (service_url, api_key)
-        };
+        cancellation_token.cancel();
+        Ok(())
+    }
 
-        logger
-            .log(&format!("Received command: {}, {}", command, message), None)
-            .await;
+    #[tokio::test]
+    async fn test_bad_connections_do_not_stop_the_server() -> Result<()> {
+        let (_dir, socket_path, cancellation_token) = start_server()?;
 
-        let result = match command {
-            "terminate" => {
-                cancellation_token.cancel();
-                return Ok(());
-            }
-            "log" => process_log_command(&service_url, &api_key, object, &tracer_client),
-            "alert" => process_alert_command(&service_url, &api_key, object, &tracer_client),
-            "start" => process_start_run_command(&tracer_client, &mut stream),
-            "end" => process_end_run_command(&tracer_client),
-            "refresh_config" => process_refresh_config_command(&tracer_client, &config),
-            "tag" => process_tag_command(&service_url, &api_key, object),
-            "log_short_lived_process" => {
-                process_log_short_lived_process_command(&tracer_client, object)
-            }
-            "info" => process_info_command(&tracer_client, &mut stream),
-            "upload" => process_upload_command(&service_url, &api_key, object),
-            _ => {
-                eprintln!("Invalid command: {}", command);
-                None
-            }
+        // hangs up without a request, and in the middle of a frame
+        drop(UnixStream::connect(&socket_path).await?);
+        let mut stream = UnixStream::connect(&socket_path).await?;
+        stream.write_all(&[0, 0]).await?;
+        drop(stream);
+        let _ = send_request(&socket_path, log("panic")).await;
+
+        assert!(matches!(
+            send_request(&socket_path, log("hello")).await?,
+            Response::Ok
+        ));
+
+        cancellation_token.cancel();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_frames_are_rejected() -> Result<()> {
+        let (_dir, socket_path, cancellation_token) = start_server()?;
+
+        let garbage = b"{not json";
+        let mut frame = (garbage.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(garbage);
+        let Response::Error(err) = send_raw(&socket_path, &frame).await? else {
+            panic!("expected an error response");
         };
+        assert_eq!(err.kind, ErrorKind::InvalidRequest);
 
-        if let Some(future) = result {
-            future.await?;
-        }
+        let too_large = (MAX_FRAME_LEN + 1).to_be_bytes();
+        let Response::Error(err) = send_raw(&socket_path, &too_large).await? else {
+            panic!("expected an error response");
+        };
+        assert_eq!(err.kind, ErrorKind::InvalidRequest);
+
+        cancellation_token.cancel();
+        Ok(())
     }
 }
@@ -1,8 +1,65 @@
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
 
-#[derive(Deserialize)]
+use crate::extracts::file_watcher::FilePollStatus;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct InfoResponse {
     pub run_name: String,
     pub run_id: String,
     pub pipeline_name: String,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The CLI and the daemon speak different protocol versions
+    IncompatibleVersion,
+    /// The request could not be decoded
+    InvalidRequest,
+    /// The request was understood but handling it failed
+    CommandFailed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorResponse {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The daemon reported an error: {}", self.message)
+    }
+}
+
+impl std::error::Error for ErrorResponse {}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportAttempt {
+    pub timestamp: DateTime<Utc>,
+    pub events: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatusResponse {
+    /// `daemon_version` is taken by the response envelope the status is flattened into
+    #[serde(rename = "version")]
+    pub daemon_version: String,
+    pub started_at: DateTime<Utc>,
+    pub uptime_sec: u64,
+    pub run: Option<InfoResponse>,
+    pub tracked_processes: usize,
+    /// Events recorded since the last flush, keyed by event type
+    pub pending_events: BTreeMap<String, u64>,
+    pub last_export_success: Option<ExportAttempt>,
+    pub last_export_failure: Option<ExportAttempt>,
+    /// Batches waiting on disk for the database to become reachable again
+    pub spooled_batches: usize,
+    pub syslog_buffer_depth: usize,
+    pub stdout_buffer_depth: usize,
+    pub stderr_buffer_depth: usize,
+    pub last_file_poll: Option<FilePollStatus>,
+}
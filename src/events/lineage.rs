@@ -0,0 +1,361 @@
+// src/events/lineage.rs
+//! Builds the lineage graph of a run from its tool execution events, as they are exported.
+use std::collections::{BTreeSet, HashMap};
+
+use crate::events::recorder::EventType;
+use crate::types::event::{
+    attributes::{
+        lineage::{LineageEdge, LineageNode, LineageNodeKind, RunLineage},
+        EventAttributes,
+    },
+    Event,
+};
+
+#[derive(Clone)]
+struct ToolExecution {
+    id: String,
+    tool_name: String,
+    tool_pid: String,
+    tool_cmd: Option<String>,
+    exit_code: Option<i32>,
+    inputs: BTreeSet<String>,
+    outputs: BTreeSet<String>,
+}
+
+/// Collects the files every tool execution of the current run read and wrote. Events are
+/// cleared from the recorder once exported, so they are observed batch by batch.
+#[derive(Clone, Default)]
+pub struct LineageTracker {
+    /// Events of other runs, or recorded between runs, are ignored
+    run_id: Option<String>,
+    tools: Vec<ToolExecution>,
+    /// Index in `tools` of the latest execution with each pid
+    by_pid: HashMap<String, usize>,
+    /// Executions seen per pid, to tell apart executions reusing a pid
+    pid_uses: HashMap<String, usize>,
+}
+
+impl LineageTracker {
+    /// Forgets what was observed so far and follows the run `run_id`.
+    pub fn start(&mut self, run_id: &str) {
+        *self = Self {
+            run_id: Some(run_id.to_string()),
+            ..Self::default()
+        };
+    }
+
+    pub fn observe(&mut self, events: &[Event]) {
+        if self.run_id.is_none() {
+            return;
+        }
+        for event in events {
+            if event.run_id != self.run_id {
+                continue;
+            }
+            match &event.attributes {
+                Some(EventAttributes::Process(properties))
+                    if event.process_status == EventType::ToolExecution.as_str() =>
+                {
+                    let index = self.add_execution(&properties.tool_pid, &properties.tool_name);
+                    let tool = &mut self.tools[index];
+                    tool.tool_cmd = Some(properties.tool_cmd.clone());
+                    // arguments matching files in the workflow directory
+                    tool.inputs.extend(
+                        properties
+                            .input_files
+                            .iter()
+                            .flatten()
+                            .map(|file| file.file_path.clone()),
+                    );
+                }
+                Some(EventAttributes::CompletedProcess(completed)) => {
+                    let index = match self.by_pid.get(&completed.tool_pid) {
+                        Some(index) => *index,
+                        None => self.add_execution(&completed.tool_pid, &completed.tool_name),
+                    };
+                    let tool = &mut self.tools[index];
+                    tool.exit_code = completed.exit_code;
+                    tool.inputs.extend(completed.input_paths.iter().cloned());
+                    tool.outputs.extend(completed.output_paths.iter().cloned());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn add_execution(&mut self, tool_pid: &str, tool_name: &str) -> usize {
+        let uses = self.pid_uses.entry(tool_pid.to_string()).or_default();
+        *uses += 1;
+        let id = match *uses {
+            1 => format!("tool:{tool_pid}"),
+            n => format!("tool:{tool_pid}#{n}"),
+        };
+        self.tools.push(ToolExecution {
+            id,
+            tool_name: tool_name.to_string(),
+            tool_pid: tool_pid.to_string(),
+            tool_cmd: None,
+            exit_code: None,
+            inputs: BTreeSet::new(),
+            outputs: BTreeSet::new(),
+        });
+        self.by_pid
+            .insert(tool_pid.to_string(), self.tools.len() - 1);
+        self.tools.len() - 1
+    }
+
+    /// The graph of everything observed so far.
+    pub fn lineage(&self) -> RunLineage {
+        let mut files = BTreeSet::new();
+        let mut edges = vec![];
+        for tool in &self.tools {
+            // a file the tool rewrote is its output, an edge back to it would make a cycle
+            for input in tool.inputs.difference(&tool.outputs) {
+                files.insert(input);
+                edges.push(LineageEdge {
+                    from: file_id(input),
+                    to: tool.id.clone(),
+                });
+            }
+            for output in &tool.outputs {
+                files.insert(output);
+                edges.push(LineageEdge {
+                    from: tool.id.clone(),
+                    to: file_id(output),
+                });
+            }
+        }
+
+        let tools = self.tools.iter().map(|tool| LineageNode {
+            id: tool.id.clone(),
+            kind: LineageNodeKind::Tool,
+            label: tool.tool_name.clone(),
+            tool_pid: Some(tool.tool_pid.clone()),
+            tool_cmd: tool.tool_cmd.clone(),
+            exit_code: tool.exit_code,
+        });
+        let files = files.into_iter().map(|path| LineageNode {
+            id: file_id(path),
+            kind: LineageNodeKind::File,
+            label: path.clone(),
+            tool_pid: None,
+            tool_cmd: None,
+            exit_code: None,
+        });
+
+        RunLineage {
+            run_id: self.run_id.clone().unwrap_or_default(),
+            nodes: tools.chain(files).collect(),
+            edges,
+        }
+    }
+
+    /// The graph of the run, forgetting it until the next one starts.
+    pub fn finish(&mut self) -> RunLineage {
+        let lineage = self.lineage();
+        *self = Self::default();
+        lineage
+    }
+}
+
+fn file_id(path: &str) -> String {
+    format!("file:{path}")
+}
+
+/// Graphviz rendering, tools as boxes and files as notes labelled with their names.
+pub fn to_dot(lineage: &RunLineage) -> String {
+    let mut dot = format!("digraph {} {{\n", quote(&lineage.run_id));
+    dot.push_str("  rankdir=LR;\n");
+    for node in &lineage.nodes {
+        let attributes = match node.kind {
+            LineageNodeKind::Tool => format!("label={}, shape=box", quote(&node.label)),
+            LineageNodeKind::File => {
+                let name = node.label.rsplit('/').next().unwrap_or(&node.label);
+                format!(
+                    "label={}, tooltip={}, shape=note",
+                    quote(name),
+                    quote(&node.label)
+                )
+            }
+        };
+        dot.push_str(&format!("  {} [{attributes}];\n", quote(&node.id)));
+    }
+    for edge in &lineage.edges {
+        dot.push_str(&format!(
+            "  {} -> {};\n",
+            quote(&edge.from),
+            quote(&edge.to)
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+    use crate::types::event::attributes::process::{
+        CompletedProcess, InputFile, ProcessProperties,
+    };
+
+    fn recorder(run_id: &str) -> EventRecorder {
+        EventRecorder::new(None, None, Some(run_id.to_string()))
+    }
+
+    fn record_start(recorder: &mut EventRecorder, pid: &str, name: &str, inputs: &[&str]) {
+        let input_files = inputs
+            .iter()
+            .map(|path| InputFile {
+                file_name: path.rsplit('/').next().unwrap().to_string(),
+                file_size: 1,
+                file_path: path.to_string(),
+                file_directory: "/data".to_string(),
+                file_updated_at_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            })
+            .collect();
+        let properties = ProcessProperties {
+            tool_name: name.to_string(),
+            tool_pid: pid.to_string(),
+            tool_parent_pid: "1".to_string(),
+            tool_binary_path: format!("/usr/bin/{name}"),
+            tool_cmd: name.to_string(),
+            start_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            process_cpu_utilization: 0.0,
+            process_memory_usage: 0,
+            process_memory_virtual: 0,
+            process_run_time: 0,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 0,
+            process_disk_usage_write_total: 0,
+            process_status: "Run".to_string(),
+            input_files: Some(input_files),
+            subtree: None,
+            cgroup: None,
+            container: None,
+            details: None,
+        };
+        recorder.record_event(
+            EventType::ToolExecution,
+            name.to_string(),
+            Some(EventAttributes::Process(properties)),
+            None,
+        );
+    }
+
+    fn record_end(recorder: &mut EventRecorder, pid: &str, inputs: &[&str], outputs: &[&str]) {
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "exited".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "tool".to_string(),
+                tool_pid: pid.to_string(),
+                duration_sec: 1,
+                exit_code: Some(0),
+                signal: None,
+                oom_killed: false,
+                resource_usage: Default::default(),
+                input_paths: inputs.iter().map(|path| path.to_string()).collect(),
+                output_paths: outputs.iter().map(|path| path.to_string()).collect(),
+            })),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_tools_are_linked_through_files() {
+        let mut tracker = LineageTracker::default();
+        tracker.start("run-1");
+        let mut recorder = recorder("run-1");
+        record_start(&mut recorder, "10", "trim_galore", &["/data/reads.fq"]);
+        tracker.observe(recorder.get_events());
+        recorder.clear();
+
+        // the end comes in a later batch, and STAR rewrites its log
+        record_end(&mut recorder, "10", &[], &["/data/trimmed.fq"]);
+        record_start(&mut recorder, "20", "STAR", &[]);
+        record_end(
+            &mut recorder,
+            "20",
+            &["/data/trimmed.fq", "/data/Log.out"],
+            &["/data/Aligned.bam", "/data/Log.out"],
+        );
+        tracker.observe(recorder.get_events());
+
+        let lineage = tracker.finish();
+        assert_eq!((lineage.tool_count(), lineage.file_count()), (2, 4));
+        let edges: Vec<_> = lineage
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                ("file:/data/reads.fq", "tool:10"),
+                ("tool:10", "file:/data/trimmed.fq"),
+                ("file:/data/trimmed.fq", "tool:20"),
+                ("tool:20", "file:/data/Aligned.bam"),
+                ("tool:20", "file:/data/Log.out"),
+            ]
+        );
+        assert_eq!(lineage.nodes[1].exit_code, Some(0));
+
+        let dot = to_dot(&lineage);
+        assert!(dot.starts_with("digraph \"run-1\" {"));
+        assert!(dot.contains("\"tool:20\" [label=\"STAR\", shape=box];"));
+        assert!(dot.contains(
+            "\"file:/data/trimmed.fq\" [label=\"trimmed.fq\", tooltip=\"/data/trimmed.fq\", shape=note];"
+        ));
+        assert!(dot.contains("\"file:/data/trimmed.fq\" -> \"tool:20\";"));
+
+        assert!(tracker.lineage().nodes.is_empty());
+    }
+
+    #[test]
+    fn test_reused_pids_are_separate_executions() {
+        let mut tracker = LineageTracker::default();
+        tracker.start("run-1");
+        let mut recorder = recorder("run-1");
+        record_start(&mut recorder, "10", "samtools", &[]);
+        record_end(&mut recorder, "10", &["/data/a.bam"], &[]);
+        record_start(&mut recorder, "10", "samtools", &[]);
+        record_end(&mut recorder, "10", &["/data/b.bam"], &[]);
+        tracker.observe(recorder.get_events());
+
+        let lineage = tracker.lineage();
+        assert_eq!(lineage.tool_count(), 2);
+        assert_eq!(lineage.edges[1].from, "file:/data/b.bam");
+        assert_eq!(lineage.edges[1].to, "tool:10#2");
+    }
+
+    #[test]
+    fn test_only_events_of_the_run_are_observed() {
+        let mut tracker = LineageTracker::default();
+        let mut between_runs = EventRecorder::default();
+        record_start(&mut between_runs, "5", "fastqc", &["/data/reads.fq"]);
+        tracker.observe(between_runs.get_events());
+        assert!(tracker.lineage().nodes.is_empty());
+
+        tracker.start("run-1");
+        // pending events recorded before the run started are exported with its first batch
+        let mut run = recorder("run-1");
+        record_start(&mut run, "10", "trim_galore", &["/data/reads.fq"]);
+        tracker.observe(between_runs.get_events());
+        tracker.observe(run.get_events());
+
+        let lineage = tracker.finish();
+        assert_eq!(lineage.run_id, "run-1");
+        assert_eq!(lineage.tool_count(), 1);
+        assert_eq!(lineage.nodes[0].id, "tool:10");
+
+        // nothing is kept once the run ended
+        tracker.observe(run.get_events());
+        assert!(tracker.lineage().nodes.is_empty());
+    }
+}
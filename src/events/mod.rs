@@ -8,6 +8,7 @@ use crate::{
     },
     utils::{debug_log::Logger, http_client::send_http_event},
 };
+pub mod lineage;
 pub mod recorder;
 mod run_details;
 use anyhow::{Context, Result};
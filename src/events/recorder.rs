@@ -1,6 +1,7 @@
 use crate::types::event::{attributes::EventAttributes, Event};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Events recorder for each pipeline run
 pub struct EventRecorder {
@@ -24,6 +25,7 @@ pub enum EventType {
     RunStatusMessage,
     Alert,
     DataSamplesEvent,
+    RunLineage,
     TestEvent, // Added TestEvent variant
 }
 
@@ -41,6 +43,7 @@ impl EventType {
             EventType::RunStatusMessage => "run_status_message",
             EventType::Alert => "alert",
             EventType::DataSamplesEvent => "datasets_in_process",
+            EventType::RunLineage => "run_lineage",
         }
     }
 }
@@ -104,6 +107,15 @@ impl EventRecorder {
         self.events.clear();
     }
 
+    /// Number of recorded events per event type
+    pub fn count_by_type(&self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+        for event in &self.events {
+            *counts.entry(event.process_status.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
     #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.events.is_empty()
@@ -167,6 +179,19 @@ mod tests {
         assert!(recorder.is_empty());
     }
 
+    #[test]
+    fn test_count_by_type() {
+        let mut recorder = EventRecorder::default();
+        recorder.record_event(EventType::ToolExecution, "a".to_string(), None, None);
+        recorder.record_event(EventType::ToolExecution, "b".to_string(), None, None);
+        recorder.record_event(EventType::MetricEvent, "c".to_string(), None, None);
+
+        let counts = recorder.count_by_type();
+        assert_eq!(counts.get("tool_execution"), Some(&2));
+        assert_eq!(counts.get("metric_event"), Some(&1));
+        assert_eq!(counts.len(), 2);
+    }
+
     #[test]
     fn test_event_type_as_str() {
         assert_eq!(EventType::FinishedRun.as_str(), "finished_run");
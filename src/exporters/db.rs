@@ -1,35 +1,68 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use log::info;
 use serde_json::Value;
-use sqlx::pool::PoolOptions;
+use sqlx::postgres::PgPoolOptions;
 use sqlx::types::Json;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
+use tokio::sync::OnceCell;
 
-use crate::types::event::Event;
+use crate::types::{config::PostgresSchema, event::Event};
+
+use super::{relational::TypedRows, EventBatch, EventExporter};
+
+/// Rows written per COPY or multi-row insert statement.
+pub const DEFAULT_INSERT_CHUNK_SIZE: usize = 1000;
 
 pub struct AuroraClient {
     pool: PgPool,
+    /// Set once the migrations ran, which happens with the first insert
+    migrated: OnceCell<()>,
+    schema: PostgresSchema,
+    insert_chunk_size: usize,
 }
 
 impl AuroraClient {
-    pub async fn new(url: &str, pool_size: Option<u32>) -> Self {
-        // Hardcoded database connection string (to change)
-
+    /// Connections are only opened when needed, so an unreachable database fails the exports
+    /// instead of the daemon start.
+    pub fn new(url: &str, pool_size: Option<u32>) -> Result<Self> {
         // Use PgPoolOptions to set max_size
-        let pool = PoolOptions::new()
+        let pool = PgPoolOptions::new()
             .max_connections(pool_size.unwrap_or(100))
-            .connect(url)
-            .await
-            .expect("Failed establish connection");
+            .connect_lazy(url)
+            .context("Invalid database URL")?;
 
-        sqlx::migrate!("./migrations")
-            .run(&pool)
-            .await
-            .expect("Failed to migrate the database");
+        Ok(AuroraClient {
+            pool,
+            migrated: OnceCell::new(),
+            schema: PostgresSchema::default(),
+            insert_chunk_size: DEFAULT_INSERT_CHUNK_SIZE,
+        })
+    }
 
-        info!("Successfully created connection pool");
+    /// Runs the migrations the first time it's called, and again after a failed attempt.
+    async fn migrate(&self) -> Result<()> {
+        self.migrated
+            .get_or_try_init(|| async {
+                sqlx::migrate!("./migrations")
+                    .run(&self.pool)
+                    .await
+                    .context("Failed to migrate the database")?;
+                info!("Successfully migrated the database");
+                Ok::<_, anyhow::Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub fn with_schema(mut self, schema: PostgresSchema) -> Self {
+        self.schema = schema;
+        self
+    }
 
-        AuroraClient { pool }
+    pub fn with_insert_chunk_size(mut self, insert_chunk_size: usize) -> Self {
+        self.insert_chunk_size = insert_chunk_size.max(1);
+        self
     }
 
     pub fn get_pool(&self) -> &PgPool {
@@ -41,6 +74,7 @@ impl AuroraClient {
 
         info!("Inserting row with job_id: {}", job_id);
 
+        self.migrate().await?;
         sqlx::query(query)
             .bind(data)
             .bind(job_id)
@@ -53,16 +87,18 @@ impl AuroraClient {
         Ok(())
     }
 
-    pub async fn batch_insert_events(
+    /// Inserts the events into the tables selected by the schema, all in one transaction. Raw
+    /// events are streamed with `COPY`, typed rows go in as multi-row inserts, so a batch takes a
+    /// few round trips per `insert_chunk_size` events instead of one per event.
+    pub async fn batch_insert_events<'a>(
         &self,
         job_id: &str,
-        data: impl IntoIterator<Item = &Event>,
+        data: impl IntoIterator<Item = &'a Event> + Clone,
     ) -> Result<()> {
-        let query = "INSERT INTO batch_jobs_logs (data, job_id) VALUES ($1, $2)";
-
         info!("Inserting row with job_id: {}", job_id);
         println!("Inserting row with job_id: {}", job_id);
 
+        self.migrate().await?;
         let mut transaction = self
             .get_pool()
             .begin()
@@ -71,17 +107,22 @@ impl AuroraClient {
 
         let mut rows_affected = 0;
 
-        for event in data {
-            let json_data = Json(serde_json::to_value(event)?); // Convert the event to JSON
+        if self.schema != PostgresSchema::Normalized {
+            let events: Vec<&Event> = data.clone().into_iter().collect();
+            for chunk in events.chunks(self.insert_chunk_size) {
+                rows_affected += copy_raw_events(&mut transaction, job_id, chunk)
+                    .await
+                    .context("Failed to insert events into database")?;
+            }
+        }
 
-            rows_affected += sqlx::query(query)
-                .bind(json_data)
-                .bind(job_id)
-                .execute(&mut *transaction) // Use the transaction directly
+        if self.schema != PostgresSchema::Raw {
+            TypedRows::from_events(data)
+                .insert(&mut transaction, self.insert_chunk_size)
                 .await
-                .context("Failed to insert event into database")?
-                .rows_affected();
+                .context("Failed to insert typed rows")?;
         }
+
         // Commit the transaction
         transaction
             .commit()
@@ -100,3 +141,184 @@ impl AuroraClient {
         Ok(())
     }
 }
+
+/// Streams the events into `batch_jobs_logs` with a single `COPY`.
+async fn copy_raw_events(
+    connection: &mut PgConnection,
+    job_id: &str,
+    events: &[&Event],
+) -> Result<u64> {
+    let mut data = Vec::new();
+    for event in events {
+        let json = serde_json::to_string(event)?;
+        data.extend_from_slice(csv_field(&json).as_bytes());
+        data.push(b',');
+        data.extend_from_slice(csv_field(job_id).as_bytes());
+        data.push(b'\n');
+    }
+
+    let mut copy = connection
+        .copy_in_raw("COPY batch_jobs_logs (data, job_id) FROM STDIN WITH (FORMAT csv)")
+        .await?;
+    if let Err(err) = copy.send(data).await {
+        copy.abort(err.to_string()).await?;
+        return Err(err.into());
+    }
+    Ok(copy.finish().await?)
+}
+
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[async_trait]
+impl EventExporter for AuroraClient {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn export(&self, batch: &EventBatch) -> Result<()> {
+        self.batch_insert_events(&batch.job_id, &batch.events).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        AuroraClient::close(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        events::recorder::{EventRecorder, EventType},
+        types::event::attributes::{
+            process::ProcessProperties, system_metrics::SystemMetric, EventAttributes,
+        },
+    };
+    use std::{collections::HashMap, time::Instant};
+
+    const BENCHMARK_EVENTS: usize = 5000;
+
+    fn benchmark_events(run_id: &str) -> Vec<Event> {
+        let mut recorder = EventRecorder::new(
+            Some("benchmark".to_string()),
+            Some("benchmark-run".to_string()),
+            Some(run_id.to_string()),
+        );
+        for i in 0..BENCHMARK_EVENTS {
+            let attributes = if i % 10 == 0 {
+                EventAttributes::SystemMetric(SystemMetric {
+                    events_name: "global_system_metrics".to_string(),
+                    system_memory_total: 4096,
+                    system_memory_used: 1024,
+                    system_memory_available: 3072,
+                    system_memory_utilization: 25.0,
+                    system_memory_swap_total: 0,
+                    system_memory_swap_used: 0,
+                    system_cpu_utilization: 12.5,
+                    system_disk_io: HashMap::new(),
+                    ..Default::default()
+                })
+            } else {
+                EventAttributes::Process(ProcessProperties {
+                    tool_name: "STAR".to_string(),
+                    tool_pid: (i % 50).to_string(),
+                    tool_parent_pid: "1".to_string(),
+                    tool_binary_path: "/usr/bin/STAR".to_string(),
+                    tool_cmd: "STAR --runThreadN \"4\"".to_string(),
+                    start_timestamp: "2025-01-01T00:00:00Z".to_string(),
+                    process_cpu_utilization: 50.0,
+                    process_memory_usage: 1024,
+                    process_memory_virtual: 2048,
+                    process_run_time: 3,
+                    process_disk_usage_read_last_interval: 0,
+                    process_disk_usage_write_last_interval: 0,
+                    process_disk_usage_read_total: 10,
+                    process_disk_usage_write_total: 20,
+                    process_status: "Run".to_string(),
+                    input_files: None,
+                    subtree: None,
+                    cgroup: None,
+                    container: None,
+                    details: None,
+                })
+            };
+            let event_type = match attributes {
+                EventAttributes::SystemMetric(_) => EventType::MetricEvent,
+                _ => EventType::ToolMetricEvent,
+            };
+            recorder.record_event(event_type, format!("event {i}"), Some(attributes), None);
+        }
+        recorder.get_events().to_vec()
+    }
+
+    async fn count_raw_events(client: &AuroraClient, job_id: &str) -> Result<i64> {
+        Ok(
+            sqlx::query_scalar("SELECT count(*) FROM batch_jobs_logs WHERE job_id = $1")
+                .bind(job_id)
+                .fetch_one(client.get_pool())
+                .await?,
+        )
+    }
+
+    async fn cleanup(client: &AuroraClient, job_id: &str, run_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM batch_jobs_logs WHERE job_id = $1")
+            .bind(job_id)
+            .execute(client.get_pool())
+            .await?;
+        sqlx::query("DELETE FROM runs WHERE run_id = $1")
+            .bind(run_id)
+            .execute(client.get_pool())
+            .await?;
+        Ok(())
+    }
+
+    /// Compares one statement per row, what `batch_insert_events` used to do, with COPY and
+    /// multi-row inserts. Run with `cargo test benchmark -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore = "requires a postgres database in TRACER_TEST_DATABASE_URL"]
+    async fn benchmark_batch_insert_events() -> Result<()> {
+        let url = std::env::var("TRACER_TEST_DATABASE_URL")?;
+        let mut throughput = Vec::new();
+
+        for chunk_size in [1, DEFAULT_INSERT_CHUNK_SIZE] {
+            // the one statement per row variant below writes both
+            let client = AuroraClient::new(&url, Some(4))?
+                .with_schema(PostgresSchema::Both)
+                .with_insert_chunk_size(chunk_size);
+            let run_id = uuid::Uuid::new_v4().to_string();
+            let job_id = format!("benchmark-{run_id}");
+            let events = benchmark_events(&run_id);
+
+            let started = Instant::now();
+            if chunk_size == 1 {
+                let mut transaction = client.get_pool().begin().await?;
+                for event in &events {
+                    sqlx::query("INSERT INTO batch_jobs_logs (data, job_id) VALUES ($1, $2)")
+                        .bind(Json(serde_json::to_value(event)?))
+                        .bind(&job_id)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+                TypedRows::from_events(&events)
+                    .insert(&mut transaction, 1)
+                    .await?;
+                transaction.commit().await?;
+            } else {
+                client.batch_insert_events(&job_id, &events).await?;
+            }
+            let events_per_sec = events.len() as f64 / started.elapsed().as_secs_f64();
+            println!("chunk size {chunk_size}: {events_per_sec:.0} events/s");
+            throughput.push(events_per_sec);
+
+            assert_eq!(
+                count_raw_events(&client, &job_id).await?,
+                events.len() as i64
+            );
+            cleanup(&client, &job_id, &run_id).await?;
+        }
+
+        assert!(throughput[1] > throughput[0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+// src/exporters/fanout.rs
+use anyhow::{anyhow, bail, Context, Result};
+use std::{path::Path, time::Duration};
+use tokio::time::timeout;
+
+use crate::{
+    config_manager::{Config, ConfigManager},
+    types::config::ExporterConfig,
+};
+
+use super::{
+    db::{AuroraClient, DEFAULT_INSERT_CHUNK_SIZE},
+    file::{FileExporter, DEFAULT_MAX_SEGMENT_AGE, DEFAULT_MAX_SEGMENT_SIZE_BYTES},
+    otlp::OtlpExporter,
+    parquet::ParquetExporter,
+    spool::EventSpool,
+    EventBatch, EventExporter,
+};
+
+/// Upper bound for a single export, so a hanging sink can't hold up the others.
+const SINK_EXPORT_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Sink {
+    exporter: Box<dyn EventExporter>,
+    spool: EventSpool,
+    failures: u64,
+}
+
+impl Sink {
+    /// Exports `batch` after replaying anything spooled before it, so batches reach the sink in
+    /// order. A batch the sink doesn't accept is spooled for a later retry, so the sink may see
+    /// a batch more than once.
+    async fn export(&mut self, batch: &EventBatch) -> Result<()> {
+        if !self.spool.is_empty() && !self.spool.ready_for_replay() {
+            // still backing off after the last failure, queue up behind the spooled batches
+            self.spool_batch(batch);
+            bail!(
+                "waiting to retry, {} batches spooled",
+                self.spool.pending_batches()
+            );
+        }
+
+        match self.replay_spool_and_export(batch).await {
+            Ok(()) => {
+                self.spool.record_export_success();
+                Ok(())
+            }
+            Err(err) => {
+                self.failures += 1;
+                self.spool.record_export_failure();
+                self.spool_batch(batch);
+                Err(err)
+            }
+        }
+    }
+
+    async fn replay_spool_and_export(&mut self, batch: &EventBatch) -> Result<()> {
+        while let Some(segment) = self.spool.oldest_segment()? {
+            let mut sent = 0;
+            let mut result = Ok(());
+            for spooled in &segment.batches {
+                result = self.export_with_timeout(spooled).await;
+                if result.is_err() {
+                    break;
+                }
+                sent += 1;
+            }
+            self.spool.commit(segment, sent)?;
+            result.context("Failed to replay spooled events")?;
+        }
+
+        self.export_with_timeout(batch).await
+    }
+
+    async fn export_with_timeout(&self, batch: &EventBatch) -> Result<()> {
+        timeout(SINK_EXPORT_TIMEOUT, self.exporter.export(batch))
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "export timed out after {} seconds",
+                    SINK_EXPORT_TIMEOUT.as_secs()
+                )
+            })?
+    }
+
+    fn spool_batch(&mut self, batch: &EventBatch) {
+        if batch.events.is_empty() {
+            return;
+        }
+        if let Err(err) = self.spool.append(batch) {
+            eprintln!(
+                "Failed to spool {} events for exporter {}, they are lost: {err:#}",
+                batch.events.len(),
+                self.exporter.name()
+            );
+        }
+    }
+}
+
+/// Sends every batch to all configured exporters. Each exporter has its own spool, so a failing
+/// exporter neither blocks the others nor loses the batches it missed.
+#[derive(Default)]
+pub struct ExporterFanOut {
+    sinks: Vec<Sink>,
+}
+
+impl ExporterFanOut {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the exporters listed in the config, each spooling into its own directory below
+    /// `config.spool_dir`.
+    pub async fn from_config(config: &Config) -> Result<Self> {
+        let mut fan_out = ExporterFanOut::new();
+
+        for exporter_config in &config.exporters {
+            let exporter: Box<dyn EventExporter> = match exporter_config {
+                ExporterConfig::Postgres {
+                    db_url,
+                    pool_size,
+                    schema,
+                    insert_chunk_size,
+                } => Box::new(
+                    AuroraClient::new(db_url.as_deref().unwrap_or(&config.db_url), *pool_size)?
+                        .with_schema(schema.unwrap_or_default())
+                        .with_insert_chunk_size(
+                            insert_chunk_size.unwrap_or(DEFAULT_INSERT_CHUNK_SIZE),
+                        ),
+                ),
+                ExporterConfig::File {
+                    dir,
+                    gzip,
+                    max_segment_size_bytes,
+                    max_segment_age_sec,
+                } => Box::new(FileExporter::new(
+                    dir,
+                    gzip.unwrap_or(false),
+                    max_segment_size_bytes.unwrap_or(DEFAULT_MAX_SEGMENT_SIZE_BYTES),
+                    max_segment_age_sec
+                        .map(Duration::from_secs)
+                        .unwrap_or(DEFAULT_MAX_SEGMENT_AGE),
+                )?),
+                ExporterConfig::Parquet { dir } => {
+                    let dir = match dir {
+                        Some(dir) => dir.into(),
+                        None => ConfigManager::get_tracer_parquet_export_dir()?,
+                    };
+                    Box::new(ParquetExporter::new(dir)?)
+                }
+                ExporterConfig::Otlp {
+                    endpoint,
+                    protocol,
+                    headers,
+                } => Box::new(OtlpExporter::new(
+                    endpoint,
+                    protocol.unwrap_or_default(),
+                    headers.clone().unwrap_or_default(),
+                )?),
+            };
+            fan_out.add_exporter(
+                exporter,
+                Path::new(&config.spool_dir),
+                config.spool_max_size_bytes,
+            )?;
+        }
+
+        Ok(fan_out)
+    }
+
+    /// Adds an exporter spooling into `spool_dir/<exporter name>`. Exporters sharing a name get
+    /// numbered spool directories.
+    pub fn add_exporter(
+        &mut self,
+        exporter: Box<dyn EventExporter>,
+        spool_dir: &Path,
+        spool_max_size_bytes: u64,
+    ) -> Result<()> {
+        let name = exporter.name();
+        let duplicates = self
+            .sinks
+            .iter()
+            .filter(|sink| sink.exporter.name() == name)
+            .count();
+        let dir_name = match duplicates {
+            0 => name.to_string(),
+            n => format!("{name}-{n}"),
+        };
+
+        let spool = EventSpool::open(spool_dir.join(dir_name), spool_max_size_bytes)
+            .with_context(|| format!("Failed to open the spool of exporter {name}"))?;
+        self.add_sink(exporter, spool);
+        Ok(())
+    }
+
+    pub fn add_sink(&mut self, exporter: Box<dyn EventExporter>, spool: EventSpool) {
+        self.sinks.push(Sink {
+            exporter,
+            spool,
+            failures: 0,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Batches waiting in the spools of all exporters.
+    pub fn pending_batches(&self) -> usize {
+        self.sinks
+            .iter()
+            .map(|sink| sink.spool.pending_batches())
+            .sum()
+    }
+
+    /// Failed exports per exporter since the daemon started.
+    pub fn export_failures(&self) -> Vec<(&str, u64)> {
+        self.sinks
+            .iter()
+            .map(|sink| (sink.exporter.name(), sink.failures))
+            .collect()
+    }
+
+    /// Sends `batch` to every exporter. Fails if any exporter failed, after all of them were
+    /// tried.
+    pub async fn export(&mut self, batch: &EventBatch) -> Result<()> {
+        let mut failures = Vec::new();
+        for sink in &mut self.sinks {
+            if let Err(err) = sink.export(batch).await {
+                failures.push(format!("{}: {err:#}", sink.exporter.name()));
+            }
+        }
+        check_failures("export events", failures)
+    }
+
+    pub async fn flush(&self) -> Result<()> {
+        let mut failures = Vec::new();
+        for sink in &self.sinks {
+            if let Err(err) = sink.exporter.flush().await {
+                failures.push(format!("{}: {err:#}", sink.exporter.name()));
+            }
+        }
+        check_failures("flush", failures)
+    }
+
+    pub async fn close(&self) -> Result<()> {
+        let mut failures = Vec::new();
+        for sink in &self.sinks {
+            if let Err(err) = sink.exporter.close().await {
+                failures.push(format!("{}: {err:#}", sink.exporter.name()));
+            }
+        }
+        check_failures("close", failures)
+    }
+}
+
+fn check_failures(action: &str, failures: Vec<String>) -> Result<()> {
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        bail!("Failed to {action} on {}", failures.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exporters::memory::MemoryExporter;
+    use crate::exporters::test_support::batch;
+    use tempfile::tempdir;
+
+    fn job_ids(exporter: &MemoryExporter) -> Vec<String> {
+        exporter
+            .batches()
+            .into_iter()
+            .map(|batch| batch.job_id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_failing_exporter_does_not_affect_others() -> Result<()> {
+        let dir = tempdir()?;
+        let healthy = MemoryExporter::new();
+        let flaky = MemoryExporter::new();
+
+        let mut fan_out = ExporterFanOut::new();
+        fan_out.add_exporter(Box::new(healthy.clone()), dir.path(), 1024 * 1024)?;
+        fan_out.add_sink(
+            Box::new(flaky.clone()),
+            EventSpool::open(dir.path().join("flaky"), 1024 * 1024)?
+                .with_backoff(Duration::ZERO, Duration::ZERO),
+        );
+
+        flaky.set_failing(true);
+        assert!(fan_out.export(&batch("first")).await.is_err());
+        assert!(fan_out.export(&batch("second")).await.is_err());
+        assert_eq!(job_ids(&healthy), vec!["first", "second"]);
+        assert_eq!(fan_out.pending_batches(), 2);
+        assert_eq!(
+            fan_out.export_failures(),
+            vec![("memory", 0), ("memory", 2)]
+        );
+
+        // the spooled batches are replayed before the new one
+        flaky.set_failing(false);
+        fan_out.export(&batch("third")).await?;
+        assert_eq!(job_ids(&flaky), vec!["first", "second", "third"]);
+        assert_eq!(fan_out.pending_batches(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_database_is_spooled() -> Result<()> {
+        let dir = tempdir()?;
+        let mut config = ConfigManager::load_default_config();
+        config.spool_dir = dir.path().to_str().unwrap().to_string();
+        config.exporters = vec![ExporterConfig::Postgres {
+            // a unix socket that doesn't exist
+            db_url: Some("postgres://tracer@localhost/tracer?host=/nonexistent".to_string()),
+            pool_size: None,
+            schema: None,
+            insert_chunk_size: None,
+        }];
+
+        let mut fan_out = ExporterFanOut::from_config(&config).await?;
+        assert!(fan_out.export(&batch("first")).await.is_err());
+        assert_eq!(fan_out.pending_batches(), 1);
+        assert_eq!(fan_out.export_failures(), vec![("postgres", 1)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_exporters_get_separate_spools() -> Result<()> {
+        let dir = tempdir()?;
+        let mut fan_out = ExporterFanOut::new();
+        fan_out.add_exporter(Box::new(MemoryExporter::new()), dir.path(), 1024)?;
+        fan_out.add_exporter(Box::new(MemoryExporter::new()), dir.path(), 1024)?;
+
+        assert_eq!(fan_out.len(), 2);
+        assert!(dir.path().join("memory").is_dir());
+        assert!(dir.path().join("memory-1").is_dir());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close_reaches_every_exporter() -> Result<()> {
+        let dir = tempdir()?;
+        let exporters = [MemoryExporter::new(), MemoryExporter::new()];
+
+        let mut fan_out = ExporterFanOut::new();
+        for exporter in &exporters {
+            fan_out.add_exporter(Box::new(exporter.clone()), dir.path(), 1024)?;
+        }
+        fan_out.close().await?;
+
+        assert!(exporters.iter().all(MemoryExporter::is_closed));
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+// src/exporters/file.rs
+//! Writes events to rotating NDJSON files, for hosts that can't reach any remote sink.
+//!
+//! Files are laid out as `<dir>/<pipeline name>/<run id>/events-<opened at>-<seq>.ndjson[.gz]`.
+//! A segment is rotated once it reaches the size or age limit, and every completed segment is
+//! appended to `<dir>/manifest.jsonl`. Files that are not listed in the manifest may still be
+//! written to.
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::types::event::Event;
+
+use super::{EventBatch, EventExporter};
+
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+pub const DEFAULT_MAX_SEGMENT_SIZE_BYTES: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_MAX_SEGMENT_AGE: Duration = Duration::from_secs(60 * 60);
+
+const UNKNOWN_PIPELINE: &str = "unknown_pipeline";
+const UNKNOWN_RUN: &str = "unknown_run";
+
+/// An entry of the manifest, describing a segment that won't be written to anymore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompletedSegment {
+    /// Relative to the export directory
+    pub path: PathBuf,
+    pub pipeline_name: String,
+    pub run_id: String,
+    pub events: u64,
+    /// Size of the file on disk
+    pub size_bytes: u64,
+    pub gzip: bool,
+    pub first_event_at: Option<DateTime<Utc>>,
+    pub last_event_at: Option<DateTime<Utc>>,
+    pub completed_at: DateTime<Utc>,
+}
+
+enum SegmentWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl SegmentWriter {
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            SegmentWriter::Plain(writer) => writer.write_all(buf),
+            SegmentWriter::Gzip(writer) => writer.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            SegmentWriter::Plain(writer) => writer.flush(),
+            SegmentWriter::Gzip(writer) => writer.flush(),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        let file = match self {
+            SegmentWriter::Plain(writer) => writer.into_inner().map_err(|err| err.into_error())?,
+            SegmentWriter::Gzip(writer) => writer
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?,
+        };
+        file.sync_all()
+    }
+}
+
+struct OpenSegment {
+    path: PathBuf,
+    writer: SegmentWriter,
+    /// Uncompressed bytes written so far
+    bytes: u64,
+    events: u64,
+    opened_at: Instant,
+    first_event_at: Option<DateTime<Utc>>,
+    last_event_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+struct Partition {
+    pipeline_name: String,
+    run_id: String,
+}
+
+impl Partition {
+    fn of(event: &Event) -> Self {
+        Partition {
+            pipeline_name: event
+                .pipeline_name
+                .clone()
+                .unwrap_or_else(|| UNKNOWN_PIPELINE.to_string()),
+            run_id: event
+                .run_id
+                .clone()
+                .unwrap_or_else(|| UNKNOWN_RUN.to_string()),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        Path::new(&sanitize_path_component(&self.pipeline_name))
+            .join(sanitize_path_component(&self.run_id))
+    }
+}
+
+pub struct FileExporter {
+    dir: PathBuf,
+    gzip: bool,
+    max_segment_size_bytes: u64,
+    max_segment_age: Duration,
+    segments: Mutex<HashMap<Partition, OpenSegment>>,
+}
+
+impl FileExporter {
+    pub fn new<P: AsRef<Path>>(
+        dir: P,
+        gzip: bool,
+        max_segment_size_bytes: u64,
+        max_segment_age: Duration,
+    ) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create export directory: {:?}", dir))?;
+
+        Ok(FileExporter {
+            dir,
+            gzip,
+            max_segment_size_bytes,
+            max_segment_age,
+            segments: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn open_segment(&self, partition: &Partition) -> Result<OpenSegment> {
+        let dir = self.dir.join(partition.dir());
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create export directory: {:?}", dir))?;
+
+        let extension = if self.gzip { "ndjson.gz" } else { "ndjson" };
+        let opened_at = Utc::now().format("%Y%m%dT%H%M%SZ");
+
+        // never append to a file left behind by an earlier segment or daemon
+        let (path, file) = (0..)
+            .find_map(|seq| {
+                let path = dir.join(format!("events-{opened_at}-{seq:04}.{extension}"));
+                match OpenOptions::new().write(true).create_new(true).open(&path) {
+                    Ok(file) => Some(Ok((path, file))),
+                    Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => None,
+                    Err(err) => Some(Err(err)),
+                }
+            })
+            .unwrap()
+            .with_context(|| format!("Failed to create export file in {:?}", dir))?;
+
+        let writer = BufWriter::new(file);
+        let writer = if self.gzip {
+            SegmentWriter::Gzip(GzEncoder::new(writer, Compression::default()))
+        } else {
+            SegmentWriter::Plain(writer)
+        };
+
+        Ok(OpenSegment {
+            path,
+            writer,
+            bytes: 0,
+            events: 0,
+            opened_at: Instant::now(),
+            first_event_at: None,
+            last_event_at: None,
+        })
+    }
+
+    fn is_due_for_rotation(&self, segment: &OpenSegment) -> bool {
+        segment.bytes >= self.max_segment_size_bytes
+            || segment.opened_at.elapsed() >= self.max_segment_age
+    }
+
+    /// Closes `segment` and lists it in the manifest.
+    fn complete_segment(&self, partition: &Partition, segment: OpenSegment) -> Result<()> {
+        segment
+            .writer
+            .finish()
+            .with_context(|| format!("Failed to finish export file {:?}", segment.path))?;
+
+        let entry = CompletedSegment {
+            path: segment
+                .path
+                .strip_prefix(&self.dir)
+                .unwrap_or(&segment.path)
+                .to_path_buf(),
+            pipeline_name: partition.pipeline_name.clone(),
+            run_id: partition.run_id.clone(),
+            events: segment.events,
+            size_bytes: fs::metadata(&segment.path)?.len(),
+            gzip: self.gzip,
+            first_event_at: segment.first_event_at,
+            last_event_at: segment.last_event_at,
+            completed_at: Utc::now(),
+        };
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(MANIFEST_FILE))
+            .context("Failed to open the export manifest")?;
+        manifest.write_all(&line)?;
+        manifest.sync_data()?;
+        Ok(())
+    }
+
+    fn write_events(&self, partition: Partition, events: &[&Event]) -> Result<()> {
+        let mut segments = self.segments.lock().unwrap();
+
+        if let Some(segment) = segments.get(&partition) {
+            if self.is_due_for_rotation(segment) {
+                let segment = segments.remove(&partition).unwrap();
+                self.complete_segment(&partition, segment)?;
+            }
+        }
+
+        let segment = match segments.get_mut(&partition) {
+            Some(segment) => segment,
+            None => {
+                let segment = self.open_segment(&partition)?;
+                segments.entry(partition.clone()).or_insert(segment)
+            }
+        };
+
+        for event in events {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+            segment
+                .writer
+                .write_all(&line)
+                .with_context(|| format!("Failed to write to {:?}", segment.path))?;
+
+            segment.bytes += line.len() as u64;
+            segment.events += 1;
+            segment.first_event_at.get_or_insert(event.timestamp);
+            segment.last_event_at = Some(event.timestamp);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventExporter for FileExporter {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn export(&self, batch: &EventBatch) -> Result<()> {
+        let mut partitions: Vec<(Partition, Vec<&Event>)> = Vec::new();
+        for event in &batch.events {
+            let partition = Partition::of(event);
+            match partitions.iter_mut().find(|(p, _)| *p == partition) {
+                Some((_, events)) => events.push(event),
+                None => partitions.push((partition, vec![event])),
+            }
+        }
+
+        for (partition, events) in partitions {
+            self.write_events(partition, &events)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes open segments and rotates the ones that got too old, even if no new events
+    /// arrived for them.
+    async fn flush(&self) -> Result<()> {
+        let mut segments = self.segments.lock().unwrap();
+
+        let expired: Vec<Partition> = segments
+            .iter()
+            .filter(|(_, segment)| self.is_due_for_rotation(segment))
+            .map(|(partition, _)| partition.clone())
+            .collect();
+        for partition in expired {
+            let segment = segments.remove(&partition).unwrap();
+            self.complete_segment(&partition, segment)?;
+        }
+
+        for segment in segments.values_mut() {
+            segment
+                .writer
+                .flush()
+                .with_context(|| format!("Failed to flush {:?}", segment.path))?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        let mut segments = self.segments.lock().unwrap();
+        for (partition, segment) in segments.drain() {
+            self.complete_segment(&partition, segment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps pipeline names and run ids from escaping the export directory.
+pub(super) fn sanitize_path_component(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match sanitized.trim_matches('.') {
+        "" => "_".to_string(),
+        _ => sanitized,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::{EventRecorder, EventType};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    fn batch(pipeline_name: &str, run_id: &str, count: usize) -> EventBatch {
+        let mut recorder = EventRecorder::new(
+            Some(pipeline_name.to_string()),
+            Some("run-name".to_string()),
+            Some(run_id.to_string()),
+        );
+        for i in 0..count {
+            recorder.record_event(EventType::TestEvent, format!("event {i}"), None, None);
+        }
+        EventBatch {
+            job_id: "run-name".to_string(),
+            events: recorder.get_events().to_vec(),
+        }
+    }
+
+    fn read_manifest(dir: &Path) -> Vec<CompletedSegment> {
+        fs::read_to_string(dir.join(MANIFEST_FILE))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn read_events(path: &Path, gzip: bool) -> Vec<Event> {
+        let mut content = String::new();
+        if gzip {
+            GzDecoder::new(File::open(path).unwrap())
+                .read_to_string(&mut content)
+                .unwrap();
+        } else {
+            content = fs::read_to_string(path).unwrap();
+        }
+        content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_events_are_partitioned_by_pipeline_and_run() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = FileExporter::new(
+            dir.path(),
+            false,
+            DEFAULT_MAX_SEGMENT_SIZE_BYTES,
+            DEFAULT_MAX_SEGMENT_AGE,
+        )?;
+
+        exporter.export(&batch("rnaseq", "run-1", 2)).await?;
+        exporter.export(&batch("rnaseq", "run-2", 1)).await?;
+        exporter.export(&batch("rnaseq", "run-1", 1)).await?;
+
+        // nothing is final before the segments are closed
+        exporter.flush().await?;
+        assert!(read_manifest(dir.path()).is_empty());
+
+        exporter.close().await?;
+        let mut manifest = read_manifest(dir.path());
+        manifest.sort_by(|a, b| a.run_id.cmp(&b.run_id));
+
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].run_id, "run-1");
+        assert_eq!(manifest[0].events, 3);
+        assert!(manifest[0].path.starts_with("rnaseq/run-1"));
+        assert_eq!(manifest[1].events, 1);
+
+        let events = read_events(&dir.path().join(&manifest[0].path), false);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].run_id.as_deref(), Some("run-1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_segments_rotate_by_size() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = FileExporter::new(dir.path(), false, 1, DEFAULT_MAX_SEGMENT_AGE)?;
+
+        for _ in 0..3 {
+            exporter.export(&batch("rnaseq", "run-1", 1)).await?;
+        }
+
+        // every batch after the first one rotates the full segment
+        assert_eq!(read_manifest(dir.path()).len(), 2);
+
+        exporter.close().await?;
+        let manifest = read_manifest(dir.path());
+        assert_eq!(manifest.len(), 3);
+        assert!(manifest.iter().all(|segment| segment.events == 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_segments_rotate_by_age_on_flush() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = FileExporter::new(
+            dir.path(),
+            false,
+            DEFAULT_MAX_SEGMENT_SIZE_BYTES,
+            Duration::ZERO,
+        )?;
+
+        exporter.export(&batch("rnaseq", "run-1", 1)).await?;
+        exporter.flush().await?;
+
+        assert_eq!(read_manifest(dir.path()).len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gzip_segments() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = FileExporter::new(
+            dir.path(),
+            true,
+            DEFAULT_MAX_SEGMENT_SIZE_BYTES,
+            DEFAULT_MAX_SEGMENT_AGE,
+        )?;
+
+        exporter.export(&batch("rnaseq", "run-1", 5)).await?;
+        exporter.close().await?;
+
+        let manifest = read_manifest(dir.path());
+        assert!(manifest[0].gzip);
+        assert!(manifest[0].path.to_str().unwrap().ends_with(".ndjson.gz"));
+        assert_eq!(
+            read_events(&dir.path().join(&manifest[0].path), true).len(),
+            5
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_path_component() {
+        assert_eq!(sanitize_path_component("my pipeline"), "my_pipeline");
+        assert_eq!(sanitize_path_component("../etc"), ".._etc");
+        assert_eq!(sanitize_path_component(".."), "_");
+    }
+}
@@ -0,0 +1,57 @@
+// src/exporters/memory.rs
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use super::{EventBatch, EventExporter};
+
+/// Keeps exported batches in memory. Clones share the same storage, so a test can hand one clone
+/// to the daemon and inspect what was exported through another.
+#[derive(Clone, Default)]
+pub struct MemoryExporter {
+    batches: Arc<Mutex<Vec<EventBatch>>>,
+    failing: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+}
+
+impl MemoryExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn batches(&self) -> Vec<EventBatch> {
+        self.batches.lock().unwrap().clone()
+    }
+
+    /// Makes every following export fail, to simulate an unreachable destination.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl EventExporter for MemoryExporter {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    async fn export(&self, batch: &EventBatch) -> Result<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            bail!("memory exporter is set to fail");
+        }
+        self.batches.lock().unwrap().push(batch.clone());
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
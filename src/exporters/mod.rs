@@ -1 +1,45 @@
+// src/exporters/mod.rs
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::types::event::Event;
+
 pub mod db;
+pub mod fanout;
+pub mod file;
+pub mod memory;
+pub mod otlp;
+pub mod parquet;
+pub mod prometheus;
+pub mod relational;
+pub mod spool;
+#[cfg(test)]
+pub mod test_support;
+
+/// A batch of events recorded for one run, the unit handed to exporters.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventBatch {
+    pub job_id: String,
+    pub events: Vec<Event>,
+}
+
+/// A destination for recorded events. Exporters are driven by `ExporterFanOut`, which takes care
+/// of spooling and retrying batches an exporter failed to accept.
+#[async_trait]
+pub trait EventExporter: Send + Sync {
+    /// Short identifier used in logs and as the name of the exporter's spool directory
+    fn name(&self) -> &str;
+
+    async fn export(&self, batch: &EventBatch) -> Result<()>;
+
+    /// Pushes out anything the exporter buffers internally.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes and releases the exporter's resources, called once when the daemon stops.
+    async fn close(&self) -> Result<()> {
+        self.flush().await
+    }
+}
@@ -0,0 +1,891 @@
+// src/exporters/otlp.rs
+//! Exports runs as OTLP traces and samples as OTLP metrics over OTLP/HTTP.
+//!
+//! Every run is a trace with a root span covering the whole run, every tool execution becomes a
+//! child span from its `ToolExecution` to its `FinishedToolExecution` event. Spans are only sent
+//! once they are complete. System and tool metric samples are sent as gauges.
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use opentelemetry_proto::tonic::{
+    collector::{metrics::v1::ExportMetricsServiceRequest, trace::v1::ExportTraceServiceRequest},
+    common::v1::{any_value, AnyValue, ArrayValue, InstrumentationScope, KeyValue},
+    metrics::v1::{
+        metric, number_data_point, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics,
+    },
+    resource::v1::Resource,
+    trace::v1::{span::SpanKind, status::StatusCode, ResourceSpans, ScopeSpans, Span, Status},
+};
+use prost::Message;
+use serde::Serialize;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use uuid::Uuid;
+
+use crate::{
+    events::recorder::EventType,
+    types::{
+        config::OtlpProtocol,
+        event::{
+            attributes::{
+                process::{CompletedProcess, ProcessProperties},
+                system_metrics::{SystemMetric, SystemPressure},
+                EventAttributes,
+            },
+            Event,
+        },
+    },
+};
+
+use super::{EventBatch, EventExporter};
+
+const TRACES_PATH: &str = "v1/traces";
+const METRICS_PATH: &str = "v1/metrics";
+const SCOPE_NAME: &str = "tracer";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const UNKNOWN_RUN: &str = "unknown_run";
+
+#[derive(Clone)]
+struct RunStart {
+    name: String,
+    pipeline_name: Option<String>,
+    tags: Vec<String>,
+    start: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+struct ToolStart {
+    start: DateTime<Utc>,
+    properties: ProcessProperties,
+}
+
+/// Open runs and tool executions, kept until their end event arrives.
+#[derive(Clone, Default)]
+struct TraceState {
+    runs: HashMap<String, RunStart>,
+    tools: HashMap<(String, String), ToolStart>,
+}
+
+pub struct OtlpExporter {
+    client: reqwest::Client,
+    endpoint: String,
+    protocol: OtlpProtocol,
+    headers: HashMap<String, String>,
+    state: Mutex<TraceState>,
+}
+
+impl OtlpExporter {
+    pub fn new(
+        endpoint: &str,
+        protocol: OtlpProtocol,
+        headers: HashMap<String, String>,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create OTLP http client")?;
+
+        Ok(OtlpExporter {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            protocol,
+            headers,
+            state: Mutex::new(TraceState::default()),
+        })
+    }
+
+    /// Turns the events into finished spans and metric data points, remembering the start of
+    /// runs and tools that are still going in `state`.
+    fn collect(state: &mut TraceState, batch: &EventBatch) -> (Vec<Span>, Vec<Metric>) {
+        let mut spans = Vec::new();
+        let mut metrics = Vec::new();
+
+        for event in &batch.events {
+            let run_id = event.run_id.as_deref().unwrap_or(UNKNOWN_RUN);
+            let status = event.process_status.as_str();
+
+            if status == EventType::NewRun.as_str() {
+                state.runs.insert(
+                    run_id.to_string(),
+                    RunStart {
+                        name: event
+                            .run_name
+                            .clone()
+                            .unwrap_or_else(|| batch.job_id.clone()),
+                        pipeline_name: event.pipeline_name.clone(),
+                        tags: event.tags.clone(),
+                        start: event.timestamp,
+                    },
+                );
+            } else if status == EventType::FinishedRun.as_str() {
+                let run = state.runs.remove(run_id);
+                spans.push(run_span(
+                    run_id,
+                    run.as_ref(),
+                    event.timestamp,
+                    event.run_name.as_deref(),
+                ));
+            }
+
+            match &event.attributes {
+                Some(EventAttributes::Process(properties))
+                    if status == EventType::ToolExecution.as_str() =>
+                {
+                    state.tools.insert(
+                        (run_id.to_string(), properties.tool_pid.clone()),
+                        ToolStart {
+                            start: event.timestamp,
+                            properties: properties.clone(),
+                        },
+                    );
+                }
+                Some(EventAttributes::CompletedProcess(completed)) => {
+                    let tool = state
+                        .tools
+                        .remove(&(run_id.to_string(), completed.tool_pid.clone()));
+                    spans.push(tool_span(run_id, tool.as_ref(), completed, event));
+                }
+                Some(EventAttributes::Process(properties))
+                    if status == EventType::ToolMetricEvent.as_str() =>
+                {
+                    metrics.extend(tool_metrics(run_id, properties, event));
+                }
+                Some(EventAttributes::SystemMetric(metric)) => {
+                    metrics.extend(system_metrics(run_id, metric, event));
+                }
+                _ => {}
+            }
+        }
+
+        (spans, metrics)
+    }
+
+    fn export_traces_request(spans: Vec<Span>) -> ExportTraceServiceRequest {
+        ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: Some(resource()),
+                scope_spans: vec![ScopeSpans {
+                    scope: Some(scope()),
+                    spans,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        }
+    }
+
+    async fn post<T: Message + Serialize>(&self, path: &str, request: &T) -> Result<()> {
+        let url = format!("{}/{}", self.endpoint, path);
+        let (content_type, body) = match self.protocol {
+            OtlpProtocol::Protobuf => ("application/x-protobuf", request.encode_to_vec()),
+            OtlpProtocol::Json => ("application/json", serde_json::to_vec(request)?),
+        };
+
+        let mut http_request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(body);
+        for (name, value) in &self.headers {
+            http_request = http_request.header(name, value);
+        }
+
+        let response = http_request
+            .send()
+            .await
+            .with_context(|| format!("Failed to send OTLP request to {url}"))?;
+        if !response.status().is_success() {
+            bail!(
+                "OTLP collector at {url} responded with {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventExporter for OtlpExporter {
+    fn name(&self) -> &str {
+        "otlp"
+    }
+
+    /// The open runs and tools only change once both requests went through. A failed batch is
+    /// replayed whole from the spool, which needs the starts of the spans it ends.
+    async fn export(&self, batch: &EventBatch) -> Result<()> {
+        let mut state = self.state.lock().unwrap().clone();
+        let (spans, metrics) = Self::collect(&mut state, batch);
+
+        if !spans.is_empty() {
+            let request = Self::export_traces_request(spans);
+            self.post(TRACES_PATH, &request).await?;
+        }
+
+        if !metrics.is_empty() {
+            let request = ExportMetricsServiceRequest {
+                resource_metrics: vec![ResourceMetrics {
+                    resource: Some(resource()),
+                    scope_metrics: vec![ScopeMetrics {
+                        scope: Some(scope()),
+                        metrics,
+                        schema_url: String::new(),
+                    }],
+                    schema_url: String::new(),
+                }],
+            };
+            self.post(METRICS_PATH, &request).await?;
+        }
+
+        *self.state.lock().unwrap() = state;
+        Ok(())
+    }
+
+    /// Ends the spans of runs that never saw their `FinishedRun` event.
+    async fn close(&self) -> Result<()> {
+        let spans: Vec<Span> = {
+            let mut state = self.state.lock().unwrap();
+            state.tools.clear();
+            state
+                .runs
+                .drain()
+                .map(|(run_id, run)| run_span(&run_id, Some(&run), Utc::now(), None))
+                .collect()
+        };
+
+        if !spans.is_empty() {
+            let request = Self::export_traces_request(spans);
+            self.post(TRACES_PATH, &request).await?;
+        }
+        Ok(())
+    }
+}
+
+fn resource() -> Resource {
+    Resource {
+        attributes: vec![
+            string_attribute("service.name", SCOPE_NAME),
+            string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+        ],
+        dropped_attributes_count: 0,
+    }
+}
+
+fn scope() -> InstrumentationScope {
+    InstrumentationScope {
+        name: SCOPE_NAME.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        ..Default::default()
+    }
+}
+
+/// Trace ids are derived from the run id, so all spans of a run end up in the same trace even
+/// across daemon restarts.
+pub fn trace_id(run_id: &str) -> [u8; 16] {
+    Uuid::parse_str(run_id)
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_OID, run_id.as_bytes()))
+        .into_bytes()
+}
+
+fn span_id(run_id: &str, name: &str) -> [u8; 8] {
+    let key = format!("{run_id}/{name}");
+    let bytes = Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).into_bytes();
+    bytes[..8].try_into().unwrap()
+}
+
+fn run_span_id(run_id: &str) -> [u8; 8] {
+    span_id(run_id, "run")
+}
+
+fn unix_nanos(timestamp: DateTime<Utc>) -> u64 {
+    timestamp.timestamp_nanos_opt().unwrap_or_default().max(0) as u64
+}
+
+/// The root span of a run. `run_name` names the span when the start of the run wasn't seen.
+fn run_span(
+    run_id: &str,
+    run: Option<&RunStart>,
+    end: DateTime<Utc>,
+    run_name: Option<&str>,
+) -> Span {
+    let mut attributes = vec![string_attribute("tracer.run.id", run_id)];
+    let name = match run {
+        Some(run) => {
+            attributes.push(string_attribute("tracer.run.name", &run.name));
+            if let Some(pipeline_name) = &run.pipeline_name {
+                attributes.push(string_attribute("tracer.pipeline.name", pipeline_name));
+            }
+            attributes.push(KeyValue {
+                key: "tracer.tags".to_string(),
+                value: Some(AnyValue {
+                    value: Some(any_value::Value::ArrayValue(ArrayValue {
+                        values: run.tags.iter().map(|tag| string_value(tag)).collect(),
+                    })),
+                }),
+            });
+            run.name.clone()
+        }
+        None => run_name.unwrap_or(run_id).to_string(),
+    };
+
+    Span {
+        trace_id: trace_id(run_id).to_vec(),
+        span_id: run_span_id(run_id).to_vec(),
+        name,
+        kind: SpanKind::Internal as i32,
+        start_time_unix_nano: unix_nanos(run.map_or(end, |run| run.start)),
+        end_time_unix_nano: unix_nanos(end),
+        attributes,
+        ..Default::default()
+    }
+}
+
+fn tool_span(
+    run_id: &str,
+    tool: Option<&ToolStart>,
+    completed: &CompletedProcess,
+    end: &Event,
+) -> Span {
+    // without the start event, the start is all we can infer from the duration
+    let start = match tool {
+        Some(tool) => tool.start,
+        None => end.timestamp - chrono::Duration::seconds(completed.duration_sec as i64),
+    };
+
+    let usage = &completed.resource_usage;
+    let mut attributes = vec![
+        string_attribute("tracer.tool.name", &completed.tool_name),
+        string_attribute("process.pid", &completed.tool_pid),
+        int_attribute("tracer.tool.duration_sec", completed.duration_sec),
+        int_attribute("tracer.tool.memory.peak", usage.peak_memory_usage),
+        int_attribute("tracer.tool.memory.virtual.peak", usage.peak_memory_virtual),
+        double_attribute("tracer.tool.cpu.time", usage.cpu_time_sec),
+        double_attribute(
+            "tracer.tool.cpu.utilization.average",
+            usage.average_cpu_utilization,
+        ),
+        int_attribute("tracer.tool.disk.read_bytes", usage.disk_usage_read_total),
+        int_attribute("tracer.tool.disk.write_bytes", usage.disk_usage_write_total),
+    ];
+    if let Some(exit_code) = completed.exit_code {
+        attributes.push(int_attribute("process.exit.code", exit_code as u64));
+    }
+    if let Some(signal) = completed.signal {
+        attributes.push(int_attribute("process.exit.signal", signal as u64));
+    }
+    attributes.push(KeyValue {
+        key: "process.oom_killed".to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::BoolValue(completed.oom_killed)),
+        }),
+    });
+    if let Some(tool) = tool {
+        attributes.extend(process_attributes(&tool.properties));
+    }
+
+    // an exit status we missed isn't a failure, the span status stays unset
+    let status = completed.failed().then(|| Status {
+        message: match (completed.oom_killed, completed.signal, completed.exit_code) {
+            (true, _, _) => "killed by the OOM killer".to_string(),
+            (false, Some(signal), _) => format!("killed by signal {signal}"),
+            (false, None, code) => format!("exited with code {}", code.unwrap_or_default()),
+        },
+        code: StatusCode::Error as i32,
+    });
+
+    Span {
+        trace_id: trace_id(run_id).to_vec(),
+        span_id: span_id(
+            run_id,
+            &format!("{}/{}", completed.tool_pid, unix_nanos(start)),
+        )
+        .to_vec(),
+        parent_span_id: run_span_id(run_id).to_vec(),
+        name: completed.tool_name.clone(),
+        kind: SpanKind::Internal as i32,
+        start_time_unix_nano: unix_nanos(start),
+        end_time_unix_nano: unix_nanos(end.timestamp),
+        attributes,
+        status,
+        ..Default::default()
+    }
+}
+
+fn process_attributes(properties: &ProcessProperties) -> Vec<KeyValue> {
+    vec![
+        string_attribute("process.parent_pid", &properties.tool_parent_pid),
+        string_attribute("process.executable.path", &properties.tool_binary_path),
+        string_attribute("process.command_line", &properties.tool_cmd),
+        string_attribute("process.start_timestamp", &properties.start_timestamp),
+        double_attribute(
+            "process.cpu.utilization",
+            properties.process_cpu_utilization as f64,
+        ),
+        int_attribute("process.memory.usage", properties.process_memory_usage),
+        int_attribute("process.memory.virtual", properties.process_memory_virtual),
+        int_attribute(
+            "process.disk.read_bytes",
+            properties.process_disk_usage_read_total,
+        ),
+        int_attribute(
+            "process.disk.write_bytes",
+            properties.process_disk_usage_write_total,
+        ),
+    ]
+}
+
+fn gauge(name: &str, unit: &str, point: NumberDataPoint) -> Metric {
+    Metric {
+        name: name.to_string(),
+        unit: unit.to_string(),
+        data: Some(metric::Data::Gauge(Gauge {
+            data_points: vec![point],
+        })),
+        ..Default::default()
+    }
+}
+
+fn data_point(
+    attributes: &[KeyValue],
+    event: &Event,
+    value: number_data_point::Value,
+) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes: attributes.to_vec(),
+        time_unix_nano: unix_nanos(event.timestamp),
+        value: Some(value),
+        ..Default::default()
+    }
+}
+
+fn run_attributes(run_id: &str, event: &Event) -> Vec<KeyValue> {
+    let mut attributes = vec![string_attribute("tracer.run.id", run_id)];
+    if let Some(pipeline_name) = &event.pipeline_name {
+        attributes.push(string_attribute("tracer.pipeline.name", pipeline_name));
+    }
+    attributes
+}
+
+fn system_metrics(run_id: &str, metric: &SystemMetric, event: &Event) -> Vec<Metric> {
+    use number_data_point::Value::{AsDouble, AsInt};
+
+    let attributes = run_attributes(run_id, event);
+    let point = |value| data_point(&attributes, event, value);
+
+    let mut metrics = vec![
+        gauge(
+            "system.memory.usage",
+            "By",
+            point(AsInt(metric.system_memory_used as i64)),
+        ),
+        gauge(
+            "system.memory.utilization",
+            "%",
+            point(AsDouble(metric.system_memory_utilization)),
+        ),
+        gauge(
+            "system.swap.usage",
+            "By",
+            point(AsInt(metric.system_memory_swap_used as i64)),
+        ),
+        gauge(
+            "system.cpu.utilization",
+            "%",
+            point(AsDouble(metric.system_cpu_utilization as f64)),
+        ),
+        gauge(
+            "system.cpu.load_average.1m",
+            "{thread}",
+            point(AsDouble(metric.system_load_average_1m)),
+        ),
+        gauge(
+            "system.cpu.load_average.5m",
+            "{thread}",
+            point(AsDouble(metric.system_load_average_5m)),
+        ),
+        gauge(
+            "system.cpu.load_average.15m",
+            "{thread}",
+            point(AsDouble(metric.system_load_average_15m)),
+        ),
+    ];
+
+    for (resource, stall) in SystemPressure::RESOURCES
+        .iter()
+        .zip(metric.system_pressure.stalls())
+    {
+        let Some(stall) = stall else {
+            continue;
+        };
+        let mut attributes = attributes.clone();
+        attributes.push(string_attribute("system.pressure.resource", resource));
+        metrics.push(gauge(
+            "system.pressure.some.avg10",
+            "%",
+            data_point(&attributes, event, AsDouble(stall.some_avg10)),
+        ));
+        metrics.push(gauge(
+            "system.pressure.full.avg10",
+            "%",
+            data_point(&attributes, event, AsDouble(stall.full_avg10)),
+        ));
+    }
+    metrics
+}
+
+fn tool_metrics(run_id: &str, properties: &ProcessProperties, event: &Event) -> Vec<Metric> {
+    use number_data_point::Value::{AsDouble, AsInt};
+
+    let mut attributes = run_attributes(run_id, event);
+    attributes.push(string_attribute("tracer.tool.name", &properties.tool_name));
+    attributes.push(string_attribute("process.pid", &properties.tool_pid));
+    let point = |value| data_point(&attributes, event, value);
+
+    let mut metrics = vec![
+        gauge(
+            "process.cpu.utilization",
+            "%",
+            point(AsDouble(properties.process_cpu_utilization as f64)),
+        ),
+        gauge(
+            "process.memory.usage",
+            "By",
+            point(AsInt(properties.process_memory_usage as i64)),
+        ),
+        gauge(
+            "process.memory.virtual",
+            "By",
+            point(AsInt(properties.process_memory_virtual as i64)),
+        ),
+        gauge(
+            "process.disk.read_bytes",
+            "By",
+            point(AsInt(properties.process_disk_usage_read_total as i64)),
+        ),
+        gauge(
+            "process.disk.write_bytes",
+            "By",
+            point(AsInt(properties.process_disk_usage_write_total as i64)),
+        ),
+    ];
+
+    if let Some(details) = &properties.details {
+        metrics.push(gauge(
+            "process.thread.count",
+            "{thread}",
+            point(AsInt(details.threads as i64)),
+        ));
+        if let Some(open_fds) = details.open_fds {
+            metrics.push(gauge(
+                "process.open_file_descriptor.count",
+                "{file_descriptor}",
+                point(AsInt(open_fds as i64)),
+            ));
+        }
+    }
+    metrics
+}
+
+fn string_value(value: &str) -> AnyValue {
+    AnyValue {
+        value: Some(any_value::Value::StringValue(value.to_string())),
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(string_value(value)),
+    }
+}
+
+fn int_attribute(key: &str, value: u64) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::IntValue(value as i64)),
+        }),
+    }
+}
+
+fn double_attribute(key: &str, value: f64) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::DoubleValue(value)),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+        task::JoinHandle,
+    };
+
+    struct CollectorRequest {
+        path: String,
+        content_type: String,
+        body: Vec<u8>,
+    }
+
+    /// A stand-in for an OTLP collector: answers requests with 200, or 500 for `failing_path`,
+    /// and hands them to the test.
+    async fn start_collector(
+        failing_path: Option<&'static str>,
+    ) -> (
+        String,
+        mpsc::UnboundedReceiver<CollectorRequest>,
+        JoinHandle<()>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let path = request_line.split(' ').nth(1).unwrap().to_string();
+
+                let mut content_type = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(':').unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-type" => content_type = value.trim().to_string(),
+                        "content-length" => content_length = value.trim().parse().unwrap(),
+                        _ => {}
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).await.unwrap();
+                let status = if failing_path == Some(path.as_str()) {
+                    "500 Internal Server Error"
+                } else {
+                    "200 OK"
+                };
+                stream
+                    .get_mut()
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+
+                sender
+                    .send(CollectorRequest {
+                        path,
+                        content_type,
+                        body,
+                    })
+                    .unwrap();
+            }
+        });
+
+        (endpoint, receiver, handle)
+    }
+
+    fn process_properties(pid: &str) -> ProcessProperties {
+        ProcessProperties {
+            tool_name: "STAR".to_string(),
+            tool_pid: pid.to_string(),
+            tool_parent_pid: "1".to_string(),
+            tool_binary_path: "/usr/bin/STAR".to_string(),
+            tool_cmd: "STAR --runThreadN 4".to_string(),
+            start_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            process_cpu_utilization: 50.0,
+            process_memory_usage: 1024,
+            process_memory_virtual: 2048,
+            process_run_time: 3,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 10,
+            process_disk_usage_write_total: 20,
+            process_status: "Run".to_string(),
+            input_files: None,
+            subtree: None,
+            cgroup: None,
+            container: None,
+            details: None,
+        }
+    }
+
+    fn run_batch() -> EventBatch {
+        let mut recorder = EventRecorder::new(
+            Some("rnaseq".to_string()),
+            Some("brave-otter".to_string()),
+            Some("5f3b8a56-1f0e-4b8e-9d55-4c1d2f1d6c01".to_string()),
+        );
+        recorder.record_event(EventType::NewRun, "start".to_string(), None, None);
+        recorder.record_event(
+            EventType::ToolExecution,
+            "STAR started".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::ToolMetricEvent,
+            "STAR metrics".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "STAR finished".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "STAR".to_string(),
+                tool_pid: "42".to_string(),
+                duration_sec: 3,
+                exit_code: Some(0),
+                signal: None,
+                oom_killed: false,
+                resource_usage: Default::default(),
+                input_paths: vec![],
+                output_paths: vec![],
+            })),
+            None,
+        );
+        recorder.record_event(EventType::FinishedRun, "end".to_string(), None, None);
+
+        EventBatch {
+            job_id: "brave-otter".to_string(),
+            events: recorder.get_events().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protobuf_traces_and_metrics() -> Result<()> {
+        let (endpoint, mut requests, collector) = start_collector(None).await;
+        let exporter = OtlpExporter::new(&endpoint, OtlpProtocol::Protobuf, HashMap::new())?;
+
+        exporter.export(&run_batch()).await?;
+
+        let traces = requests.recv().await.unwrap();
+        assert_eq!(traces.path, "/v1/traces");
+        assert_eq!(traces.content_type, "application/x-protobuf");
+        let traces = ExportTraceServiceRequest::decode(traces.body.as_slice())?;
+        let spans = &traces.resource_spans[0].scope_spans[0].spans;
+
+        let tool = spans.iter().find(|span| span.name == "STAR").unwrap();
+        let run = spans
+            .iter()
+            .find(|span| span.name == "brave-otter")
+            .unwrap();
+        assert_eq!(tool.trace_id, run.trace_id);
+        assert_eq!(tool.parent_span_id, run.span_id);
+        assert!(run.parent_span_id.is_empty());
+        assert_eq!(
+            Uuid::from_slice(&run.trace_id)?.to_string(),
+            "5f3b8a56-1f0e-4b8e-9d55-4c1d2f1d6c01"
+        );
+        assert!(tool
+            .attributes
+            .iter()
+            .any(|attribute| attribute.key == "process.command_line"));
+
+        let metrics = requests.recv().await.unwrap();
+        assert_eq!(metrics.path, "/v1/metrics");
+        let metrics = ExportMetricsServiceRequest::decode(metrics.body.as_slice())?;
+        let names: Vec<_> = metrics.resource_metrics[0].scope_metrics[0]
+            .metrics
+            .iter()
+            .map(|metric| metric.name.as_str())
+            .collect();
+        assert!(names.contains(&"process.memory.usage"));
+
+        collector.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_json_encoding() -> Result<()> {
+        let (endpoint, mut requests, collector) = start_collector(None).await;
+        let exporter = OtlpExporter::new(&endpoint, OtlpProtocol::Json, HashMap::new())?;
+
+        exporter.export(&run_batch()).await?;
+
+        let traces = requests.recv().await.unwrap();
+        assert_eq!(traces.content_type, "application/json");
+        let traces: Value = serde_json::from_slice(&traces.body)?;
+        let spans = &traces["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 2);
+        // OTLP/JSON encodes ids as hex
+        assert_eq!(spans[0]["traceId"], "5f3b8a561f0e4b8e9d554c1d2f1d6c01");
+
+        collector.abort();
+        Ok(())
+    }
+
+    #[test]
+    fn test_spans_wait_for_their_end_event() {
+        let batch = run_batch();
+
+        // the first two events only open the run and the tool
+        let mut state = TraceState::default();
+        let (spans, _) = OtlpExporter::collect(
+            &mut state,
+            &EventBatch {
+                job_id: batch.job_id.clone(),
+                events: batch.events[..2].to_vec(),
+            },
+        );
+        assert!(spans.is_empty());
+
+        let (spans, metrics) = OtlpExporter::collect(
+            &mut state,
+            &EventBatch {
+                job_id: batch.job_id.clone(),
+                events: batch.events[2..].to_vec(),
+            },
+        );
+        assert_eq!(spans.len(), 2);
+        assert_eq!(metrics.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_failed_export_keeps_open_spans() -> Result<()> {
+        let (endpoint, mut requests, collector) = start_collector(Some("/v1/metrics")).await;
+        let exporter = OtlpExporter::new(&endpoint, OtlpProtocol::Json, HashMap::new())?;
+        let batch = run_batch();
+        let opening = EventBatch {
+            job_id: batch.job_id.clone(),
+            events: batch.events[..2].to_vec(),
+        };
+        let closing = EventBatch {
+            job_id: batch.job_id.clone(),
+            events: batch.events[2..].to_vec(),
+        };
+
+        exporter.export(&opening).await?;
+        // the traces go through, the metrics don't
+        assert!(exporter.export(&closing).await.is_err());
+        assert_eq!(requests.recv().await.unwrap().path, "/v1/traces");
+        assert_eq!(requests.recv().await.unwrap().path, "/v1/metrics");
+
+        // replaying the batch still finds the starts of the run and the tool
+        let state = exporter.state.lock().unwrap().clone();
+        assert_eq!((state.runs.len(), state.tools.len()), (1, 1));
+
+        collector.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,1054 @@
+// src/exporters/parquet.rs
+//! Writes events to Parquet files, one file per run and event family, with the event attributes
+//! flattened into typed columns.
+//!
+//! Files are written to `<dir>/<run id>/<family>.parquet`. While a run is in progress its files
+//! carry an `.inprogress` suffix, they get their final name once the run finishes or the daemon
+//! stops.
+use anyhow::{Context, Result};
+use arrow_array::{
+    builder::{ListBuilder, StringBuilder},
+    ArrayRef, BooleanArray, Float32Array, Float64Array, Int32Array, RecordBatch, StringArray,
+    TimestampMillisecondArray, UInt64Array,
+};
+use arrow_schema::{Field, Schema};
+use async_trait::async_trait;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    events::recorder::EventType,
+    types::event::{
+        attributes::{
+            cgroup::CgroupUsage,
+            lineage::RunLineage,
+            process::{CompletedProcess, ProcessProperties},
+            syslog::SyslogProperties,
+            system_metrics::{SystemMetric, SystemPressure},
+            EventAttributes,
+        },
+        Event,
+    },
+};
+
+use super::{file::sanitize_path_component, EventBatch, EventExporter};
+
+const IN_PROGRESS_SUFFIX: &str = "inprogress";
+const UNKNOWN_RUN: &str = "unknown_run";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventFamily {
+    Process,
+    SystemMetric,
+    Syslog,
+    CompletedProcess,
+    CgroupUsage,
+    RunLineage,
+}
+
+impl EventFamily {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventFamily::Process => "process",
+            EventFamily::SystemMetric => "system_metric",
+            EventFamily::Syslog => "syslog",
+            EventFamily::CompletedProcess => "completed_process",
+            EventFamily::CgroupUsage => "cgroup_usage",
+            EventFamily::RunLineage => "run_lineage",
+        }
+    }
+
+    fn of(event: &Event) -> Option<Self> {
+        match event.attributes.as_ref()? {
+            EventAttributes::Process(_) => Some(EventFamily::Process),
+            EventAttributes::SystemMetric(_) => Some(EventFamily::SystemMetric),
+            EventAttributes::Syslog(_) => Some(EventFamily::Syslog),
+            EventAttributes::CompletedProcess(_) => Some(EventFamily::CompletedProcess),
+            EventAttributes::CgroupUsage(_) => Some(EventFamily::CgroupUsage),
+            EventAttributes::RunLineage(_) => Some(EventFamily::RunLineage),
+            _ => None,
+        }
+    }
+}
+
+/// Accumulates the columns of a record batch.
+#[derive(Default)]
+struct Columns {
+    fields: Vec<Field>,
+    arrays: Vec<ArrayRef>,
+}
+
+impl Columns {
+    fn push(&mut self, name: &str, nullable: bool, array: ArrayRef) {
+        self.fields
+            .push(Field::new(name, array.data_type().clone(), nullable));
+        self.arrays.push(array);
+    }
+
+    fn utf8<'a>(&mut self, name: &str, values: impl Iterator<Item = &'a str>) {
+        self.push(name, false, Arc::new(StringArray::from_iter_values(values)));
+    }
+
+    fn optional_utf8<'a>(&mut self, name: &str, values: impl Iterator<Item = Option<&'a str>>) {
+        self.push(name, true, Arc::new(values.collect::<StringArray>()));
+    }
+
+    fn u64(&mut self, name: &str, values: impl Iterator<Item = u64>) {
+        self.push(name, false, Arc::new(UInt64Array::from_iter_values(values)));
+    }
+
+    fn optional_f64(&mut self, name: &str, values: impl Iterator<Item = Option<f64>>) {
+        self.push(name, true, Arc::new(values.collect::<Float64Array>()));
+    }
+
+    fn optional_u64(&mut self, name: &str, values: impl Iterator<Item = Option<u64>>) {
+        self.push(name, true, Arc::new(values.collect::<UInt64Array>()));
+    }
+
+    fn optional_i32(&mut self, name: &str, values: impl Iterator<Item = Option<i32>>) {
+        self.push(name, true, Arc::new(values.collect::<Int32Array>()));
+    }
+
+    fn bool(&mut self, name: &str, values: impl Iterator<Item = bool>) {
+        self.push(
+            name,
+            false,
+            Arc::new(values.map(Some).collect::<BooleanArray>()),
+        );
+    }
+
+    fn f32(&mut self, name: &str, values: impl Iterator<Item = f32>) {
+        self.push(
+            name,
+            false,
+            Arc::new(Float32Array::from_iter_values(values)),
+        );
+    }
+
+    fn f64(&mut self, name: &str, values: impl Iterator<Item = f64>) {
+        self.push(
+            name,
+            false,
+            Arc::new(Float64Array::from_iter_values(values)),
+        );
+    }
+
+    fn string_list<'a, I>(&mut self, name: &str, values: impl Iterator<Item = I>)
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let mut builder = ListBuilder::new(StringBuilder::new());
+        for list in values {
+            for value in list {
+                builder.values().append_value(value);
+            }
+            builder.append(true);
+        }
+        self.push(name, false, Arc::new(builder.finish()));
+    }
+
+    fn into_record_batch(self) -> Result<RecordBatch> {
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(self.fields)),
+            self.arrays,
+        )?)
+    }
+}
+
+/// Columns shared by every family.
+fn event_columns(events: &[&Event]) -> Columns {
+    let mut columns = Columns::default();
+    columns.push(
+        "timestamp",
+        false,
+        Arc::new(
+            TimestampMillisecondArray::from_iter_values(
+                events
+                    .iter()
+                    .map(|event| event.timestamp.timestamp_millis()),
+            )
+            .with_timezone("UTC"),
+        ),
+    );
+    columns.utf8(
+        "event_type",
+        events.iter().map(|e| e.process_status.as_str()),
+    );
+    columns.utf8("message", events.iter().map(|e| e.message.as_str()));
+    columns.optional_utf8(
+        "pipeline_name",
+        events.iter().map(|e| e.pipeline_name.as_deref()),
+    );
+    columns.optional_utf8("run_name", events.iter().map(|e| e.run_name.as_deref()));
+    columns.optional_utf8("run_id", events.iter().map(|e| e.run_id.as_deref()));
+    columns.string_list("tags", events.iter().map(|e| &e.tags));
+    columns
+}
+
+fn process_columns(columns: &mut Columns, attributes: &[&ProcessProperties]) -> Result<()> {
+    let a = attributes;
+    columns.utf8("tool_name", a.iter().map(|p| p.tool_name.as_str()));
+    columns.utf8("tool_pid", a.iter().map(|p| p.tool_pid.as_str()));
+    columns.utf8(
+        "tool_parent_pid",
+        a.iter().map(|p| p.tool_parent_pid.as_str()),
+    );
+    columns.utf8(
+        "tool_binary_path",
+        a.iter().map(|p| p.tool_binary_path.as_str()),
+    );
+    columns.utf8("tool_cmd", a.iter().map(|p| p.tool_cmd.as_str()));
+    columns.utf8(
+        "start_timestamp",
+        a.iter().map(|p| p.start_timestamp.as_str()),
+    );
+    columns.f32(
+        "process_cpu_utilization",
+        a.iter().map(|p| p.process_cpu_utilization),
+    );
+    columns.u64(
+        "process_memory_usage",
+        a.iter().map(|p| p.process_memory_usage),
+    );
+    columns.u64(
+        "process_memory_virtual",
+        a.iter().map(|p| p.process_memory_virtual),
+    );
+    columns.u64("process_run_time", a.iter().map(|p| p.process_run_time));
+    columns.u64(
+        "process_disk_usage_read_last_interval",
+        a.iter().map(|p| p.process_disk_usage_read_last_interval),
+    );
+    columns.u64(
+        "process_disk_usage_write_last_interval",
+        a.iter().map(|p| p.process_disk_usage_write_last_interval),
+    );
+    columns.u64(
+        "process_disk_usage_read_total",
+        a.iter().map(|p| p.process_disk_usage_read_total),
+    );
+    columns.u64(
+        "process_disk_usage_write_total",
+        a.iter().map(|p| p.process_disk_usage_write_total),
+    );
+    columns.utf8(
+        "process_status",
+        a.iter().map(|p| p.process_status.as_str()),
+    );
+
+    let input_files = a
+        .iter()
+        .map(|p| {
+            p.input_files
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.optional_utf8("input_files", input_files.iter().map(|f| f.as_deref()));
+
+    let subtrees = a
+        .iter()
+        .map(|p| p.subtree.as_ref().map(serde_json::to_string).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.optional_utf8("subtree", subtrees.iter().map(|s| s.as_deref()));
+
+    columns.optional_utf8("cgroup", a.iter().map(|p| p.cgroup.as_deref()));
+    let container = || a.iter().map(|p| p.container.as_ref());
+    columns.optional_utf8(
+        "container_runtime",
+        container().map(|c| c.map(|c| c.runtime.as_str())),
+    );
+    columns.optional_utf8("container_id", container().map(|c| c?.id.as_deref()));
+    columns.optional_utf8("container_image", container().map(|c| c?.image.as_deref()));
+    let labels = container()
+        .map(|c| c.map(|c| serde_json::to_string(&c.labels)).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.optional_utf8("container_labels", labels.iter().map(|l| l.as_deref()));
+
+    let details = || a.iter().map(|p| p.details.as_ref());
+    columns.optional_u64("threads", details().map(|d| d.map(|d| d.threads)));
+    columns.optional_u64("open_fds", details().map(|d| d?.open_fds));
+    columns.optional_u64("fd_limit", details().map(|d| d?.fd_limit));
+    columns.optional_u64(
+        "voluntary_context_switches",
+        details().map(|d| d.map(|d| d.voluntary_context_switches)),
+    );
+    columns.optional_u64(
+        "involuntary_context_switches",
+        details().map(|d| d.map(|d| d.involuntary_context_switches)),
+    );
+    columns.optional_f64(
+        "blkio_delay_sec",
+        details().map(|d| d.map(|d| d.blkio_delay_sec)),
+    );
+    let io = || details().map(|d| d.and_then(|d| d.io.as_ref()));
+    columns.optional_u64("io_read_chars", io().map(|io| io.map(|io| io.read_chars)));
+    columns.optional_u64("io_write_chars", io().map(|io| io.map(|io| io.write_chars)));
+    columns.optional_u64(
+        "io_read_syscalls",
+        io().map(|io| io.map(|io| io.read_syscalls)),
+    );
+    columns.optional_u64(
+        "io_write_syscalls",
+        io().map(|io| io.map(|io| io.write_syscalls)),
+    );
+    Ok(())
+}
+
+fn system_metric_columns(columns: &mut Columns, attributes: &[&SystemMetric]) -> Result<()> {
+    let a = attributes;
+    columns.utf8("events_name", a.iter().map(|m| m.events_name.as_str()));
+    columns.u64(
+        "system_memory_total",
+        a.iter().map(|m| m.system_memory_total),
+    );
+    columns.u64("system_memory_used", a.iter().map(|m| m.system_memory_used));
+    columns.u64(
+        "system_memory_available",
+        a.iter().map(|m| m.system_memory_available),
+    );
+    columns.f64(
+        "system_memory_utilization",
+        a.iter().map(|m| m.system_memory_utilization),
+    );
+    columns.u64(
+        "system_memory_swap_total",
+        a.iter().map(|m| m.system_memory_swap_total),
+    );
+    columns.u64(
+        "system_memory_swap_used",
+        a.iter().map(|m| m.system_memory_swap_used),
+    );
+    columns.f32(
+        "system_cpu_utilization",
+        a.iter().map(|m| m.system_cpu_utilization),
+    );
+    columns.f64("system_cpu_iowait", a.iter().map(|m| m.system_cpu_iowait));
+    columns.f64("system_cpu_steal", a.iter().map(|m| m.system_cpu_steal));
+    let cpu_cores = a
+        .iter()
+        .map(|m| serde_json::to_string(&m.system_cpu_cores))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("system_cpu_cores", cpu_cores.iter().map(String::as_str));
+    columns.u64(
+        "system_disk_total_space",
+        a.iter().map(|m| {
+            m.system_disk_io
+                .values()
+                .map(|disk| disk.disk_total_space)
+                .sum()
+        }),
+    );
+    columns.u64(
+        "system_disk_used_space",
+        a.iter().map(|m| {
+            m.system_disk_io
+                .values()
+                .map(|disk| disk.disk_used_space)
+                .sum()
+        }),
+    );
+
+    let disk_io = a
+        .iter()
+        .map(|m| serde_json::to_string(&m.system_disk_io))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("system_disk_io", disk_io.iter().map(String::as_str));
+    let device_io = a
+        .iter()
+        .map(|m| serde_json::to_string(&m.system_device_io))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("system_device_io", device_io.iter().map(String::as_str));
+    let network_io = a
+        .iter()
+        .map(|m| serde_json::to_string(&m.system_network_io))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("system_network_io", network_io.iter().map(String::as_str));
+
+    columns.f64(
+        "system_load_average_1m",
+        a.iter().map(|m| m.system_load_average_1m),
+    );
+    columns.f64(
+        "system_load_average_5m",
+        a.iter().map(|m| m.system_load_average_5m),
+    );
+    columns.f64(
+        "system_load_average_15m",
+        a.iter().map(|m| m.system_load_average_15m),
+    );
+    columns.u64(
+        "system_context_switches_total",
+        a.iter().map(|m| m.system_context_switches_total),
+    );
+    columns.u64(
+        "system_procs_running",
+        a.iter().map(|m| m.system_procs_running),
+    );
+    columns.u64(
+        "system_procs_blocked",
+        a.iter().map(|m| m.system_procs_blocked),
+    );
+    columns.u64(
+        "system_major_faults_total",
+        a.iter().map(|m| m.system_major_faults_total),
+    );
+    // the short-term averages as columns, everything else in the JSON
+    for (index, resource) in SystemPressure::RESOURCES.iter().enumerate() {
+        let stalls = || a.iter().map(|m| m.system_pressure.stalls()[index]);
+        columns.optional_f64(
+            &format!("system_pressure_{resource}_some_avg10"),
+            stalls().map(|s| s.map(|s| s.some_avg10)),
+        );
+        columns.optional_f64(
+            &format!("system_pressure_{resource}_full_avg10"),
+            stalls().map(|s| s.map(|s| s.full_avg10)),
+        );
+    }
+    let pressure = a
+        .iter()
+        .map(|m| serde_json::to_string(&m.system_pressure))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("system_pressure", pressure.iter().map(String::as_str));
+    Ok(())
+}
+
+fn syslog_columns(columns: &mut Columns, attributes: &[&SyslogProperties]) {
+    let a = attributes;
+    columns.utf8(
+        "error_display_name",
+        a.iter().map(|s| s.error_display_name.as_str()),
+    );
+    columns.utf8("error_id", a.iter().map(|s| s.error_id.as_str()));
+    columns.utf8("error_line", a.iter().map(|s| s.error_line.as_str()));
+    columns.u64("file_line_number", a.iter().map(|s| s.file_line_number));
+    columns.string_list(
+        "file_previous_logs",
+        a.iter().map(|s| &s.file_previous_logs),
+    );
+    columns.f64(
+        "system_memory_utilization",
+        a.iter().map(|s| s.system_metrics.system_memory_utilization),
+    );
+    columns.f32(
+        "system_cpu_utilization",
+        a.iter().map(|s| s.system_metrics.system_cpu_utilization),
+    );
+}
+
+fn completed_process_columns(columns: &mut Columns, attributes: &[&CompletedProcess]) {
+    let a = attributes;
+    columns.utf8("tool_name", a.iter().map(|p| p.tool_name.as_str()));
+    columns.utf8("tool_pid", a.iter().map(|p| p.tool_pid.as_str()));
+    columns.u64("duration_sec", a.iter().map(|p| p.duration_sec));
+    columns.optional_i32("exit_code", a.iter().map(|p| p.exit_code));
+    columns.optional_i32("signal", a.iter().map(|p| p.signal));
+    columns.bool("oom_killed", a.iter().map(|p| p.oom_killed));
+    let usage = || a.iter().map(|p| &p.resource_usage);
+    columns.u64("peak_memory_usage", usage().map(|u| u.peak_memory_usage));
+    columns.u64(
+        "peak_memory_virtual",
+        usage().map(|u| u.peak_memory_virtual),
+    );
+    columns.f64("cpu_time_sec", usage().map(|u| u.cpu_time_sec));
+    columns.f64(
+        "average_cpu_utilization",
+        usage().map(|u| u.average_cpu_utilization),
+    );
+    columns.u64(
+        "disk_usage_read_total",
+        usage().map(|u| u.disk_usage_read_total),
+    );
+    columns.u64(
+        "disk_usage_write_total",
+        usage().map(|u| u.disk_usage_write_total),
+    );
+    columns.string_list("input_paths", a.iter().map(|p| &p.input_paths));
+    columns.string_list("output_paths", a.iter().map(|p| &p.output_paths));
+}
+
+/// The graph itself is kept as JSON, one row per run.
+fn run_lineage_columns(columns: &mut Columns, attributes: &[&RunLineage]) -> Result<()> {
+    let a = attributes;
+    columns.u64("tool_count", a.iter().map(|l| l.tool_count() as u64));
+    columns.u64("file_count", a.iter().map(|l| l.file_count() as u64));
+    let nodes = a
+        .iter()
+        .map(|l| serde_json::to_string(&l.nodes))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("nodes", nodes.iter().map(String::as_str));
+    let edges = a
+        .iter()
+        .map(|l| serde_json::to_string(&l.edges))
+        .collect::<Result<Vec<_>, _>>()?;
+    columns.utf8("edges", edges.iter().map(String::as_str));
+    Ok(())
+}
+
+fn cgroup_usage_columns(columns: &mut Columns, attributes: &[&CgroupUsage]) {
+    let a = attributes;
+    columns.utf8("cgroup", a.iter().map(|u| u.cgroup.as_str()));
+    columns.utf8("scope", a.iter().map(|u| u.scope.as_str()));
+    columns.string_list("tools", a.iter().map(|u| &u.tools));
+    columns.optional_u64("memory_current", a.iter().map(|u| u.memory_current));
+    columns.optional_u64("memory_peak", a.iter().map(|u| u.memory_peak));
+    columns.optional_u64("memory_max", a.iter().map(|u| u.memory_max));
+    columns.u64("memory_anon", a.iter().map(|u| u.memory_anon));
+    columns.u64("memory_file", a.iter().map(|u| u.memory_file));
+    columns.u64("memory_kernel", a.iter().map(|u| u.memory_kernel));
+    columns.u64("memory_max_events", a.iter().map(|u| u.memory_max_events));
+    columns.u64("memory_oom_events", a.iter().map(|u| u.memory_oom_events));
+    columns.u64(
+        "memory_oom_kill_events",
+        a.iter().map(|u| u.memory_oom_kill_events),
+    );
+    columns.u64("cpu_usage_usec", a.iter().map(|u| u.cpu_usage_usec));
+    columns.u64("cpu_user_usec", a.iter().map(|u| u.cpu_user_usec));
+    columns.u64("cpu_system_usec", a.iter().map(|u| u.cpu_system_usec));
+    columns.u64("cpu_periods", a.iter().map(|u| u.cpu_periods));
+    columns.u64(
+        "cpu_throttled_periods",
+        a.iter().map(|u| u.cpu_throttled_periods),
+    );
+    columns.u64("cpu_throttled_usec", a.iter().map(|u| u.cpu_throttled_usec));
+    columns.u64("io_read_bytes", a.iter().map(|u| u.io_read_bytes));
+    columns.u64("io_write_bytes", a.iter().map(|u| u.io_write_bytes));
+    columns.u64("io_read_ops", a.iter().map(|u| u.io_read_ops));
+    columns.u64("io_write_ops", a.iter().map(|u| u.io_write_ops));
+    columns.bool("memory_limit_hit", a.iter().map(|u| u.memory_limit_hit));
+    columns.bool("cpu_throttled", a.iter().map(|u| u.cpu_throttled));
+}
+
+/// Flattens events of a single family into a record batch.
+fn record_batch(family: EventFamily, events: &[&Event]) -> Result<RecordBatch> {
+    let mut columns = event_columns(events);
+    let attributes = events.iter().filter_map(|event| event.attributes.as_ref());
+
+    match family {
+        EventFamily::Process => {
+            let attributes: Vec<_> = attributes
+                .filter_map(|attributes| match attributes {
+                    EventAttributes::Process(process) => Some(process),
+                    _ => None,
+                })
+                .collect();
+            process_columns(&mut columns, &attributes)?;
+        }
+        EventFamily::SystemMetric => {
+            let attributes: Vec<_> = attributes
+                .filter_map(|attributes| match attributes {
+                    EventAttributes::SystemMetric(metric) => Some(metric),
+                    _ => None,
+                })
+                .collect();
+            system_metric_columns(&mut columns, &attributes)?;
+        }
+        EventFamily::Syslog => {
+            let attributes: Vec<_> = attributes
+                .filter_map(|attributes| match attributes {
+                    EventAttributes::Syslog(syslog) => Some(syslog),
+                    _ => None,
+                })
+                .collect();
+            syslog_columns(&mut columns, &attributes);
+        }
+        EventFamily::CompletedProcess => {
+            let attributes: Vec<_> = attributes
+                .filter_map(|attributes| match attributes {
+                    EventAttributes::CompletedProcess(process) => Some(process),
+                    _ => None,
+                })
+                .collect();
+            completed_process_columns(&mut columns, &attributes);
+        }
+        EventFamily::CgroupUsage => {
+            let attributes: Vec<_> = attributes
+                .filter_map(|attributes| match attributes {
+                    EventAttributes::CgroupUsage(usage) => Some(usage),
+                    _ => None,
+                })
+                .collect();
+            cgroup_usage_columns(&mut columns, &attributes);
+        }
+        EventFamily::RunLineage => {
+            let attributes: Vec<_> = attributes
+                .filter_map(|attributes| match attributes {
+                    EventAttributes::RunLineage(lineage) => Some(lineage),
+                    _ => None,
+                })
+                .collect();
+            run_lineage_columns(&mut columns, &attributes)?;
+        }
+    }
+
+    columns.into_record_batch()
+}
+
+struct OpenFile {
+    path: PathBuf,
+    writer: ArrowWriter<File>,
+}
+
+pub struct ParquetExporter {
+    dir: PathBuf,
+    files: Mutex<HashMap<(String, EventFamily), OpenFile>>,
+}
+
+impl ParquetExporter {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create parquet export directory: {:?}", dir))?;
+
+        Ok(ParquetExporter {
+            dir,
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Path of the finished file of `family` for `run_id`.
+    pub fn file_path(&self, run_id: &str, family: EventFamily) -> PathBuf {
+        self.part_path(run_id, family, 0)
+    }
+
+    /// Events arriving after their run's file was finished, like replayed spool batches, go to
+    /// further parts `<family>-<n>.parquet` instead of overwriting it.
+    fn part_path(&self, run_id: &str, family: EventFamily, part: usize) -> PathBuf {
+        let name = match part {
+            0 => format!("{}.parquet", family.as_str()),
+            part => format!("{}-{part}.parquet", family.as_str()),
+        };
+        self.dir.join(sanitize_path_component(run_id)).join(name)
+    }
+
+    fn open_file(
+        &self,
+        run_id: &str,
+        family: EventFamily,
+        batch: &RecordBatch,
+    ) -> Result<OpenFile> {
+        let path = (0..)
+            .map(|part| self.part_path(run_id, family, part))
+            .find(|path| !path.exists() && !in_progress_path(path).exists())
+            .expect("unbounded part numbers");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let in_progress_path = in_progress_path(&path);
+        let file = File::create(&in_progress_path)
+            .with_context(|| format!("Failed to create {:?}", in_progress_path))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(file, batch.schema(), Some(properties))?;
+
+        Ok(OpenFile { path, writer })
+    }
+
+    fn write(&self, run_id: &str, family: EventFamily, events: &[&Event]) -> Result<()> {
+        let batch = record_batch(family, events)?;
+        let mut files = self.files.lock().unwrap();
+
+        let key = (run_id.to_string(), family);
+        let file = match files.get_mut(&key) {
+            Some(file) => file,
+            None => {
+                let file = self.open_file(run_id, family, &batch)?;
+                files.entry(key).or_insert(file)
+            }
+        };
+        file.writer
+            .write(&batch)
+            .with_context(|| format!("Failed to write to {:?}", file.path))?;
+        Ok(())
+    }
+
+    /// Finishes the files of `run_id`, or of every run if `run_id` is `None`.
+    fn finish_run(&self, run_id: Option<&str>) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let keys: Vec<_> = files
+            .keys()
+            .filter(|(id, _)| run_id.map_or(true, |run_id| id == run_id))
+            .cloned()
+            .collect();
+
+        for key in keys {
+            let file = files.remove(&key).unwrap();
+            file.writer
+                .close()
+                .with_context(|| format!("Failed to finish {:?}", file.path))?;
+            fs::rename(in_progress_path(&file.path), &file.path)?;
+        }
+        Ok(())
+    }
+}
+
+fn in_progress_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(IN_PROGRESS_SUFFIX);
+    PathBuf::from(path)
+}
+
+#[async_trait]
+impl EventExporter for ParquetExporter {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    async fn export(&self, batch: &EventBatch) -> Result<()> {
+        let mut groups: Vec<((&str, EventFamily), Vec<&Event>)> = Vec::new();
+        let mut finished_runs = Vec::new();
+
+        for event in &batch.events {
+            let run_id = event.run_id.as_deref().unwrap_or(UNKNOWN_RUN);
+            if event.process_status == EventType::FinishedRun.as_str() {
+                finished_runs.push(run_id);
+            }
+
+            let Some(family) = EventFamily::of(event) else {
+                continue;
+            };
+            match groups.iter_mut().find(|(key, _)| *key == (run_id, family)) {
+                Some((_, events)) => events.push(event),
+                None => groups.push(((run_id, family), vec![event])),
+            }
+        }
+
+        for ((run_id, family), events) in groups {
+            self.write(run_id, family, &events)?;
+        }
+        for run_id in finished_runs {
+            self.finish_run(Some(run_id))?;
+        }
+        Ok(())
+    }
+
+    /// Writes buffered rows of every open file out as row groups.
+    async fn flush(&self) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        for file in files.values_mut() {
+            file.writer
+                .flush()
+                .with_context(|| format!("Failed to flush {:?}", file.path))?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.finish_run(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+    use crate::types::event::attributes::lineage::{LineageNode, LineageNodeKind};
+    use crate::types::event::attributes::process::ContainerInfo;
+    use crate::types::event::attributes::system_metrics::DiskStatistic;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, UInt64Type};
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::collections::BTreeMap;
+    use tempfile::tempdir;
+
+    fn recorder(run_id: &str) -> EventRecorder {
+        EventRecorder::new(
+            Some("pipeline".to_string()),
+            Some("run-name".to_string()),
+            Some(run_id.to_string()),
+        )
+    }
+
+    fn system_metric() -> SystemMetric {
+        SystemMetric {
+            events_name: "global_system_metrics".to_string(),
+            system_memory_total: 100,
+            system_memory_used: 40,
+            system_memory_available: 60,
+            system_memory_utilization: 40.0,
+            system_memory_swap_total: 0,
+            system_memory_swap_used: 0,
+            system_cpu_utilization: 12.5,
+            system_disk_io: HashMap::from([(
+                "/dev/sda".to_string(),
+                DiskStatistic {
+                    disk_total_space: 1000,
+                    disk_used_space: 250,
+                    disk_available_space: 750,
+                    disk_utilization: 25.0,
+                },
+            )]),
+            system_load_average_1m: 3.5,
+            ..Default::default()
+        }
+    }
+
+    fn process_properties(container: Option<ContainerInfo>) -> ProcessProperties {
+        ProcessProperties {
+            tool_name: "STAR".to_string(),
+            tool_pid: "42".to_string(),
+            tool_parent_pid: "1".to_string(),
+            tool_binary_path: "/usr/bin/STAR".to_string(),
+            tool_cmd: "STAR --runThreadN 8".to_string(),
+            start_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            process_cpu_utilization: 80.0,
+            process_memory_usage: 1 << 30,
+            process_memory_virtual: 1 << 31,
+            process_run_time: 5,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 0,
+            process_disk_usage_write_total: 0,
+            process_status: "Run".to_string(),
+            input_files: None,
+            subtree: None,
+            cgroup: container
+                .as_ref()
+                .map(|_| "/system.slice/docker-abc123.scope".to_string()),
+            container,
+            details: None,
+        }
+    }
+
+    fn read_batches(path: &Path) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_one_file_per_run_and_family() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = ParquetExporter::new(dir.path())?;
+
+        let mut events = recorder("run-1");
+        for _ in 0..2 {
+            events.record_event(
+                EventType::MetricEvent,
+                "metrics".to_string(),
+                Some(EventAttributes::SystemMetric(system_metric())),
+                None,
+            );
+        }
+        events.record_event(
+            EventType::ToolExecution,
+            "started".to_string(),
+            Some(EventAttributes::Process(process_properties(Some(
+                ContainerInfo {
+                    runtime: "docker".to_string(),
+                    id: Some("abc123".to_string()),
+                    image: Some("quay.io/biocontainers/star:2.7.11b".to_string()),
+                    labels: BTreeMap::from([("stage".to_string(), "align".to_string())]),
+                },
+            )))),
+            None,
+        );
+        events.record_event(
+            EventType::ToolMetricEvent,
+            "sample".to_string(),
+            Some(EventAttributes::Process(process_properties(None))),
+            None,
+        );
+        events.record_event(
+            EventType::FinishedToolExecution,
+            "done".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "STAR".to_string(),
+                tool_pid: "42".to_string(),
+                duration_sec: 7,
+                exit_code: Some(0),
+                signal: None,
+                oom_killed: false,
+                resource_usage: Default::default(),
+                input_paths: vec!["/data/sample_R1.fq.gz".to_string()],
+                output_paths: vec![
+                    "/results/Aligned.out.bam".to_string(),
+                    "/results/Log.out".to_string(),
+                ],
+            })),
+            None,
+        );
+        events.record_event(
+            EventType::MetricEvent,
+            "cgroup".to_string(),
+            Some(EventAttributes::CgroupUsage(CgroupUsage {
+                cgroup: "/kubepods/burstable/pod1".to_string(),
+                memory_max: Some(1 << 30),
+                memory_limit_hit: true,
+                ..Default::default()
+            })),
+            None,
+        );
+        events.record_event(
+            EventType::RunLineage,
+            "lineage".to_string(),
+            Some(EventAttributes::RunLineage(RunLineage {
+                run_id: "run-1".to_string(),
+                nodes: vec![LineageNode {
+                    id: "tool:42".to_string(),
+                    kind: LineageNodeKind::Tool,
+                    label: "STAR".to_string(),
+                    tool_pid: Some("42".to_string()),
+                    tool_cmd: None,
+                    exit_code: Some(0),
+                }],
+                edges: vec![],
+            })),
+            None,
+        );
+        let batch = EventBatch {
+            job_id: "run-name".to_string(),
+            events: events.get_events().to_vec(),
+        };
+
+        // two exports end up in the same file
+        exporter.export(&batch).await?;
+        exporter.export(&batch).await?;
+
+        let metrics_path = exporter.file_path("run-1", EventFamily::SystemMetric);
+        assert!(!metrics_path.exists());
+        exporter.close().await?;
+
+        let batches = read_batches(&metrics_path);
+        let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(rows, 4);
+        let used = batches[0]
+            .column_by_name("system_disk_used_space")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(used.value(0), 250);
+        let load = batches[0]
+            .column_by_name("system_load_average_1m")
+            .unwrap()
+            .as_primitive::<Float64Type>();
+        assert_eq!(load.value(0), 3.5);
+        let io_pressure = batches[0]
+            .column_by_name("system_pressure_io_some_avg10")
+            .unwrap();
+        assert!(io_pressure.is_null(0));
+
+        let completed = read_batches(&exporter.file_path("run-1", EventFamily::CompletedProcess));
+        let tool_name = completed[0]
+            .column_by_name("tool_name")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(tool_name.value(0), "STAR");
+        let output_paths = completed[0]
+            .column_by_name("output_paths")
+            .unwrap()
+            .as_list::<i32>();
+        assert_eq!(output_paths.value(0).len(), 2);
+
+        let processes = read_batches(&exporter.file_path("run-1", EventFamily::Process));
+        let image = processes[0]
+            .column_by_name("container_image")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(image.value(0), "quay.io/biocontainers/star:2.7.11b");
+        assert!(image.is_null(1));
+        let labels = processes[0]
+            .column_by_name("container_labels")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(labels.value(0), r#"{"stage":"align"}"#);
+        let cgroup = processes[0].column_by_name("cgroup").unwrap();
+        assert!(cgroup.is_null(1));
+
+        let lineage = read_batches(&exporter.file_path("run-1", EventFamily::RunLineage));
+        let tool_count = lineage[0]
+            .column_by_name("tool_count")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(tool_count.value(0), 1);
+
+        let cgroups = read_batches(&exporter.file_path("run-1", EventFamily::CgroupUsage));
+        let memory_peak = cgroups[0].column_by_name("memory_peak").unwrap();
+        assert!(memory_peak.is_null(0));
+        let memory_max = cgroups[0]
+            .column_by_name("memory_max")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(memory_max.value(0), 1 << 30);
+
+        assert!(!exporter.file_path("run-1", EventFamily::Syslog).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_files_are_finished_when_the_run_ends() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = ParquetExporter::new(dir.path())?;
+
+        let mut events = recorder("run-2");
+        events.record_event(
+            EventType::MetricEvent,
+            "metrics".to_string(),
+            Some(EventAttributes::SystemMetric(system_metric())),
+            None,
+        );
+        events.record_event(EventType::FinishedRun, "end".to_string(), None, None);
+
+        exporter
+            .export(&EventBatch {
+                job_id: "run-name".to_string(),
+                events: events.get_events().to_vec(),
+            })
+            .await?;
+
+        let path = exporter.file_path("run-2", EventFamily::SystemMetric);
+        assert!(path.exists());
+        assert!(!in_progress_path(&path).exists());
+        assert_eq!(read_batches(&path)[0].num_rows(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_late_events_go_to_a_new_part() -> Result<()> {
+        let dir = tempdir()?;
+        let exporter = ParquetExporter::new(dir.path())?;
+
+        let mut events = recorder("run-3");
+        for _ in 0..2 {
+            events.record_event(
+                EventType::MetricEvent,
+                "metrics".to_string(),
+                Some(EventAttributes::SystemMetric(system_metric())),
+                None,
+            );
+        }
+        events.record_event(EventType::FinishedRun, "end".to_string(), None, None);
+        let batch = EventBatch {
+            job_id: "run-name".to_string(),
+            events: events.get_events().to_vec(),
+        };
+        exporter.export(&batch).await?;
+
+        // a replay of the last batch, after the run ended
+        exporter
+            .export(&EventBatch {
+                job_id: "run-name".to_string(),
+                events: batch.events[1..].to_vec(),
+            })
+            .await?;
+
+        let first = exporter.file_path("run-3", EventFamily::SystemMetric);
+        assert_eq!(read_batches(&first)[0].num_rows(), 2);
+        let late = exporter.part_path("run-3", EventFamily::SystemMetric, 1);
+        assert!(!in_progress_path(&late).exists());
+        assert_eq!(read_batches(&late)[0].num_rows(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,778 @@
+// src/exporters/prometheus.rs
+//! Latest host and tool metrics plus event counters, rendered in the Prometheus text format.
+//!
+//! The registry is fed every batch the daemon submits, so scraped values are at most one batch
+//! interval old. Tool series disappear once their tool finished, host and tool series of a run
+//! disappear once the run finished.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    events::recorder::EventType,
+    types::event::{
+        attributes::{
+            process::{ProcessDetails, ProcessProperties},
+            system_metrics::{
+                CpuCoreUsage, DeviceIo, NetworkInterfaceIo, PressureStall, SystemMetric,
+                SystemPressure,
+            },
+            EventAttributes,
+        },
+        Event,
+    },
+};
+
+use super::EventBatch;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Pipeline and run an event belongs to.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RunKey {
+    pipeline: String,
+    run: String,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ToolKey {
+    run: RunKey,
+    tool: String,
+    pid: String,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    system: BTreeMap<RunKey, SystemMetric>,
+    tools: BTreeMap<ToolKey, ProcessProperties>,
+    events_emitted: BTreeMap<(String, String), u64>,
+    export_failures: BTreeMap<String, u64>,
+}
+
+/// Clones share the same state, so the daemon loop can update the registry while the metrics
+/// endpoint renders it.
+#[derive(Clone, Default)]
+pub struct PrometheusMetrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl PrometheusMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe_batch(&self, batch: &EventBatch) {
+        let mut state = self.state.lock().unwrap();
+        for event in &batch.events {
+            state.observe_event(event);
+        }
+    }
+
+    /// Replaces the export failure counters with the totals kept by the exporters.
+    pub fn set_export_failures<'a>(&self, failures: impl IntoIterator<Item = (&'a str, u64)>) {
+        let mut state = self.state.lock().unwrap();
+        for (exporter, count) in failures {
+            state.export_failures.insert(exporter.to_string(), count);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        let system = |name, help, value: fn(&SystemMetric) -> f64| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .system
+                .iter()
+                .map(|(run, metric)| (run.labels(), value(metric)))
+                .collect(),
+        };
+        system(
+            "tracer_system_cpu_utilization_percent",
+            "CPU utilization of the host",
+            |metric| metric.system_cpu_utilization as f64,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_cpu_iowait_percent",
+            "Share of CPU time the host was idle with IO outstanding",
+            |metric| metric.system_cpu_iowait,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_cpu_steal_percent",
+            "Share of CPU time the hypervisor gave to other guests",
+            |metric| metric.system_cpu_steal,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_memory_total_bytes",
+            "Total memory of the host",
+            |metric| metric.system_memory_total as f64,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_memory_used_bytes",
+            "Memory in use on the host",
+            |metric| metric.system_memory_used as f64,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_memory_available_bytes",
+            "Memory available on the host",
+            |metric| metric.system_memory_available as f64,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_memory_utilization_percent",
+            "Memory utilization of the host",
+            |metric| metric.system_memory_utilization,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_swap_total_bytes",
+            "Total swap of the host",
+            |metric| metric.system_memory_swap_total as f64,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_swap_used_bytes",
+            "Swap in use on the host",
+            |metric| metric.system_memory_swap_used as f64,
+        )
+        .write(&mut out);
+
+        system(
+            "tracer_system_load_average_1m",
+            "Load average of the host over 1 minute",
+            |metric| metric.system_load_average_1m,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_load_average_5m",
+            "Load average of the host over 5 minutes",
+            |metric| metric.system_load_average_5m,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_load_average_15m",
+            "Load average of the host over 15 minutes",
+            |metric| metric.system_load_average_15m,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_procs_running",
+            "Runnable tasks on the host",
+            |metric| metric.system_procs_running as f64,
+        )
+        .write(&mut out);
+        system(
+            "tracer_system_procs_blocked",
+            "Tasks on the host waiting for IO",
+            |metric| metric.system_procs_blocked as f64,
+        )
+        .write(&mut out);
+        Family {
+            kind: "counter",
+            ..system(
+                "tracer_system_context_switches_total",
+                "Context switches on the host since boot",
+                |metric| metric.system_context_switches_total as f64,
+            )
+        }
+        .write(&mut out);
+        Family {
+            kind: "counter",
+            ..system(
+                "tracer_system_major_faults_total",
+                "Major page faults on the host since boot",
+                |metric| metric.system_major_faults_total as f64,
+            )
+        }
+        .write(&mut out);
+
+        let pressure = |name, help, kind, value: fn(&PressureStall) -> f64| Family {
+            name,
+            help,
+            kind,
+            samples: state
+                .system
+                .iter()
+                .flat_map(|(run, metric)| {
+                    SystemPressure::RESOURCES
+                        .iter()
+                        .zip(metric.system_pressure.stalls())
+                        .filter_map(move |(resource, stall)| {
+                            let mut labels = run.labels();
+                            labels.push(("resource", resource.to_string()));
+                            Some((labels, value(stall?)))
+                        })
+                })
+                .collect(),
+        };
+        pressure(
+            "tracer_system_pressure_some_avg10_percent",
+            "Share of the last 10 seconds some tasks were stalled on the resource",
+            "gauge",
+            |stall| stall.some_avg10,
+        )
+        .write(&mut out);
+        pressure(
+            "tracer_system_pressure_full_avg10_percent",
+            "Share of the last 10 seconds all non-idle tasks were stalled on the resource",
+            "gauge",
+            |stall| stall.full_avg10,
+        )
+        .write(&mut out);
+        pressure(
+            "tracer_system_pressure_some_seconds_total",
+            "Time some tasks were stalled on the resource since boot",
+            "counter",
+            |stall| stall.some_total_usec as f64 / 1e6,
+        )
+        .write(&mut out);
+        pressure(
+            "tracer_system_pressure_full_seconds_total",
+            "Time all non-idle tasks were stalled on the resource since boot",
+            "counter",
+            |stall| stall.full_total_usec as f64 / 1e6,
+        )
+        .write(&mut out);
+
+        let disks = |name, help, value: fn(u64, u64) -> u64| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .system
+                .iter()
+                .flat_map(|(run, metric)| {
+                    metric.system_disk_io.iter().map(move |(disk, stats)| {
+                        let mut labels = run.labels();
+                        labels.push(("disk", disk.clone()));
+                        let value = value(stats.disk_total_space, stats.disk_used_space);
+                        (labels, value as f64)
+                    })
+                })
+                .collect(),
+        };
+        disks(
+            "tracer_system_disk_total_bytes",
+            "Size of the disk",
+            |total, _| total,
+        )
+        .write(&mut out);
+        disks(
+            "tracer_system_disk_used_bytes",
+            "Space used on the disk",
+            |_, used| used,
+        )
+        .write(&mut out);
+
+        let cores = |name, help, value: fn(&CpuCoreUsage) -> Option<f64>| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .system
+                .iter()
+                .flat_map(|(run, metric)| {
+                    metric.system_cpu_cores.iter().filter_map(move |core| {
+                        let mut labels = run.labels();
+                        labels.push(("core", core.core.to_string()));
+                        Some((labels, value(core)?))
+                    })
+                })
+                .collect(),
+        };
+        cores(
+            "tracer_system_cpu_core_utilization_percent",
+            "CPU utilization of the core",
+            |core| Some(core.utilization),
+        )
+        .write(&mut out);
+        cores(
+            "tracer_system_cpu_core_iowait_percent",
+            "Share of the core's time idle with IO outstanding",
+            |core| Some(core.iowait),
+        )
+        .write(&mut out);
+        cores(
+            "tracer_system_cpu_core_steal_percent",
+            "Share of the core's time the hypervisor gave to other guests",
+            |core| Some(core.steal),
+        )
+        .write(&mut out);
+        cores(
+            "tracer_system_cpu_core_frequency_mhz",
+            "Current frequency of the core",
+            |core| core.frequency_mhz,
+        )
+        .write(&mut out);
+
+        let devices = |name, help, value: fn(&DeviceIo) -> f64| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .system
+                .iter()
+                .flat_map(|(run, metric)| {
+                    metric.system_device_io.iter().map(move |(device, io)| {
+                        let mut labels = run.labels();
+                        labels.push(("device", device.clone()));
+                        (labels, value(io))
+                    })
+                })
+                .collect(),
+        };
+        devices(
+            "tracer_system_device_read_bytes_per_second",
+            "Bytes read from the block device or NFS export",
+            |io| io.read_bytes_per_sec,
+        )
+        .write(&mut out);
+        devices(
+            "tracer_system_device_written_bytes_per_second",
+            "Bytes written to the block device or NFS export",
+            |io| io.write_bytes_per_sec,
+        )
+        .write(&mut out);
+        devices(
+            "tracer_system_device_reads_per_second",
+            "Read operations on the block device or NFS export",
+            |io| io.read_ops_per_sec,
+        )
+        .write(&mut out);
+        devices(
+            "tracer_system_device_writes_per_second",
+            "Write operations on the block device or NFS export",
+            |io| io.write_ops_per_sec,
+        )
+        .write(&mut out);
+
+        let interfaces = |name, help, value: fn(&NetworkInterfaceIo) -> f64| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .system
+                .iter()
+                .flat_map(|(run, metric)| {
+                    metric.system_network_io.iter().map(move |(interface, io)| {
+                        let mut labels = run.labels();
+                        labels.push(("interface", interface.clone()));
+                        (labels, value(io))
+                    })
+                })
+                .collect(),
+        };
+        interfaces(
+            "tracer_system_network_received_bytes_per_second",
+            "Bytes received on the network interface",
+            |io| io.rx_bytes_per_sec,
+        )
+        .write(&mut out);
+        interfaces(
+            "tracer_system_network_transmitted_bytes_per_second",
+            "Bytes transmitted on the network interface",
+            |io| io.tx_bytes_per_sec,
+        )
+        .write(&mut out);
+
+        let tool = |name, help, value: fn(&ProcessProperties) -> f64| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .tools
+                .iter()
+                .map(|(tool, properties)| (tool.labels(), value(properties)))
+                .collect(),
+        };
+        tool(
+            "tracer_tool_cpu_utilization_percent",
+            "CPU utilization of the tool process",
+            |properties| properties.process_cpu_utilization as f64,
+        )
+        .write(&mut out);
+        tool(
+            "tracer_tool_memory_usage_bytes",
+            "Resident memory of the tool process",
+            |properties| properties.process_memory_usage as f64,
+        )
+        .write(&mut out);
+        tool(
+            "tracer_tool_memory_virtual_bytes",
+            "Virtual memory of the tool process",
+            |properties| properties.process_memory_virtual as f64,
+        )
+        .write(&mut out);
+        tool(
+            "tracer_tool_run_time_seconds",
+            "Time the tool process has been running",
+            |properties| properties.process_run_time as f64,
+        )
+        .write(&mut out);
+        tool(
+            "tracer_tool_disk_read_bytes",
+            "Bytes read from disk by the tool process so far",
+            |properties| properties.process_disk_usage_read_total as f64,
+        )
+        .write(&mut out);
+        tool(
+            "tracer_tool_disk_written_bytes",
+            "Bytes written to disk by the tool process so far",
+            |properties| properties.process_disk_usage_write_total as f64,
+        )
+        .write(&mut out);
+
+        let details = |name, help, value: fn(&ProcessDetails) -> Option<f64>| Family {
+            name,
+            help,
+            kind: "gauge",
+            samples: state
+                .tools
+                .iter()
+                .filter_map(|(tool, properties)| {
+                    Some((tool.labels(), value(properties.details.as_ref()?)?))
+                })
+                .collect(),
+        };
+        details(
+            "tracer_tool_threads",
+            "Threads of the tool process",
+            |details| Some(details.threads as f64),
+        )
+        .write(&mut out);
+        details(
+            "tracer_tool_open_fds",
+            "File descriptors the tool process has open",
+            |details| details.open_fds.map(|fds| fds as f64),
+        )
+        .write(&mut out);
+        details(
+            "tracer_tool_fd_limit",
+            "Soft limit on the file descriptors of the tool process",
+            |details| details.fd_limit.map(|limit| limit as f64),
+        )
+        .write(&mut out);
+        Family {
+            kind: "counter",
+            ..details(
+                "tracer_tool_blkio_delay_seconds_total",
+                "Time the tool process waited for block IO",
+                |details| Some(details.blkio_delay_sec),
+            )
+        }
+        .write(&mut out);
+
+        Family {
+            name: "tracer_events_emitted_total",
+            help: "Events recorded by the daemon",
+            kind: "counter",
+            samples: state
+                .events_emitted
+                .iter()
+                .map(|((pipeline, event_type), count)| {
+                    (
+                        vec![
+                            ("pipeline", pipeline.clone()),
+                            ("event_type", event_type.clone()),
+                        ],
+                        *count as f64,
+                    )
+                })
+                .collect(),
+        }
+        .write(&mut out);
+        Family {
+            name: "tracer_export_failures_total",
+            help: "Batches an exporter failed to accept",
+            kind: "counter",
+            samples: state
+                .export_failures
+                .iter()
+                .map(|(exporter, count)| (vec![("exporter", exporter.clone())], *count as f64))
+                .collect(),
+        }
+        .write(&mut out);
+
+        out
+    }
+}
+
+impl MetricsState {
+    fn observe_event(&mut self, event: &Event) {
+        let run = RunKey {
+            pipeline: event.pipeline_name.clone().unwrap_or_default(),
+            run: event.run_name.clone().unwrap_or_default(),
+        };
+        let status = event.process_status.as_str();
+
+        *self
+            .events_emitted
+            .entry((run.pipeline.clone(), status.to_string()))
+            .or_default() += 1;
+
+        match &event.attributes {
+            Some(EventAttributes::SystemMetric(metric)) => {
+                self.system.insert(run, metric.clone());
+            }
+            Some(EventAttributes::Process(properties))
+                if status == EventType::ToolExecution.as_str()
+                    || status == EventType::ToolMetricEvent.as_str() =>
+            {
+                let key = ToolKey {
+                    run,
+                    tool: properties.tool_name.clone(),
+                    pid: properties.tool_pid.clone(),
+                };
+                self.tools.insert(key, properties.clone());
+            }
+            Some(EventAttributes::CompletedProcess(completed)) => {
+                let key = ToolKey {
+                    run,
+                    tool: completed.tool_name.clone(),
+                    pid: completed.tool_pid.clone(),
+                };
+                self.tools.remove(&key);
+            }
+            _ if status == EventType::FinishedRun.as_str() => {
+                self.system.remove(&run);
+                self.tools.retain(|tool, _| tool.run != run);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl RunKey {
+    fn labels(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("pipeline", self.pipeline.clone()),
+            ("run", self.run.clone()),
+        ]
+    }
+}
+
+impl ToolKey {
+    fn labels(&self) -> Vec<(&'static str, String)> {
+        let mut labels = self.run.labels();
+        labels.push(("tool", self.tool.clone()));
+        labels.push(("pid", self.pid.clone()));
+        labels
+    }
+}
+
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Family {
+    fn write(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (labels, value) in &self.samples {
+            let labels = labels
+                .iter()
+                .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = writeln!(out, "{}{{{labels}}} {value}", self.name);
+        }
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        events::recorder::EventRecorder, types::event::attributes::process::CompletedProcess,
+    };
+    use std::collections::HashMap;
+
+    fn process_properties(pid: &str) -> ProcessProperties {
+        ProcessProperties {
+            tool_name: "STAR".to_string(),
+            tool_pid: pid.to_string(),
+            tool_parent_pid: "1".to_string(),
+            tool_binary_path: "/usr/bin/STAR".to_string(),
+            tool_cmd: "STAR --runThreadN 4".to_string(),
+            start_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            process_cpu_utilization: 50.0,
+            process_memory_usage: 1024,
+            process_memory_virtual: 2048,
+            process_run_time: 3,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 10,
+            process_disk_usage_write_total: 20,
+            process_status: "Run".to_string(),
+            input_files: None,
+            subtree: None,
+            cgroup: None,
+            container: None,
+            details: None,
+        }
+    }
+
+    fn system_metric() -> SystemMetric {
+        SystemMetric {
+            events_name: "global_system_metrics".to_string(),
+            system_memory_total: 4096,
+            system_memory_used: 1024,
+            system_memory_available: 3072,
+            system_memory_utilization: 25.0,
+            system_memory_swap_total: 0,
+            system_memory_swap_used: 0,
+            system_cpu_utilization: 12.5,
+            system_disk_io: HashMap::new(),
+            system_cpu_cores: vec![CpuCoreUsage {
+                core: 3,
+                utilization: 97.5,
+                frequency_mhz: None,
+                ..Default::default()
+            }],
+            system_device_io: HashMap::from([(
+                "fileserver:/references".to_string(),
+                DeviceIo {
+                    mount_points: vec!["/mnt/references".to_string()],
+                    read_bytes_per_sec: 1048576.0,
+                    ..Default::default()
+                },
+            )]),
+            system_pressure: SystemPressure {
+                io: Some(PressureStall {
+                    some_avg10: 2.5,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn recorder() -> EventRecorder {
+        EventRecorder::new(
+            Some("rnaseq".to_string()),
+            Some("brave-otter".to_string()),
+            Some("run-1".to_string()),
+        )
+    }
+
+    fn batch(recorder: &EventRecorder) -> EventBatch {
+        EventBatch {
+            job_id: "brave-otter".to_string(),
+            events: recorder.get_events().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_renders_labelled_gauges_and_counters() {
+        let metrics = PrometheusMetrics::new();
+        let mut recorder = recorder();
+        recorder.record_event(
+            EventType::MetricEvent,
+            "metrics".to_string(),
+            Some(EventAttributes::SystemMetric(system_metric())),
+            None,
+        );
+        recorder.record_event(
+            EventType::ToolMetricEvent,
+            "STAR metrics".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        metrics.observe_batch(&batch(&recorder));
+        metrics.set_export_failures([("postgres", 3)]);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE tracer_system_memory_used_bytes gauge\n"));
+        assert!(text.contains(
+            "tracer_system_cpu_utilization_percent{pipeline=\"rnaseq\",run=\"brave-otter\"} 12.5\n"
+        ));
+        assert!(text.contains(
+            "tracer_system_pressure_some_avg10_percent{pipeline=\"rnaseq\",run=\"brave-otter\",resource=\"io\"} 2.5\n"
+        ));
+        assert!(!text.contains("resource=\"cpu\""));
+        assert!(text.contains(
+            "tracer_system_cpu_core_utilization_percent{pipeline=\"rnaseq\",run=\"brave-otter\",core=\"3\"} 97.5\n"
+        ));
+        assert!(!text.contains("tracer_system_cpu_core_frequency_mhz{"));
+        assert!(text.contains(
+            "tracer_system_device_read_bytes_per_second{pipeline=\"rnaseq\",run=\"brave-otter\",device=\"fileserver:/references\"} 1048576\n"
+        ));
+        assert!(text.contains(
+            "tracer_tool_memory_usage_bytes{pipeline=\"rnaseq\",run=\"brave-otter\",tool=\"STAR\",pid=\"42\"} 1024\n"
+        ));
+        assert!(text.contains(
+            "tracer_events_emitted_total{pipeline=\"rnaseq\",event_type=\"tool_metric_event\"} 1\n"
+        ));
+        assert!(text.contains("tracer_export_failures_total{exporter=\"postgres\"} 3\n"));
+    }
+
+    #[test]
+    fn test_finished_tools_and_runs_are_removed() {
+        let metrics = PrometheusMetrics::new();
+        let mut recorder = recorder();
+        recorder.record_event(
+            EventType::ToolExecution,
+            "STAR started".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::ToolExecution,
+            "STAR started".to_string(),
+            Some(EventAttributes::Process(process_properties("43"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "STAR finished".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "STAR".to_string(),
+                tool_pid: "42".to_string(),
+                duration_sec: 3,
+                exit_code: Some(0),
+                signal: None,
+                oom_killed: false,
+                resource_usage: Default::default(),
+                input_paths: vec![],
+                output_paths: vec![],
+            })),
+            None,
+        );
+        metrics.observe_batch(&batch(&recorder));
+
+        let text = metrics.render();
+        assert!(!text.contains("pid=\"42\""));
+        assert!(text.contains("pid=\"43\""));
+
+        recorder.clear();
+        recorder.record_event(EventType::FinishedRun, "end".to_string(), None, None);
+        metrics.observe_batch(&batch(&recorder));
+        assert!(!metrics.render().contains("pid=\"43\""));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
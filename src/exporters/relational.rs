@@ -0,0 +1,716 @@
+// src/exporters/relational.rs
+//! Typed rows for the normalized tables (`runs`, `tool_executions`, `process_samples`,
+//! `system_samples`, `syslog_errors` and `files`), extracted from recorded events.
+//!
+//! Events without a run id have nothing to reference and only end up in the raw table.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, PgConnection};
+
+use crate::{
+    events::recorder::EventType,
+    types::event::{
+        attributes::{
+            process::{CompletedProcess, InputFile, ProcessProperties},
+            syslog::SyslogProperties,
+            system_metrics::{SystemMetric, SystemProperties},
+            EventAttributes,
+        },
+        Event,
+    },
+};
+
+/// A run and whatever the batch tells about it. Fields that are `None` leave the stored values
+/// untouched.
+#[derive(Debug)]
+pub struct RunRow<'a> {
+    pub run_id: &'a str,
+    pub run_name: Option<&'a str>,
+    pub pipeline_name: Option<&'a str>,
+    pub tags: &'a [String],
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub system: Option<&'a SystemProperties>,
+}
+
+#[derive(Debug)]
+pub struct ToolStartRow<'a> {
+    pub run_id: &'a str,
+    pub pipeline_name: Option<&'a str>,
+    pub started_at: DateTime<Utc>,
+    pub properties: &'a ProcessProperties,
+}
+
+#[derive(Debug)]
+pub struct ToolEndRow<'a> {
+    pub run_id: &'a str,
+    pub pipeline_name: Option<&'a str>,
+    pub finished_at: DateTime<Utc>,
+    pub completed: &'a CompletedProcess,
+}
+
+#[derive(Debug)]
+pub struct ProcessSampleRow<'a> {
+    pub run_id: &'a str,
+    pub sampled_at: DateTime<Utc>,
+    pub properties: &'a ProcessProperties,
+}
+
+#[derive(Debug)]
+pub struct SystemSampleRow<'a> {
+    pub run_id: &'a str,
+    pub sampled_at: DateTime<Utc>,
+    pub metric: &'a SystemMetric,
+}
+
+#[derive(Debug)]
+pub struct SyslogErrorRow<'a> {
+    pub run_id: &'a str,
+    pub logged_at: DateTime<Utc>,
+    pub syslog: &'a SyslogProperties,
+}
+
+#[derive(Debug)]
+pub struct FileRow<'a> {
+    pub run_id: &'a str,
+    pub tool_pid: &'a str,
+    pub seen_at: DateTime<Utc>,
+    pub file: &'a InputFile,
+}
+
+#[derive(Debug, Default)]
+pub struct TypedRows<'a> {
+    pub runs: Vec<RunRow<'a>>,
+    pub tool_starts: Vec<ToolStartRow<'a>>,
+    pub tool_ends: Vec<ToolEndRow<'a>>,
+    pub process_samples: Vec<ProcessSampleRow<'a>>,
+    pub system_samples: Vec<SystemSampleRow<'a>>,
+    pub syslog_errors: Vec<SyslogErrorRow<'a>>,
+    pub files: Vec<FileRow<'a>>,
+}
+
+impl<'a> TypedRows<'a> {
+    pub fn from_events(events: impl IntoIterator<Item = &'a Event>) -> Self {
+        let mut rows = TypedRows::default();
+
+        for event in events {
+            let Some(run_id) = event.run_id.as_deref() else {
+                continue;
+            };
+            let status = event.process_status.as_str();
+            let run = rows.run(run_id, event);
+
+            if status == EventType::NewRun.as_str() {
+                run.started_at = Some(event.timestamp);
+                if let Some(EventAttributes::SystemProperties(system)) = &event.attributes {
+                    run.system = Some(system);
+                }
+            } else if status == EventType::FinishedRun.as_str() {
+                run.finished_at = Some(event.timestamp);
+            }
+
+            match &event.attributes {
+                Some(EventAttributes::Process(properties))
+                    if status == EventType::ToolExecution.as_str() =>
+                {
+                    rows.tool_starts.push(ToolStartRow {
+                        run_id,
+                        pipeline_name: event.pipeline_name.as_deref(),
+                        started_at: event.timestamp,
+                        properties,
+                    });
+                    rows.files
+                        .extend(properties.input_files.iter().flatten().map(|file| FileRow {
+                            run_id,
+                            tool_pid: &properties.tool_pid,
+                            seen_at: event.timestamp,
+                            file,
+                        }));
+                }
+                Some(EventAttributes::Process(properties))
+                    if status == EventType::ToolMetricEvent.as_str() =>
+                {
+                    rows.process_samples.push(ProcessSampleRow {
+                        run_id,
+                        sampled_at: event.timestamp,
+                        properties,
+                    });
+                }
+                Some(EventAttributes::CompletedProcess(completed)) => {
+                    rows.tool_ends.push(ToolEndRow {
+                        run_id,
+                        pipeline_name: event.pipeline_name.as_deref(),
+                        finished_at: event.timestamp,
+                        completed,
+                    });
+                }
+                Some(EventAttributes::SystemMetric(metric)) => {
+                    rows.system_samples.push(SystemSampleRow {
+                        run_id,
+                        sampled_at: event.timestamp,
+                        metric,
+                    });
+                }
+                Some(EventAttributes::Syslog(syslog)) => {
+                    rows.syslog_errors.push(SyslogErrorRow {
+                        run_id,
+                        logged_at: event.timestamp,
+                        syslog,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        rows
+    }
+
+    fn run(&mut self, run_id: &'a str, event: &'a Event) -> &mut RunRow<'a> {
+        let index = match self.runs.iter().position(|run| run.run_id == run_id) {
+            Some(index) => index,
+            None => {
+                self.runs.push(RunRow {
+                    run_id,
+                    run_name: None,
+                    pipeline_name: None,
+                    tags: &[],
+                    started_at: None,
+                    finished_at: None,
+                    system: None,
+                });
+                self.runs.len() - 1
+            }
+        };
+
+        let run = &mut self.runs[index];
+        run.run_name = event.run_name.as_deref().or(run.run_name);
+        run.pipeline_name = event.pipeline_name.as_deref().or(run.pipeline_name);
+        if !event.tags.is_empty() {
+            run.tags = &event.tags;
+        }
+        run
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Writes the rows in dependency order: runs first, then tool executions, then everything
+    /// referencing them. Runs and tool ends, a handful per batch, are written one by one, the
+    /// other tables with one multi-row insert per `chunk_size` rows.
+    pub async fn insert(&self, connection: &mut PgConnection, chunk_size: usize) -> Result<()> {
+        let chunk_size = chunk_size.max(1);
+
+        for run in &self.runs {
+            insert_run(connection, run)
+                .await
+                .with_context(|| format!("Failed to insert run {}", run.run_id))?;
+        }
+        for starts in self.tool_starts.chunks(chunk_size) {
+            insert_tool_starts(connection, starts)
+                .await
+                .context("Failed to insert tool executions")?;
+        }
+        for end in &self.tool_ends {
+            insert_tool_end(connection, end)
+                .await
+                .context("Failed to finish tool execution")?;
+        }
+        for samples in self.process_samples.chunks(chunk_size) {
+            insert_process_samples(connection, samples)
+                .await
+                .context("Failed to insert process samples")?;
+        }
+        for samples in self.system_samples.chunks(chunk_size) {
+            insert_system_samples(connection, samples)
+                .await
+                .context("Failed to insert system samples")?;
+        }
+        for errors in self.syslog_errors.chunks(chunk_size) {
+            insert_syslog_errors(connection, errors)
+                .await
+                .context("Failed to insert syslog errors")?;
+        }
+        for files in self.files.chunks(chunk_size) {
+            insert_files(connection, files)
+                .await
+                .context("Failed to insert files")?;
+        }
+        Ok(())
+    }
+}
+
+/// Postgres has no unsigned integers, counters beyond `i64::MAX` are clamped.
+fn to_i64(value: u64) -> i64 {
+    value.min(i64::MAX as u64) as i64
+}
+
+/// Latest execution of the tool within the run of the unnested row `u`, what samples and files
+/// refer to.
+const TOOL_EXECUTION_ID: &str = "(SELECT id FROM tool_executions t
+      WHERE t.run_id = u.run_id AND t.tool_pid = u.tool_pid
+      ORDER BY t.started_at DESC LIMIT 1)";
+
+async fn insert_run(connection: &mut PgConnection, run: &RunRow<'_>) -> Result<()> {
+    let system = run.system;
+    sqlx::query(
+        "INSERT INTO runs (run_id, run_name, pipeline_name, tags, started_at, finished_at,
+             hostname, os, os_version, kernel_version, arch, num_cpus, total_memory, total_swap,
+             is_aws_instance, ec2_cost_per_hour)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+         ON CONFLICT (run_id) DO UPDATE SET
+             run_name = COALESCE(EXCLUDED.run_name, runs.run_name),
+             pipeline_name = COALESCE(EXCLUDED.pipeline_name, runs.pipeline_name),
+             tags = CASE WHEN cardinality(EXCLUDED.tags) > 0 THEN EXCLUDED.tags ELSE runs.tags END,
+             started_at = COALESCE(EXCLUDED.started_at, runs.started_at),
+             finished_at = COALESCE(EXCLUDED.finished_at, runs.finished_at),
+             hostname = COALESCE(EXCLUDED.hostname, runs.hostname),
+             os = COALESCE(EXCLUDED.os, runs.os),
+             os_version = COALESCE(EXCLUDED.os_version, runs.os_version),
+             kernel_version = COALESCE(EXCLUDED.kernel_version, runs.kernel_version),
+             arch = COALESCE(EXCLUDED.arch, runs.arch),
+             num_cpus = COALESCE(EXCLUDED.num_cpus, runs.num_cpus),
+             total_memory = COALESCE(EXCLUDED.total_memory, runs.total_memory),
+             total_swap = COALESCE(EXCLUDED.total_swap, runs.total_swap),
+             is_aws_instance = COALESCE(EXCLUDED.is_aws_instance, runs.is_aws_instance),
+             ec2_cost_per_hour = COALESCE(EXCLUDED.ec2_cost_per_hour, runs.ec2_cost_per_hour)",
+    )
+    .bind(run.run_id)
+    .bind(run.run_name)
+    .bind(run.pipeline_name)
+    .bind(run.tags)
+    .bind(run.started_at)
+    .bind(run.finished_at)
+    .bind(system.and_then(|system| system.hostname.as_deref()))
+    .bind(system.and_then(|system| system.os.as_deref()))
+    .bind(system.and_then(|system| system.os_version.as_deref()))
+    .bind(system.and_then(|system| system.kernel_version.as_deref()))
+    .bind(system.and_then(|system| system.arch.as_deref()))
+    .bind(system.map(|system| system.num_cpus.min(i32::MAX as usize) as i32))
+    .bind(system.map(|system| to_i64(system.total_memory)))
+    .bind(system.map(|system| to_i64(system.total_swap)))
+    .bind(system.map(|system| system.is_aws_instance))
+    .bind(system.and_then(|system| system.ec2_cost_per_hour))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+async fn insert_tool_starts(
+    connection: &mut PgConnection,
+    starts: &[ToolStartRow<'_>],
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO tool_executions (run_id, tool_pid, pipeline_name, tool_name,
+             tool_parent_pid, tool_binary_path, tool_cmd, started_at)
+         SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[],
+             $6::text[], $7::text[], $8::timestamptz[])
+         ON CONFLICT (run_id, tool_pid, started_at) DO NOTHING",
+    )
+    .bind(column(starts, |start| start.run_id))
+    .bind(column(starts, |start| start.properties.tool_pid.as_str()))
+    .bind(column(starts, |start| start.pipeline_name))
+    .bind(column(starts, |start| start.properties.tool_name.as_str()))
+    .bind(column(starts, |start| {
+        start.properties.tool_parent_pid.as_str()
+    }))
+    .bind(column(starts, |start| {
+        start.properties.tool_binary_path.as_str()
+    }))
+    .bind(column(starts, |start| start.properties.tool_cmd.as_str()))
+    .bind(column(starts, |start| start.started_at))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+/// Finishes the open execution of the tool. Without one (the start was never exported), the
+/// execution is inserted with its start derived from the duration.
+async fn insert_tool_end(connection: &mut PgConnection, end: &ToolEndRow<'_>) -> Result<()> {
+    let usage = &end.completed.resource_usage;
+    sqlx::query(
+        "WITH finished AS (
+             UPDATE tool_executions SET finished_at = $4, duration_sec = $5, exit_code = $7,
+                 signal = $8, oom_killed = $9, peak_memory_usage = $10,
+                 peak_memory_virtual = $11, cpu_time_sec = $12, average_cpu_utilization = $13,
+                 disk_read_bytes = $14, disk_write_bytes = $15
+             WHERE id = (SELECT id FROM tool_executions
+                 WHERE run_id = $1 AND tool_pid = $2 AND finished_at IS NULL
+                 ORDER BY started_at DESC LIMIT 1)
+             RETURNING id
+         )
+         INSERT INTO tool_executions (run_id, tool_pid, pipeline_name, tool_name, started_at,
+             finished_at, duration_sec, exit_code, signal, oom_killed, peak_memory_usage,
+             peak_memory_virtual, cpu_time_sec, average_cpu_utilization, disk_read_bytes,
+             disk_write_bytes)
+         SELECT $1, $2, $6, $3, $4 - make_interval(secs => $5), $4, $5, $7, $8, $9, $10, $11,
+             $12, $13, $14, $15
+         WHERE NOT EXISTS (SELECT 1 FROM finished)
+             AND NOT EXISTS (SELECT 1 FROM tool_executions
+                 WHERE run_id = $1 AND tool_pid = $2 AND finished_at = $4)
+         ON CONFLICT (run_id, tool_pid, started_at) DO NOTHING",
+    )
+    .bind(end.run_id)
+    .bind(&end.completed.tool_pid)
+    .bind(&end.completed.tool_name)
+    .bind(end.finished_at)
+    .bind(to_i64(end.completed.duration_sec))
+    .bind(end.pipeline_name)
+    .bind(end.completed.exit_code)
+    .bind(end.completed.signal)
+    .bind(end.completed.oom_killed)
+    .bind(to_i64(usage.peak_memory_usage))
+    .bind(to_i64(usage.peak_memory_virtual))
+    .bind(usage.cpu_time_sec)
+    .bind(usage.average_cpu_utilization)
+    .bind(to_i64(usage.disk_usage_read_total))
+    .bind(to_i64(usage.disk_usage_write_total))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+async fn insert_process_samples(
+    connection: &mut PgConnection,
+    samples: &[ProcessSampleRow<'_>],
+) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO process_samples (run_id, tool_pid, tool_execution_id, tool_name, sampled_at,
+             cpu_utilization, memory_usage, memory_virtual, run_time_sec,
+             disk_read_bytes_last_interval, disk_write_bytes_last_interval,
+             disk_read_bytes_total, disk_write_bytes_total, status)
+         SELECT u.run_id, u.tool_pid, {TOOL_EXECUTION_ID}, u.tool_name, u.sampled_at,
+             u.cpu_utilization, u.memory_usage, u.memory_virtual, u.run_time_sec,
+             u.disk_read_bytes_last_interval, u.disk_write_bytes_last_interval,
+             u.disk_read_bytes_total, u.disk_write_bytes_total, u.status
+         FROM UNNEST($1::text[], $2::text[], $3::text[], $4::timestamptz[], $5::real[],
+             $6::bigint[], $7::bigint[], $8::bigint[], $9::bigint[], $10::bigint[],
+             $11::bigint[], $12::bigint[], $13::text[])
+             AS u(run_id, tool_pid, tool_name, sampled_at, cpu_utilization, memory_usage,
+                 memory_virtual, run_time_sec, disk_read_bytes_last_interval,
+                 disk_write_bytes_last_interval, disk_read_bytes_total, disk_write_bytes_total,
+                 status)"
+    ))
+    .bind(column(samples, |sample| sample.run_id))
+    .bind(column(samples, |sample| {
+        sample.properties.tool_pid.as_str()
+    }))
+    .bind(column(samples, |sample| {
+        sample.properties.tool_name.as_str()
+    }))
+    .bind(column(samples, |sample| sample.sampled_at))
+    .bind(column(samples, |sample| {
+        sample.properties.process_cpu_utilization
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_memory_usage)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_memory_virtual)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_run_time)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_disk_usage_read_last_interval)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_disk_usage_write_last_interval)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_disk_usage_read_total)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.properties.process_disk_usage_write_total)
+    }))
+    .bind(column(samples, |sample| {
+        sample.properties.process_status.as_str()
+    }))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+async fn insert_system_samples(
+    connection: &mut PgConnection,
+    samples: &[SystemSampleRow<'_>],
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO system_samples (run_id, sampled_at, memory_total, memory_used,
+             memory_available, memory_utilization, swap_total, swap_used, cpu_utilization, disks)
+         SELECT * FROM UNNEST($1::text[], $2::timestamptz[], $3::bigint[], $4::bigint[],
+             $5::bigint[], $6::double precision[], $7::bigint[], $8::bigint[], $9::real[],
+             $10::jsonb[])",
+    )
+    .bind(column(samples, |sample| sample.run_id))
+    .bind(column(samples, |sample| sample.sampled_at))
+    .bind(column(samples, |sample| {
+        to_i64(sample.metric.system_memory_total)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.metric.system_memory_used)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.metric.system_memory_available)
+    }))
+    .bind(column(samples, |sample| {
+        sample.metric.system_memory_utilization
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.metric.system_memory_swap_total)
+    }))
+    .bind(column(samples, |sample| {
+        to_i64(sample.metric.system_memory_swap_used)
+    }))
+    .bind(column(samples, |sample| {
+        sample.metric.system_cpu_utilization
+    }))
+    .bind(column(samples, |sample| {
+        Json(&sample.metric.system_disk_io)
+    }))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+async fn insert_syslog_errors(
+    connection: &mut PgConnection,
+    errors: &[SyslogErrorRow<'_>],
+) -> Result<()> {
+    // arrays of arrays can't be unnested row by row, the previous lines travel as JSON
+    sqlx::query(
+        "INSERT INTO syslog_errors (run_id, logged_at, error_id, error_display_name, error_line,
+             file_line_number, previous_lines)
+         SELECT u.run_id, u.logged_at, u.error_id, u.error_display_name, u.error_line,
+             u.file_line_number, ARRAY(SELECT jsonb_array_elements_text(u.previous_lines))
+         FROM UNNEST($1::text[], $2::timestamptz[], $3::text[], $4::text[], $5::text[],
+             $6::bigint[], $7::jsonb[])
+             AS u(run_id, logged_at, error_id, error_display_name, error_line, file_line_number,
+                 previous_lines)",
+    )
+    .bind(column(errors, |error| error.run_id))
+    .bind(column(errors, |error| error.logged_at))
+    .bind(column(errors, |error| error.syslog.error_id.as_str()))
+    .bind(column(errors, |error| {
+        error.syslog.error_display_name.as_str()
+    }))
+    .bind(column(errors, |error| error.syslog.error_line.as_str()))
+    .bind(column(errors, |error| {
+        to_i64(error.syslog.file_line_number)
+    }))
+    .bind(column(errors, |error| {
+        Json(&error.syslog.file_previous_logs)
+    }))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+async fn insert_files(connection: &mut PgConnection, files: &[FileRow<'_>]) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO files (run_id, tool_pid, tool_execution_id, file_name, file_path,
+             file_directory, file_size, file_updated_at, seen_at)
+         SELECT u.run_id, u.tool_pid, {TOOL_EXECUTION_ID}, u.file_name, u.file_path,
+             u.file_directory, u.file_size, u.file_updated_at, u.seen_at
+         FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::bigint[],
+             $7::text[], $8::timestamptz[])
+             AS u(run_id, tool_pid, file_name, file_path, file_directory, file_size,
+                 file_updated_at, seen_at)
+         ON CONFLICT (run_id, tool_pid, file_path) DO NOTHING"
+    ))
+    .bind(column(files, |file| file.run_id))
+    .bind(column(files, |file| file.tool_pid))
+    .bind(column(files, |file| file.file.file_name.as_str()))
+    .bind(column(files, |file| file.file.file_path.as_str()))
+    .bind(column(files, |file| file.file.file_directory.as_str()))
+    .bind(column(files, |file| to_i64(file.file.file_size)))
+    .bind(column(files, |file| {
+        file.file.file_updated_at_timestamp.as_str()
+    }))
+    .bind(column(files, |file| file.seen_at))
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+/// One column of a multi-row insert, bound as an array and unnested by the query.
+fn column<'r, R, T>(rows: &'r [R], value: impl Fn(&'r R) -> T) -> Vec<T> {
+    rows.iter().map(value).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+    use crate::exporters::db::DEFAULT_INSERT_CHUNK_SIZE;
+    use crate::types::event::attributes::process::ResourceUsage;
+
+    fn process_properties(pid: &str) -> ProcessProperties {
+        ProcessProperties {
+            tool_name: "STAR".to_string(),
+            tool_pid: pid.to_string(),
+            tool_parent_pid: "1".to_string(),
+            tool_binary_path: "/usr/bin/STAR".to_string(),
+            tool_cmd: "STAR --readFilesIn sample.fastq".to_string(),
+            start_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            process_cpu_utilization: 50.0,
+            process_memory_usage: 1024,
+            process_memory_virtual: 2048,
+            process_run_time: 3,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 10,
+            process_disk_usage_write_total: 20,
+            process_status: "Run".to_string(),
+            input_files: Some(vec![InputFile {
+                file_name: "sample.fastq".to_string(),
+                file_size: 4096,
+                file_path: "/data/sample.fastq".to_string(),
+                file_directory: "/data".to_string(),
+                file_updated_at_timestamp: "2025-01-01T00:00:00Z".to_string(),
+            }]),
+            subtree: None,
+            cgroup: None,
+            container: None,
+            details: None,
+        }
+    }
+
+    #[test]
+    fn test_rows_from_run_events() {
+        let mut recorder = EventRecorder::new(
+            Some("rnaseq".to_string()),
+            Some("brave-otter".to_string()),
+            Some("run-1".to_string()),
+        );
+        recorder.record_event(EventType::NewRun, "start".to_string(), None, None);
+        recorder.record_event(
+            EventType::ToolExecution,
+            "STAR started".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::ToolMetricEvent,
+            "STAR metrics".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "STAR finished".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "STAR".to_string(),
+                tool_pid: "42".to_string(),
+                duration_sec: 3,
+                exit_code: Some(0),
+                signal: None,
+                oom_killed: false,
+                resource_usage: Default::default(),
+                input_paths: vec![],
+                output_paths: vec![],
+            })),
+            None,
+        );
+        recorder.record_event(EventType::FinishedRun, "end".to_string(), None, None);
+
+        let rows = TypedRows::from_events(recorder.get_events());
+        assert_eq!(rows.runs.len(), 1);
+        let run = &rows.runs[0];
+        assert_eq!(run.run_name, Some("brave-otter"));
+        assert_eq!(run.pipeline_name, Some("rnaseq"));
+        assert!(run.started_at.is_some() && run.finished_at.is_some());
+
+        assert_eq!(rows.tool_starts.len(), 1);
+        assert_eq!(rows.process_samples.len(), 1);
+        assert_eq!(rows.tool_ends.len(), 1);
+        assert_eq!(rows.files.len(), 1);
+        assert_eq!(rows.files[0].tool_pid, "42");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a postgres database in TRACER_TEST_DATABASE_URL"]
+    async fn test_rows_round_trip_through_postgres() -> Result<()> {
+        let pool = sqlx::PgPool::connect(&std::env::var("TRACER_TEST_DATABASE_URL")?).await?;
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        let run_id = uuid::Uuid::new_v4().to_string();
+        let mut recorder = EventRecorder::new(
+            Some("rnaseq".to_string()),
+            Some("brave-otter".to_string()),
+            Some(run_id.clone()),
+        );
+        recorder.record_event(
+            EventType::ToolExecution,
+            "STAR started".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::ToolMetricEvent,
+            "STAR metrics".to_string(),
+            Some(EventAttributes::Process(process_properties("42"))),
+            None,
+        );
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "STAR finished".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "STAR".to_string(),
+                tool_pid: "42".to_string(),
+                duration_sec: 3,
+                exit_code: Some(0),
+                signal: None,
+                oom_killed: false,
+                resource_usage: ResourceUsage {
+                    peak_memory_usage: 1 << 30,
+                    ..Default::default()
+                },
+                input_paths: vec![],
+                output_paths: vec![],
+            })),
+            None,
+        );
+
+        let mut connection = pool.acquire().await?;
+        TypedRows::from_events(recorder.get_events())
+            .insert(&mut connection, DEFAULT_INSERT_CHUNK_SIZE)
+            .await?;
+
+        let (pipeline_name, duration_sec, exit_code, peak_memory_usage, samples, files): (
+            String,
+            i64,
+            Option<i32>,
+            Option<i64>,
+            i64,
+            i64,
+        ) = sqlx::query_as(
+            "SELECT t.pipeline_name, t.duration_sec, t.exit_code, t.peak_memory_usage,
+                     (SELECT count(*) FROM process_samples p WHERE p.tool_execution_id = t.id),
+                     (SELECT count(*) FROM files f WHERE f.tool_execution_id = t.id)
+                 FROM tool_executions t WHERE t.run_id = $1",
+        )
+        .bind(&run_id)
+        .fetch_one(&mut *connection)
+        .await?;
+        assert_eq!(pipeline_name, "rnaseq");
+        assert_eq!(duration_sec, 3);
+        assert_eq!(exit_code, Some(0));
+        assert_eq!(peak_memory_usage, Some(1 << 30));
+        assert_eq!((samples, files), (1, 1));
+
+        sqlx::query("DELETE FROM runs WHERE run_id = $1")
+            .bind(&run_id)
+            .execute(&mut *connection)
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_without_run_are_skipped() {
+        let mut recorder = EventRecorder::default();
+        recorder.record_event(EventType::TestEvent, "orphan".to_string(), None, None);
+
+        assert!(TypedRows::from_events(recorder.get_events()).is_empty());
+    }
+}
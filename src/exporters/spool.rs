@@ -0,0 +1,411 @@
+// src/exporters/spool.rs
+//! Append-only on-disk spool for event batches that could not be exported.
+//!
+//! Batches are stored one per line in numbered JSONL segments. They are replayed oldest first,
+//! and when the spool grows past its size cap whole segments are evicted, oldest first. Since
+//! everything lives on disk, spooled batches survive a daemon restart.
+//!
+//! Delivery is at-least-once: a replayed batch is sent again if the daemon stops, or the
+//! segment can't be rewritten, before its export was recorded on disk.
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use super::EventBatch;
+
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_EXTENSION: &str = "jsonl";
+const MAX_SEGMENT_SIZE_BYTES: u64 = 8 * 1024 * 1024;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// The batches of a single segment, handed out for replay.
+pub struct SpoolSegment {
+    id: u64,
+    pub batches: Vec<EventBatch>,
+}
+
+struct SegmentInfo {
+    id: u64,
+    size: u64,
+    batches: usize,
+    /// Batches at the start of the file already exported, but not yet removed from it
+    committed: usize,
+}
+
+pub struct EventSpool {
+    dir: PathBuf,
+    max_size_bytes: u64,
+    max_segment_size_bytes: u64,
+    segments: Vec<SegmentInfo>,
+    backoff: Backoff,
+    evicted_batches: u64,
+}
+
+impl EventSpool {
+    /// Opens the spool in `dir`, picking up any segments left over by a previous daemon.
+    pub fn open<P: AsRef<Path>>(dir: P, max_size_bytes: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create spool directory: {:?}", dir))?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(id) = segment_id(&path) else {
+                continue;
+            };
+            let size = fs::metadata(&path)?.len();
+            let batches = BufReader::new(File::open(&path)?).lines().count();
+            segments.push(SegmentInfo {
+                id,
+                size,
+                batches,
+                committed: 0,
+            });
+        }
+        segments.sort_by_key(|segment| segment.id);
+
+        Ok(EventSpool {
+            dir,
+            max_size_bytes,
+            max_segment_size_bytes: MAX_SEGMENT_SIZE_BYTES.min((max_size_bytes / 4).max(1)),
+            segments,
+            backoff: Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF),
+            evicted_batches: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending_batches() == 0
+    }
+
+    pub fn pending_batches(&self) -> usize {
+        self.segments.iter().map(|segment| segment.batches).sum()
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.size).sum()
+    }
+
+    /// Number of batches dropped by the size cap since the spool was opened.
+    pub fn evicted_batches(&self) -> u64 {
+        self.evicted_batches
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Backoff::new(initial, max);
+        self
+    }
+
+    /// Whether the backoff after the last failed export has elapsed.
+    pub fn ready_for_replay(&self) -> bool {
+        self.backoff.ready()
+    }
+
+    pub fn record_export_success(&mut self) {
+        self.backoff.success();
+    }
+
+    pub fn record_export_failure(&mut self) {
+        self.backoff.failure();
+    }
+
+    pub fn append(&mut self, batch: &EventBatch) -> Result<()> {
+        let mut line = serde_json::to_vec(batch)?;
+        line.push(b'\n');
+
+        let needs_new_segment = match self.segments.last() {
+            Some(segment) => segment.size + line.len() as u64 > self.max_segment_size_bytes,
+            None => true,
+        };
+        if needs_new_segment {
+            let id = self.segments.last().map_or(0, |segment| segment.id + 1);
+            self.segments.push(SegmentInfo {
+                id,
+                size: 0,
+                batches: 0,
+                committed: 0,
+            });
+        }
+
+        let segment = self.segments.last_mut().unwrap();
+        let path = segment_path(&self.dir, segment.id);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open spool segment: {:?}", path))?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+
+        segment.size += line.len() as u64;
+        segment.batches += 1;
+
+        self.evict_oldest_segments()
+    }
+
+    /// Returns the batches of the oldest segment, or `None` if the spool is empty. Lines that
+    /// can't be decoded (e.g. a write cut short by a crash) are skipped.
+    pub fn oldest_segment(&self) -> Result<Option<SpoolSegment>> {
+        let Some(segment) = self.segments.first() else {
+            return Ok(None);
+        };
+
+        let path = segment_path(&self.dir, segment.id);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Some(SpoolSegment {
+                    id: segment.id,
+                    batches: Vec::new(),
+                }))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut batches = Vec::new();
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(batch) => batches.push(batch),
+                Err(err) => eprintln!("Skipping corrupted spool entry in {:?}: {err}", path),
+            }
+        }
+        batches.drain(..segment.committed.min(batches.len()));
+
+        Ok(Some(SpoolSegment {
+            id: segment.id,
+            batches,
+        }))
+    }
+
+    /// Marks the first `sent` batches of `segment` as exported. A fully exported segment is
+    /// removed, a partially exported one is rewritten with the remaining batches. The progress
+    /// is kept in memory first, so batches aren't handed out again if updating the file fails.
+    pub fn commit(&mut self, segment: SpoolSegment, sent: usize) -> Result<()> {
+        let Some(index) = self.segments.iter().position(|info| info.id == segment.id) else {
+            // evicted in the meantime
+            return Ok(());
+        };
+        let path = segment_path(&self.dir, segment.id);
+
+        let info = &mut self.segments[index];
+        info.committed += sent;
+        info.batches = segment.batches.len().saturating_sub(sent);
+
+        if sent >= segment.batches.len() {
+            remove_segment_file(&path)?;
+            self.segments.remove(index);
+            return Ok(());
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        let mut size = 0;
+        for batch in &segment.batches[sent..] {
+            let mut line = serde_json::to_vec(batch)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            size += line.len() as u64;
+        }
+        file.sync_data()?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to rewrite spool segment: {:?}", path))?;
+
+        let info = &mut self.segments[index];
+        info.size = size;
+        info.committed = 0;
+        Ok(())
+    }
+
+    fn evict_oldest_segments(&mut self) -> Result<()> {
+        while self.size_bytes() > self.max_size_bytes && self.segments.len() > 1 {
+            let oldest = self.segments.remove(0);
+            remove_segment_file(&segment_path(&self.dir, oldest.id))?;
+            self.evicted_batches += oldest.batches as u64;
+            eprintln!(
+                "Spool exceeded {} bytes, dropped {} of the oldest batches",
+                self.max_size_bytes, oldest.batches
+            );
+        }
+        Ok(())
+    }
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{SEGMENT_PREFIX}{id:010}.{SEGMENT_EXTENSION}"))
+}
+
+fn segment_id(path: &Path) -> Option<u64> {
+    if path.extension()? != SEGMENT_EXTENSION {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(SEGMENT_PREFIX)?
+        .parse()
+        .ok()
+}
+
+fn remove_segment_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Exponential backoff between export attempts while the destination is unreachable.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            current: initial,
+            next_attempt: None,
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        self.next_attempt
+            .map_or(true, |next_attempt| Instant::now() >= next_attempt)
+    }
+
+    pub fn success(&mut self) {
+        self.current = self.initial;
+        self.next_attempt = None;
+    }
+
+    pub fn failure(&mut self) {
+        self.next_attempt = Some(Instant::now() + self.current);
+        self.current = (self.current * 2).min(self.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exporters::test_support::batch;
+    use tempfile::tempdir;
+
+    fn job_ids(segment: &SpoolSegment) -> Vec<&str> {
+        segment
+            .batches
+            .iter()
+            .map(|batch| batch.job_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_batches_survive_reopening_in_order() -> Result<()> {
+        let dir = tempdir()?;
+
+        let mut spool = EventSpool::open(dir.path(), 1024 * 1024)?;
+        spool.append(&batch("first"))?;
+        spool.append(&batch("second"))?;
+        drop(spool);
+
+        let spool = EventSpool::open(dir.path(), 1024 * 1024)?;
+        assert_eq!(spool.pending_batches(), 2);
+
+        let segment = spool.oldest_segment()?.unwrap();
+        assert_eq!(job_ids(&segment), vec!["first", "second"]);
+        assert_eq!(segment.batches[0].events[0].message, "first");
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_commit_keeps_remaining_batches() -> Result<()> {
+        let dir = tempdir()?;
+        let mut spool = EventSpool::open(dir.path(), 1024 * 1024)?;
+        for job_id in ["a", "b", "c"] {
+            spool.append(&batch(job_id))?;
+        }
+
+        let segment = spool.oldest_segment()?.unwrap();
+        spool.commit(segment, 1)?;
+        assert_eq!(spool.pending_batches(), 2);
+
+        let segment = spool.oldest_segment()?.unwrap();
+        assert_eq!(job_ids(&segment), vec!["b", "c"]);
+
+        spool.commit(segment, 2)?;
+        assert!(spool.is_empty());
+        assert!(spool.oldest_segment()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_commit_does_not_replay_sent_batches() -> Result<()> {
+        let dir = tempdir()?;
+        let mut spool = EventSpool::open(dir.path(), 1024 * 1024)?;
+        for job_id in ["a", "b", "c"] {
+            spool.append(&batch(job_id))?;
+        }
+
+        // a directory in the way of the rewritten segment
+        let tmp_path = segment_path(dir.path(), 0).with_extension("tmp");
+        fs::create_dir(&tmp_path)?;
+        let segment = spool.oldest_segment()?.unwrap();
+        assert!(spool.commit(segment, 1).is_err());
+        assert_eq!(spool.pending_batches(), 2);
+
+        let segment = spool.oldest_segment()?.unwrap();
+        assert_eq!(job_ids(&segment), vec!["b", "c"]);
+
+        fs::remove_dir(&tmp_path)?;
+        spool.commit(segment, 1)?;
+        let segment = spool.oldest_segment()?.unwrap();
+        assert_eq!(job_ids(&segment), vec!["c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_oldest_segments_are_evicted_over_size_cap() -> Result<()> {
+        let dir = tempdir()?;
+        let line_len = serde_json::to_vec(&batch("job-00"))?.len() as u64 + 1;
+
+        // room for four batches, segments of one batch each
+        let mut spool = EventSpool::open(dir.path(), line_len * 4)?;
+        for i in 0..10 {
+            spool.append(&batch(&format!("job-{i:02}")))?;
+        }
+
+        assert_eq!(spool.pending_batches(), 4);
+        assert_eq!(spool.evicted_batches(), 6);
+        assert!(spool.size_bytes() <= line_len * 4);
+
+        let segment = spool.oldest_segment()?.unwrap();
+        assert_eq!(job_ids(&segment), vec!["job-06"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(3));
+        assert!(backoff.ready());
+
+        backoff.failure();
+        assert!(!backoff.ready());
+        assert_eq!(backoff.current, Duration::from_secs(2));
+
+        backoff.failure();
+        backoff.failure();
+        assert_eq!(backoff.current, Duration::from_secs(3));
+
+        backoff.success();
+        assert!(backoff.ready());
+        assert_eq!(backoff.current, Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,14 @@
+// src/exporters/test_support.rs
+use crate::events::recorder::{EventRecorder, EventType};
+
+use super::EventBatch;
+
+/// A batch with a single test event, told apart by its job ID.
+pub fn batch(job_id: &str) -> EventBatch {
+    let mut recorder = EventRecorder::default();
+    recorder.record_event(EventType::TestEvent, job_id.to_string(), None, None);
+    EventBatch {
+        job_id: job_id.to_string(),
+        events: recorder.get_events().to_vec(),
+    }
+}
@@ -0,0 +1,354 @@
+// src/extracts/cgroups.rs
+//! Accounting of the cgroups tracked tools run in, read from the cgroup v2 interface files.
+//!
+//! The kernel charges page cache, kernel memory and throttling to the cgroup, none of which the
+//! per-process numbers show. Under Slurm or Kubernetes the cgroup is also where the limits a tool
+//! runs into are set.
+use chrono::Utc;
+use itertools::Itertools;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    events::recorder::{EventRecorder, EventType},
+    types::event::attributes::{
+        cgroup::{CgroupScope, CgroupUsage},
+        EventAttributes,
+    },
+};
+
+pub struct CgroupCollector {
+    /// Where the cgroup2 hierarchy is mounted, `None` on hosts without one
+    root: Option<PathBuf>,
+    /// The last sample of every cgroup, to tell whether limits were hit since
+    last_samples: HashMap<String, CgroupUsage>,
+}
+
+impl Default for CgroupCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CgroupCollector {
+    pub fn new() -> Self {
+        Self::with_root(cgroup2_mount())
+    }
+
+    pub fn with_root(root: Option<PathBuf>) -> Self {
+        CgroupCollector {
+            root,
+            last_samples: HashMap::new(),
+        }
+    }
+
+    /// Records the usage of the cgroups in `tools`, which maps cgroup paths to the names of the
+    /// tracked tools in them, and of the closest cgroup they share.
+    pub fn collect_metrics(
+        &mut self,
+        tools: &BTreeMap<String, Vec<String>>,
+        logs: &mut EventRecorder,
+    ) {
+        let Some(root) = &self.root else {
+            return;
+        };
+
+        let run = run_cgroup(tools.keys().map(String::as_str))
+            .filter(|cgroup| !tools.contains_key(cgroup))
+            .map(|cgroup| {
+                let names = tools.values().flatten().cloned().sorted().dedup().collect();
+                (cgroup, CgroupScope::Run, names)
+            });
+        let cgroups = tools
+            .iter()
+            .map(|(cgroup, names)| (cgroup.clone(), CgroupScope::Tool, names.clone()))
+            .chain(run);
+
+        let mut samples = HashMap::new();
+        for (cgroup, scope, names) in cgroups {
+            // the root cgroup has none of the accounting files
+            if cgroup == "/" {
+                continue;
+            }
+            let Some(mut usage) = read_usage(root, &cgroup) else {
+                continue;
+            };
+            usage.scope = scope;
+            usage.tools = names;
+            // counters of a cgroup seen for the first time count from its creation
+            let last = self.last_samples.get(&cgroup);
+            let last_counter = |counter: fn(&CgroupUsage) -> u64| last.map_or(0, counter);
+            usage.memory_limit_hit = usage.memory_max_events
+                > last_counter(|usage| usage.memory_max_events)
+                || usage.memory_oom_kill_events
+                    > last_counter(|usage| usage.memory_oom_kill_events);
+            usage.cpu_throttled =
+                usage.cpu_throttled_periods > last_counter(|usage| usage.cpu_throttled_periods);
+
+            logs.record_event(
+                EventType::MetricEvent,
+                format!("[{}] Resources of cgroup {}", Utc::now(), cgroup),
+                Some(EventAttributes::CgroupUsage(usage.clone())),
+                None,
+            );
+            samples.insert(cgroup, usage);
+        }
+        self.last_samples = samples;
+    }
+}
+
+/// The mount point of the cgroup2 hierarchy, `/sys/fs/cgroup` or `/sys/fs/cgroup/unified` on
+/// hybrid hosts.
+fn cgroup2_mount() -> Option<PathBuf> {
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let mount_point = fields.nth(1)?;
+        (fields.next()? == "cgroup2").then(|| PathBuf::from(mount_point))
+    })
+}
+
+/// The deepest cgroup that contains all of `cgroups`, `None` when that's the root.
+fn run_cgroup<'a>(mut cgroups: impl Iterator<Item = &'a str>) -> Option<String> {
+    let components = |cgroup: &'a str| cgroup.split('/').filter(|c| !c.is_empty());
+    let mut common: Vec<&str> = components(cgroups.next()?).collect();
+    for cgroup in cgroups {
+        let shared = common
+            .iter()
+            .zip(components(cgroup))
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        common.truncate(shared);
+    }
+    (!common.is_empty()).then(|| format!("/{}", common.join("/")))
+}
+
+/// `None` once the cgroup is gone.
+fn read_usage(root: &Path, cgroup: &str) -> Option<CgroupUsage> {
+    let dir = root.join(cgroup.trim_start_matches('/'));
+    if !dir.is_dir() {
+        return None;
+    }
+
+    let value = |file: &str| -> Option<String> {
+        Some(fs::read_to_string(dir.join(file)).ok()?.trim().to_string())
+    };
+    let keyed = |file: &str| {
+        value(file)
+            .map(|content| parse_keyed(&content))
+            .unwrap_or_default()
+    };
+
+    let memory_stat = keyed("memory.stat");
+    let memory_events = keyed("memory.events");
+    let cpu_stat = keyed("cpu.stat");
+    let io = value("io.stat")
+        .map(|content| parse_io_stat(&content))
+        .unwrap_or_default();
+    let counter = |counters: &HashMap<String, u64>, key| counters.get(key).copied().unwrap_or(0);
+
+    Some(CgroupUsage {
+        cgroup: cgroup.to_string(),
+        memory_current: value("memory.current").and_then(|v| v.parse().ok()),
+        memory_peak: value("memory.peak").and_then(|v| v.parse().ok()),
+        memory_max: value("memory.max").and_then(|v| v.parse().ok()),
+        memory_anon: counter(&memory_stat, "anon"),
+        memory_file: counter(&memory_stat, "file"),
+        // `kernel` only exists since Linux 5.18, before that these are its largest parts
+        memory_kernel: memory_stat.get("kernel").copied().unwrap_or_else(|| {
+            counter(&memory_stat, "kernel_stack")
+                + counter(&memory_stat, "pagetables")
+                + counter(&memory_stat, "slab")
+        }),
+        memory_max_events: counter(&memory_events, "max"),
+        memory_oom_events: counter(&memory_events, "oom"),
+        memory_oom_kill_events: counter(&memory_events, "oom_kill"),
+        cpu_usage_usec: counter(&cpu_stat, "usage_usec"),
+        cpu_user_usec: counter(&cpu_stat, "user_usec"),
+        cpu_system_usec: counter(&cpu_stat, "system_usec"),
+        cpu_periods: counter(&cpu_stat, "nr_periods"),
+        cpu_throttled_periods: counter(&cpu_stat, "nr_throttled"),
+        cpu_throttled_usec: counter(&cpu_stat, "throttled_usec"),
+        io_read_bytes: counter(&io, "rbytes"),
+        io_write_bytes: counter(&io, "wbytes"),
+        io_read_ops: counter(&io, "rios"),
+        io_write_ops: counter(&io, "wios"),
+        ..Default::default()
+    })
+}
+
+/// Files of `key value` lines, like `memory.stat` and `cpu.stat`.
+fn parse_keyed(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// `io.stat` has a `key=value` line per device, summed over all of them.
+fn parse_io_stat(content: &str) -> HashMap<String, u64> {
+    let mut totals = HashMap::new();
+    for (key, value) in content
+        .lines()
+        .flat_map(|line| line.split_whitespace().skip(1))
+        .filter_map(|field| field.split_once('='))
+    {
+        if let Ok(value) = value.parse::<u64>() {
+            *totals.entry(key.to_string()).or_default() += value;
+        }
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB: &str = "/system.slice/slurmstepd.scope/job_42";
+
+    fn write_cgroup(root: &Path, cgroup: &str, files: &[(&str, &str)]) {
+        let dir = root.join(cgroup.trim_start_matches('/'));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+    }
+
+    fn usage_events(logs: &EventRecorder) -> Vec<CgroupUsage> {
+        logs.get_events()
+            .iter()
+            .filter_map(|event| match &event.attributes {
+                Some(EventAttributes::CgroupUsage(usage)) => Some(usage.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_cgroup_accounting() {
+        let root = tempfile::tempdir().unwrap();
+        write_cgroup(
+            root.path(),
+            JOB,
+            &[
+                ("memory.current", "1073741824\n"),
+                ("memory.peak", "2147483648\n"),
+                ("memory.max", "4294967296\n"),
+                (
+                    "memory.stat",
+                    "anon 805306368\nfile 201326592\nkernel 67108864\nsock 0\n",
+                ),
+                (
+                    "memory.events",
+                    "low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\noom_group_kill 0\n",
+                ),
+                (
+                    "cpu.stat",
+                    "usage_usec 9000000\nuser_usec 8000000\nsystem_usec 1000000\nnr_periods 100\nnr_throttled 7\nthrottled_usec 350000\n",
+                ),
+                (
+                    "io.stat",
+                    "8:0 rbytes=4096 wbytes=8192 rios=1 wios=2 dbytes=0 dios=0\n259:0 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n",
+                ),
+            ],
+        );
+
+        let usage = read_usage(root.path(), JOB).unwrap();
+        assert_eq!(usage.memory_current, Some(1 << 30));
+        assert_eq!(usage.memory_peak, Some(2 << 30));
+        assert_eq!(usage.memory_max, Some(4 << 30));
+        assert_eq!(usage.memory_file, 201326592);
+        assert_eq!(usage.memory_kernel, 64 << 20);
+        assert_eq!(usage.memory_oom_kill_events, 1);
+        assert_eq!(usage.cpu_throttled_usec, 350000);
+        assert_eq!(usage.io_read_bytes, 8192);
+        assert_eq!(usage.io_write_ops, 2);
+
+        write_cgroup(root.path(), "/unlimited", &[("memory.max", "max\n")]);
+        let usage = read_usage(root.path(), "/unlimited").unwrap();
+        assert_eq!(usage.memory_max, None);
+        assert_eq!(usage.memory_current, None);
+
+        assert_eq!(read_usage(root.path(), "/gone"), None);
+    }
+
+    #[test]
+    fn test_run_cgroup_is_the_closest_shared_one() {
+        assert_eq!(
+            run_cgroup(
+                [format!("{JOB}/step_0"), format!("{JOB}/step_1/user")]
+                    .iter()
+                    .map(String::as_str)
+            ),
+            Some(JOB.to_string())
+        );
+        assert_eq!(run_cgroup([JOB].into_iter()), Some(JOB.to_string()));
+        assert_eq!(
+            run_cgroup(["/system.slice/a.scope", "/user.slice"].into_iter()),
+            None
+        );
+        assert_eq!(run_cgroup(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_flags_limits_hit_since_the_last_sample() {
+        let root = tempfile::tempdir().unwrap();
+        let step = format!("{JOB}/step_0");
+        let events = |max: u64, throttled: u64| {
+            [
+                ("memory.events", format!("max {max}\noom 0\noom_kill 0\n")),
+                (
+                    "cpu.stat",
+                    format!("usage_usec 10\nnr_throttled {throttled}\n"),
+                ),
+            ]
+        };
+        let write = |max, throttled| {
+            let files = events(max, throttled);
+            let files: Vec<_> = files.iter().map(|(f, c)| (*f, c.as_str())).collect();
+            write_cgroup(root.path(), &step, &files);
+            write_cgroup(root.path(), JOB, &files);
+        };
+
+        let mut collector = CgroupCollector::with_root(Some(root.path().to_path_buf()));
+        let tools = BTreeMap::from([(step.clone(), vec!["STAR".to_string()])]);
+        let mut logs = EventRecorder::default();
+
+        write(3, 0);
+        collector.collect_metrics(&tools, &mut logs);
+        write(3, 5);
+        collector.collect_metrics(&tools, &mut logs);
+
+        let usage = usage_events(&logs);
+        // the only cgroup is the run's as well
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].scope, CgroupScope::Tool);
+        assert_eq!(usage[0].tools, vec!["STAR".to_string()]);
+        assert!(usage[0].memory_limit_hit);
+        assert!(!usage[0].cpu_throttled);
+        assert!(!usage[1].memory_limit_hit);
+        assert!(usage[1].cpu_throttled);
+
+        let tools = BTreeMap::from([
+            (step.clone(), vec!["STAR".to_string()]),
+            (format!("{JOB}/step_1"), vec!["samtools".to_string()]),
+        ]);
+        let mut logs = EventRecorder::default();
+        collector.collect_metrics(&tools, &mut logs);
+        let usage = usage_events(&logs);
+        // step_1 doesn't exist (anymore)
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[1].cgroup, JOB);
+        assert_eq!(usage[1].scope, CgroupScope::Run);
+        assert_eq!(
+            usage[1].tools,
+            vec!["STAR".to_string(), "samtools".to_string()]
+        );
+    }
+}
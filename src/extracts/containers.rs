@@ -0,0 +1,423 @@
+// src/extracts/containers.rs
+//! Which container a process runs in. Docker, Podman and Kubernetes runtimes are recognized from
+//! the cgroup, Singularity and Apptainer from the environment they set. With a Docker-compatible
+//! API socket configured, image names and labels are looked up too.
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    time::Duration,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::exporters::spool::Backoff;
+use crate::types::event::attributes::process::ContainerInfo;
+
+const RUNTIME_API_TIMEOUT: Duration = Duration::from_secs(2);
+const INSPECT_RETRY_INITIAL: Duration = Duration::from_secs(5);
+const INSPECT_RETRY_MAX: Duration = Duration::from_secs(5 * 60);
+const CONTAINER_ID_LENGTH: usize = 64;
+
+/// Scope prefixes the runtimes give the cgroups of their containers under systemd.
+const SCOPE_PREFIXES: [(&str, &str); 4] = [
+    ("docker-", "docker"),
+    ("libpod-", "podman"),
+    ("cri-containerd-", "containerd"),
+    ("crio-", "crio"),
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProcessPlacement {
+    pub cgroup: Option<String>,
+    pub container: Option<ContainerInfo>,
+}
+
+/// Resolves and caches the placement of processes, and the runtime details of containers.
+/// Containers are inspected in the background, processes get their image and labels from the
+/// first `resolve` after the runtime answered.
+pub struct ContainerResolver {
+    runtime_socket: Option<String>,
+    placements: HashMap<u32, ProcessPlacement>,
+    /// By container ID
+    inspected: HashMap<String, Inspection>,
+    retry_backoff: (Duration, Duration),
+    results: UnboundedSender<(String, Result<Option<RuntimeDetails>>)>,
+    pending_results: UnboundedReceiver<(String, Result<Option<RuntimeDetails>>)>,
+}
+
+enum Inspection {
+    InFlight(Backoff),
+    /// Retried once the backoff allows
+    Failed(Backoff),
+    /// `None` when the runtime doesn't know the container
+    Done(Option<RuntimeDetails>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct RuntimeDetails {
+    image: String,
+    labels: BTreeMap<String, String>,
+}
+
+impl ContainerResolver {
+    pub fn new(runtime_socket: Option<String>) -> Self {
+        let (results, pending_results) = unbounded_channel();
+        ContainerResolver {
+            runtime_socket,
+            placements: HashMap::new(),
+            inspected: HashMap::new(),
+            retry_backoff: (INSPECT_RETRY_INITIAL, INSPECT_RETRY_MAX),
+            results,
+            pending_results,
+        }
+    }
+
+    pub fn with_retry_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.retry_backoff = (initial, max);
+        self
+    }
+
+    pub fn resolve(&mut self, pid: u32) -> ProcessPlacement {
+        if let Some(mut placement) = self.placements.get(&pid).cloned() {
+            if let Some(container) = placement.container.as_mut().filter(|c| c.image.is_none()) {
+                self.inspect(container);
+                self.placements.insert(pid, placement.clone());
+            }
+            return placement;
+        }
+
+        let cgroup = fs::read_to_string(format!("/proc/{pid}/cgroup"))
+            .ok()
+            .and_then(|content| parse_cgroup(&content));
+        let mut container = cgroup
+            .as_deref()
+            .and_then(container_from_cgroup)
+            .or_else(|| {
+                let environ = fs::read(format!("/proc/{pid}/environ")).ok()?;
+                container_from_environ(&environ)
+            });
+        if let Some(container) = container.as_mut() {
+            self.inspect(container);
+        }
+
+        let placement = ProcessPlacement { cgroup, container };
+        self.placements.insert(pid, placement.clone());
+        placement
+    }
+
+    /// Forgets the processes `keep` returns false for. Containers stay cached.
+    pub fn retain(&mut self, keep: impl Fn(u32) -> bool) {
+        self.placements.retain(|pid, _| keep(*pid));
+    }
+
+    /// Fills in the image and labels if the runtime already answered, else starts a lookup on the
+    /// blocking pool unless one is running or a failed one is backing off.
+    fn inspect(&mut self, container: &mut ContainerInfo) {
+        let (Some(socket), Some(id)) = (self.runtime_socket.clone(), container.id.clone()) else {
+            return;
+        };
+        self.collect_inspections();
+
+        let backoff = match self.inspected.remove(&id) {
+            None => Backoff::new(self.retry_backoff.0, self.retry_backoff.1),
+            Some(Inspection::Failed(backoff)) if backoff.ready() => backoff,
+            Some(inspection) => {
+                if let Inspection::Done(Some(details)) = &inspection {
+                    container.image = Some(details.image.clone());
+                    container.labels = details.labels.clone();
+                }
+                self.inspected.insert(id, inspection);
+                return;
+            }
+        };
+
+        let results = self.results.clone();
+        let inspected_id = id.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = inspect_container(&socket, &inspected_id);
+            let _ = results.send((inspected_id, result));
+        });
+        self.inspected.insert(id, Inspection::InFlight(backoff));
+    }
+
+    /// Records the answers of finished lookups, only successful ones are kept for good.
+    fn collect_inspections(&mut self) {
+        while let Ok((id, result)) = self.pending_results.try_recv() {
+            let inspection = match result {
+                Ok(details) => Inspection::Done(details),
+                Err(err) => {
+                    eprintln!("Failed to inspect container {id}: {err:#}");
+                    let Some(Inspection::InFlight(mut backoff)) = self.inspected.remove(&id) else {
+                        continue;
+                    };
+                    backoff.failure();
+                    Inspection::Failed(backoff)
+                }
+            };
+            self.inspected.insert(id, inspection);
+        }
+    }
+}
+
+/// The cgroup v2 path, or for v1 the path that names a container, else the first one.
+fn parse_cgroup(content: &str) -> Option<String> {
+    let paths: Vec<(&str, &str)> = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let hierarchy = fields.next()?;
+            fields.next()?;
+            Some((hierarchy, fields.next()?))
+        })
+        .collect();
+
+    paths
+        .iter()
+        .find(|(hierarchy, _)| *hierarchy == "0")
+        .or_else(|| {
+            paths
+                .iter()
+                .find(|(_, path)| container_from_cgroup(path).is_some())
+        })
+        .or_else(|| paths.first())
+        .map(|(_, path)| path.to_string())
+}
+
+fn container_from_cgroup(path: &str) -> Option<ContainerInfo> {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+
+    for (index, component) in components.iter().enumerate().rev() {
+        let name = component.strip_suffix(".scope").unwrap_or(component);
+        for (prefix, runtime) in SCOPE_PREFIXES {
+            if let Some(id) = name.strip_prefix(prefix).filter(|id| is_container_id(id)) {
+                return Some(container(runtime, id));
+            }
+        }
+
+        // cgroupfs drivers use the bare ID, under a directory named after the runtime
+        if is_container_id(name) {
+            let runtime = match index.checked_sub(1).map(|parent| components[parent]) {
+                Some("docker") => "docker",
+                Some("libpod_parent") => "podman",
+                _ if components.iter().any(|c| c.starts_with("kubepods")) => "cri",
+                _ => continue,
+            };
+            return Some(container(runtime, name));
+        }
+    }
+    None
+}
+
+fn is_container_id(id: &str) -> bool {
+    id.len() == CONTAINER_ID_LENGTH && id.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn container(runtime: &str, id: &str) -> ContainerInfo {
+    ContainerInfo {
+        runtime: runtime.to_string(),
+        id: Some(id.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Singularity and Apptainer run in the caller's cgroup, but export the image they run.
+fn container_from_environ(environ: &[u8]) -> Option<ContainerInfo> {
+    environ.split(|byte| *byte == 0).find_map(|variable| {
+        let variable = std::str::from_utf8(variable).ok()?;
+        let (runtime, image) = if let Some(image) = variable.strip_prefix("APPTAINER_CONTAINER=") {
+            ("apptainer", image)
+        } else {
+            (
+                "singularity",
+                variable.strip_prefix("SINGULARITY_CONTAINER=")?,
+            )
+        };
+        Some(ContainerInfo {
+            runtime: runtime.to_string(),
+            id: None,
+            image: Some(image.to_string()),
+            labels: BTreeMap::new(),
+        })
+    })
+}
+
+#[derive(Deserialize)]
+struct InspectResponse {
+    #[serde(rename = "Config")]
+    config: InspectConfig,
+}
+
+#[derive(Deserialize)]
+struct InspectConfig {
+    #[serde(rename = "Image")]
+    image: String,
+    #[serde(rename = "Labels", default)]
+    labels: Option<BTreeMap<String, String>>,
+}
+
+/// Asks a Docker-compatible API for the image and labels of a container, `None` when it doesn't
+/// know the container.
+fn inspect_container(socket: &str, id: &str) -> Result<Option<RuntimeDetails>> {
+    let mut stream =
+        UnixStream::connect(socket).with_context(|| format!("Failed to connect to {socket}"))?;
+    stream.set_read_timeout(Some(RUNTIME_API_TIMEOUT))?;
+    stream.set_write_timeout(Some(RUNTIME_API_TIMEOUT))?;
+
+    // HTTP/1.0, so the response is neither chunked nor kept alive
+    write!(
+        stream,
+        "GET /containers/{id}/json HTTP/1.0\r\nHost: localhost\r\n\r\n"
+    )?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?;
+
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .context("Malformed response from the container runtime")?;
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    match status {
+        "200" => {}
+        "404" => return Ok(None),
+        status => bail!("Container runtime answered with status {status}"),
+    }
+
+    let inspected: InspectResponse = serde_json::from_str(body)?;
+    Ok(Some(RuntimeDetails {
+        image: inspected.config.image,
+        labels: inspected.config.labels.unwrap_or_default(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    const ID: &str = "3f4e2c1a9b8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3e2f";
+
+    #[test]
+    fn test_containers_from_cgroups() {
+        let docker_v2 = format!("0::/system.slice/docker-{ID}.scope\n");
+        let cgroup = parse_cgroup(&docker_v2).unwrap();
+        assert_eq!(
+            container_from_cgroup(&cgroup),
+            Some(container("docker", ID))
+        );
+
+        let docker_v1 = format!("12:pids:/docker/{ID}\n11:memory:/docker/{ID}\n1:name=systemd:/\n");
+        assert_eq!(parse_cgroup(&docker_v1), Some(format!("/docker/{ID}")));
+
+        let podman = format!("0::/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{ID}.scope/container\n");
+        assert_eq!(
+            container_from_cgroup(&parse_cgroup(&podman).unwrap()),
+            Some(container("podman", ID))
+        );
+
+        let kubernetes =
+            format!("0::/kubepods/burstable/pod7c9a4b2e-1f3d-4c5b-8a6e-9d0f1e2a3b4c/{ID}\n");
+        assert_eq!(
+            container_from_cgroup(&parse_cgroup(&kubernetes).unwrap()),
+            Some(container("cri", ID))
+        );
+
+        assert_eq!(
+            container_from_cgroup("/user.slice/user-1000.slice/session-2.scope"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_singularity_from_environ() {
+        let environ =
+            b"PATH=/usr/bin\0SINGULARITY_CONTAINER=/images/star_2.7.10b.sif\0HOME=/root\0";
+        let container = container_from_environ(environ).unwrap();
+        assert_eq!(container.runtime, "singularity");
+        assert_eq!(container.image.as_deref(), Some("/images/star_2.7.10b.sif"));
+        assert_eq!(container_from_environ(b"PATH=/usr/bin\0"), None);
+    }
+
+    /// Answers a single request on `listener` with `body`, returns the request.
+    fn serve_inspect(listener: UnixListener) -> std::thread::JoinHandle<Result<String>> {
+        std::thread::spawn(move || -> Result<String> {
+            let (mut stream, _) = listener.accept()?;
+            let mut request = [0u8; 1024];
+            let len = stream.read(&mut request)?;
+            let body = r#"{"Id":"x","Config":{"Image":"quay.io/biocontainers/star:2.7.10b","Labels":{"nextflow.io/taskName":"STAR_ALIGN"}}}"#;
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{body}"
+            )?;
+            Ok(String::from_utf8_lossy(&request[..len]).into_owned())
+        })
+    }
+
+    /// Inspects `info` until the runtime's answer shows up or a second passed.
+    async fn inspect_until_answered(resolver: &mut ContainerResolver, info: &mut ContainerInfo) {
+        for _ in 0..100 {
+            resolver.inspect(info);
+            if info.image.is_some() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inspects_container_through_runtime_socket() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        let runtime = serve_inspect(UnixListener::bind(&socket)?);
+
+        let mut resolver = ContainerResolver::new(Some(socket.to_str().unwrap().to_string()));
+        let mut info = container("docker", ID);
+        // the lookup runs in the background
+        resolver.inspect(&mut info);
+        assert_eq!(info.image, None);
+        inspect_until_answered(&mut resolver, &mut info).await;
+
+        let request = runtime.join().unwrap()?;
+        assert!(request.starts_with(&format!("GET /containers/{ID}/json ")));
+        assert_eq!(
+            info.image.as_deref(),
+            Some("quay.io/biocontainers/star:2.7.10b")
+        );
+        assert_eq!(info.labels["nextflow.io/taskName"], "STAR_ALIGN");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_inspections_are_retried() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        let mut resolver = ContainerResolver::new(Some(socket.to_str().unwrap().to_string()))
+            .with_retry_backoff(Duration::ZERO, Duration::ZERO);
+
+        // the runtime isn't up yet
+        let mut info = container("docker", ID);
+        resolver.inspect(&mut info);
+        for _ in 0..100 {
+            resolver.collect_inspections();
+            if matches!(resolver.inspected.get(ID), Some(Inspection::Failed(_))) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(matches!(
+            resolver.inspected.get(ID),
+            Some(Inspection::Failed(_))
+        ));
+
+        let runtime = serve_inspect(UnixListener::bind(&socket)?);
+        inspect_until_answered(&mut resolver, &mut info).await;
+        runtime.join().unwrap()?;
+        assert_eq!(
+            info.image.as_deref(),
+            Some("quay.io/biocontainers/star:2.7.10b")
+        );
+        Ok(())
+    }
+}
@@ -1,34 +1,73 @@
-// #include <linux/bpf.h>
-// #include <linux/ptrace.h>
-// #include <bpf/bpf_helpers.h>
+// src/extracts/ebpf/mod.rs
+//! Exec and exit events of all processes, streamed from kernel tracepoints instead of polled.
+//!
+//! Polling misses tools that live shorter than the polling interval. With the `ebpf` feature the
+//! daemon attaches to `sched_process_exec` and `sched_process_exit` and gets every one of them,
+//! with exact timestamps and exit statuses. Without the feature, or without the privileges to
+//! load the program, the proc connector in [`crate::extracts::proc_connector`] is the next best
+//! source, and polling is all there is without either.
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
-// struct event {
-//     u32 pid;
-//     char comm[16];
-//     char filename[256];
-// };
+use crate::extracts::proc_connector::ProcConnectorSource;
 
-// struct {
-//     __uint(type, BPF_MAP_TYPE_PERF_EVENT_ARRAY);
-//     __uint(key_size, sizeof(int));
-//     __uint(value_size, sizeof(u32));
-//     __uint(max_entries, 1024);
-// } events SEC(".maps");
+mod replay;
+#[cfg(feature = "ebpf")]
+mod tracepoints;
 
-// SEC("tracepoint/syscalls/sys_enter_execve")
-// int trace_execve(struct trace_event_raw_sys_enter *ctx)
-// {
-//     struct event event = {};
+pub use replay::ReplaySource;
+#[cfg(feature = "ebpf")]
+pub use tracepoints::TracepointSource;
 
-//     event.pid = bpf_get_current_pid_tgid() >> 32;
-//     bpf_get_current_comm(&event.comm, sizeof(event.comm));
+/// One line of a replay file, e.g.
+/// `{"kind":"exec","pid":42,"ppid":1,"name":"bwa","argv":["bwa","mem"],"timestamp":"..."}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProcessEvent {
+    Exec {
+        pid: u32,
+        ppid: u32,
+        /// The kernel's command name, truncated to 15 characters like the one sysinfo reports
+        name: String,
+        argv: Vec<String>,
+        timestamp: DateTime<Utc>,
+    },
+    Exit {
+        pid: u32,
+        /// The `waitpid` status word
+        wait_status: i32,
+        timestamp: DateTime<Utc>,
+    },
+}
 
-//     const char *filename = (const char *)ctx->args[0];
-//     bpf_probe_read_user_str(event.filename, sizeof(event.filename), filename);
+pub trait ProcessEventSource: Send + Sync {
+    /// The events since the last call, oldest first.
+    fn drain(&mut self) -> Result<Vec<ProcessEvent>>;
+}
 
-//     bpf_perf_event_output(ctx, &events, BPF_F_CURRENT_CPU, &event, sizeof(event));
+/// The replay file if one is configured, otherwise the kernel tracepoints when the feature is
+/// enabled and the program loads, otherwise the proc connector. `None` leaves the watcher to
+/// polling alone.
+pub fn open_event_source(replay_file: Option<&str>) -> Option<Box<dyn ProcessEventSource>> {
+    if let Some(replay_file) = replay_file {
+        match ReplaySource::from_file(replay_file) {
+            Ok(source) => return Some(Box::new(source)),
+            Err(err) => eprintln!("Failed to read process events to replay: {err:#}"),
+        }
+    }
 
-//     return 0;
-// }
+    #[cfg(feature = "ebpf")]
+    match TracepointSource::load() {
+        Ok(source) => return Some(Box::new(source)),
+        Err(err) => eprintln!("Failed to attach eBPF tracepoints: {err:#}"),
+    }
 
-// char LICENSE[] SEC("license") = "GPL";
+    match ProcConnectorSource::open() {
+        Ok(source) => Some(Box::new(source)),
+        Err(err) => {
+            eprintln!("Failed to listen to the proc connector, polling processes: {err:#}");
+            None
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: GPL-2.0
+// Exec and exit events of all processes, read by src/extracts/ebpf/tracepoints.rs. The layout of
+// `struct event` has to match `RawEvent` there.
+#include "vmlinux.h"
+#include <bpf/bpf_core_read.h>
+#include <bpf/bpf_helpers.h>
+
+#define EVENT_EXEC 0
+#define EVENT_EXIT 1
+#define COMM_SIZE 16
+#define ARGS_SIZE 1024
+
+struct event {
+    __u32 kind;
+    __u32 pid;
+    __u32 ppid;
+    __s32 wait_status;
+    __u64 timestamp_ns;
+    __u32 args_size;
+    char comm[COMM_SIZE];
+    // NUL-separated argv, truncated to ARGS_SIZE
+    char args[ARGS_SIZE];
+};
+
+struct {
+    __uint(type, BPF_MAP_TYPE_RINGBUF);
+    __uint(max_entries, 1 << 22);
+} EVENTS SEC(".maps");
+
+static struct event *reserve_event(__u32 kind, struct task_struct *task)
+{
+    struct event *event = bpf_ringbuf_reserve(&EVENTS, sizeof(*event), 0);
+    if (!event)
+        return NULL;
+
+    event->kind = kind;
+    event->pid = bpf_get_current_pid_tgid() >> 32;
+    event->ppid = BPF_CORE_READ(task, real_parent, tgid);
+    event->wait_status = 0;
+    // the boot clock, so user space can convert it with the uptime
+    event->timestamp_ns = bpf_ktime_get_boot_ns();
+    event->args_size = 0;
+    bpf_get_current_comm(&event->comm, sizeof(event->comm));
+    return event;
+}
+
+SEC("tracepoint/sched/sched_process_exec")
+int handle_exec(void *ctx)
+{
+    struct task_struct *task = (struct task_struct *)bpf_get_current_task();
+    struct event *event = reserve_event(EVENT_EXEC, task);
+    if (!event)
+        return 0;
+
+    unsigned long arg_start = BPF_CORE_READ(task, mm, arg_start);
+    unsigned long arg_end = BPF_CORE_READ(task, mm, arg_end);
+    unsigned long size = arg_end - arg_start;
+    if (size >= ARGS_SIZE)
+        size = ARGS_SIZE - 1;
+    // the mask keeps the verifier convinced of the bound
+    size &= ARGS_SIZE - 1;
+    if (bpf_probe_read_user(&event->args, size, (const void *)arg_start) == 0)
+        event->args_size = size;
+
+    bpf_ringbuf_submit(event, 0);
+    return 0;
+}
+
+SEC("tracepoint/sched/sched_process_exit")
+int handle_exit(void *ctx)
+{
+    __u64 pid_tgid = bpf_get_current_pid_tgid();
+    // threads exiting aren't of interest, only the thread group leader
+    if ((__u32)(pid_tgid >> 32) != (__u32)pid_tgid)
+        return 0;
+
+    struct task_struct *task = (struct task_struct *)bpf_get_current_task();
+    struct event *event = reserve_event(EVENT_EXIT, task);
+    if (!event)
+        return 0;
+
+    event->wait_status = BPF_CORE_READ(task, exit_code);
+    bpf_ringbuf_submit(event, 0);
+    return 0;
+}
+
+char LICENSE[] SEC("license") = "GPL";
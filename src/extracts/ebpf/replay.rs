@@ -0,0 +1,71 @@
+// src/extracts/ebpf/replay.rs
+use anyhow::{Context, Result};
+use std::fs;
+
+use super::{ProcessEvent, ProcessEventSource};
+
+/// Replays recorded events, for tests and machines where the program can't be loaded. All events
+/// are handed out on the first drain.
+pub struct ReplaySource {
+    events: Vec<ProcessEvent>,
+}
+
+impl ReplaySource {
+    pub fn new(events: Vec<ProcessEvent>) -> Self {
+        ReplaySource { events }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+        let events = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid process event on line {}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(ReplaySource::new(events))
+    }
+}
+
+impl ProcessEventSource for ReplaySource {
+    fn drain(&mut self) -> Result<Vec<ProcessEvent>> {
+        Ok(std::mem::take(&mut self.events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_replays_recorded_events_once() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"{{"kind":"exec","pid":42,"ppid":1,"name":"bwa","argv":["bwa","mem"],"timestamp":"2025-03-01T10:00:00Z"}}"#
+        )?;
+        writeln!(file)?;
+        writeln!(
+            file,
+            r#"{{"kind":"exit","pid":42,"wait_status":256,"timestamp":"2025-03-01T10:00:00.000400Z"}}"#
+        )?;
+
+        let mut source = ReplaySource::from_file(file.path().to_str().unwrap())?;
+        let events = source.drain()?;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], ProcessEvent::Exec { pid: 42, argv, .. } if argv[1] == "mem"));
+        assert!(matches!(
+            events[1],
+            ProcessEvent::Exit {
+                wait_status: 256,
+                ..
+            }
+        ));
+        assert!(source.drain()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+// src/extracts/ebpf/tracepoints.rs
+use anyhow::{Context, Result};
+use aya::{
+    include_bytes_aligned,
+    maps::{MapData, RingBuf},
+    programs::TracePoint,
+    Ebpf,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::{ProcessEvent, ProcessEventSource};
+use crate::extracts::procfs;
+
+/// Built from `process_events.bpf.c` by the build script.
+static PROGRAM: &[u8] = include_bytes_aligned!(concat!(env!("OUT_DIR"), "/process_events.bpf.o"));
+
+const EVENT_EXEC: u32 = 0;
+const EVENT_EXIT: u32 = 1;
+const COMM_SIZE: usize = 16;
+const ARGS_SIZE: usize = 1024;
+
+/// Mirrors `struct event` in the program.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawEvent {
+    kind: u32,
+    pid: u32,
+    ppid: u32,
+    wait_status: i32,
+    timestamp_ns: u64,
+    args_size: u32,
+    comm: [u8; COMM_SIZE],
+    args: [u8; ARGS_SIZE],
+}
+
+pub struct TracepointSource {
+    // the programs stay attached for as long as this is alive
+    _ebpf: Ebpf,
+    events: RingBuf<MapData>,
+    boot_time: DateTime<Utc>,
+}
+
+impl TracepointSource {
+    pub fn load() -> Result<Self> {
+        let boot_time = procfs::boot_time().context("Failed to read the boot time")?;
+        let mut ebpf = Ebpf::load(PROGRAM).context("Failed to load the eBPF program")?;
+
+        for (name, tracepoint) in [
+            ("handle_exec", "sched_process_exec"),
+            ("handle_exit", "sched_process_exit"),
+        ] {
+            let program: &mut TracePoint = ebpf
+                .program_mut(name)
+                .with_context(|| format!("Program {name} is missing"))?
+                .try_into()?;
+            program.load()?;
+            program
+                .attach("sched", tracepoint)
+                .with_context(|| format!("Failed to attach to {tracepoint}"))?;
+        }
+
+        let events = RingBuf::try_from(ebpf.take_map("EVENTS").context("Map EVENTS is missing")?)?;
+        Ok(TracepointSource {
+            _ebpf: ebpf,
+            events,
+            boot_time,
+        })
+    }
+}
+
+impl ProcessEventSource for TracepointSource {
+    fn drain(&mut self) -> Result<Vec<ProcessEvent>> {
+        let mut events = vec![];
+        while let Some(record) = self.events.next() {
+            if let Some(event) = parse_event(&record, self.boot_time) {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+}
+
+fn parse_event(record: &[u8], boot_time: DateTime<Utc>) -> Option<ProcessEvent> {
+    if record.len() < size_of::<RawEvent>() {
+        return None;
+    }
+    // SAFETY: the length is checked and every bit pattern is a valid `RawEvent`
+    let raw = unsafe { std::ptr::read_unaligned(record.as_ptr() as *const RawEvent) };
+    let timestamp = boot_time + TimeDelta::nanoseconds(raw.timestamp_ns as i64);
+
+    match raw.kind {
+        EVENT_EXEC => {
+            let args = &raw.args[..(raw.args_size as usize).min(ARGS_SIZE)];
+            Some(ProcessEvent::Exec {
+                pid: raw.pid,
+                ppid: raw.ppid,
+                name: c_string(&raw.comm),
+                argv: args
+                    .split(|byte| *byte == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect(),
+                timestamp,
+            })
+        }
+        EVENT_EXIT => Some(ProcessEvent::Exit {
+            pid: raw.pid,
+            wait_status: raw.wait_status,
+            timestamp,
+        }),
+        _ => None,
+    }
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exec_event() {
+        let mut raw = RawEvent {
+            kind: EVENT_EXEC,
+            pid: 42,
+            ppid: 1,
+            wait_status: 0,
+            timestamp_ns: 1_500_000,
+            args_size: 12,
+            comm: [0; COMM_SIZE],
+            args: [0; ARGS_SIZE],
+        };
+        raw.comm[..3].copy_from_slice(b"bwa");
+        raw.args[..12].copy_from_slice(b"bwa\0mem\0-t\x008");
+        // SAFETY: plain old data
+        let record = unsafe {
+            std::slice::from_raw_parts(&raw as *const RawEvent as *const u8, size_of::<RawEvent>())
+        };
+
+        let boot_time = Utc::now();
+        assert_eq!(
+            parse_event(record, boot_time),
+            Some(ProcessEvent::Exec {
+                pid: 42,
+                ppid: 1,
+                name: "bwa".to_string(),
+                argv: vec!["bwa".into(), "mem".into(), "-t".into(), "8".into()],
+                timestamp: boot_time + TimeDelta::microseconds(1500),
+            })
+        );
+        assert_eq!(parse_event(&record[..8], boot_time), None);
+    }
+}
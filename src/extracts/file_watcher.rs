@@ -8,6 +8,7 @@ use lazy_static::lazy_static;
 use predicates::prelude::predicate;
 use predicates::str::RegexPredicate;
 use predicates::Predicate;
+use serde::{Deserialize, Serialize};
 
 use crate::utils::debug_log::Logger;
 use crate::utils::upload::upload_from_file_path;
@@ -30,9 +31,19 @@ pub struct FileInfo {
     pub last_update: DateTime<Utc>,
 }
 
+/// Outcome of the last `FileWatcher::poll_files` call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePollStatus {
+    pub timestamp: DateTime<Utc>,
+    pub files_found: usize,
+    pub files_watched: usize,
+    pub error: Option<String>,
+}
+
 pub struct FileWatcher {
     watched_files: HashMap<String, WatchedFileInfo>,
     all_files: HashMap<String, FileInfo>,
+    last_poll: Option<FilePollStatus>,
 }
 
 pub enum FilePattern {
@@ -105,6 +116,7 @@ impl FileWatcher {
         Self {
             watched_files: HashMap::new(),
             all_files: HashMap::new(),
+            last_poll: None,
         }
     }
 
@@ -347,14 +359,12 @@ impl FileWatcher {
 
     pub fn prepare_cache_directory(&self, file_cache_dir: &str) -> Result<()> {
         let path = Path::new(file_cache_dir);
-        if path.exists() {
-            fs::remove_dir_all(path).with_context(|| {
-                format!(
-                    "Failed to remove existing cache directory: {}",
-                    file_cache_dir
-                )
-            })?;
-        }
+        remove_cached_files(file_cache_dir).with_context(|| {
+            format!(
+                "Failed to clear existing cache directory: {}",
+                file_cache_dir
+            )
+        })?;
 
         fs::create_dir_all(path)
             .with_context(|| format!("Failed to create cache directory: {}", file_cache_dir))?;
@@ -404,6 +414,10 @@ impl FileWatcher {
         path.and_then(|p| self.all_files.get(p).map(|info| (p, info)))
     }
 
+    pub fn get_last_poll_status(&self) -> Option<&FilePollStatus> {
+        self.last_poll.as_ref()
+    }
+
     pub async fn poll_files(
         &mut self,
         service_url: &str,
@@ -411,6 +425,34 @@ impl FileWatcher {
         workflow_directory: &str,
         file_cache_dir: &str,
         new_size_duration: TimeDelta,
+    ) -> Result<()> {
+        let result = self
+            .poll_files_inner(
+                service_url,
+                api_key,
+                workflow_directory,
+                file_cache_dir,
+                new_size_duration,
+            )
+            .await;
+
+        self.last_poll = Some(FilePollStatus {
+            timestamp: Utc::now(),
+            files_found: self.all_files.len(),
+            files_watched: self.watched_files.len(),
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+        });
+
+        result
+    }
+
+    async fn poll_files_inner(
+        &mut self,
+        service_url: &str,
+        api_key: &str,
+        workflow_directory: &str,
+        file_cache_dir: &str,
+        new_size_duration: TimeDelta,
     ) -> Result<()> {
         let logger = Logger::new();
         let mut to_upload: Vec<WatchedFileInfo> = Vec::new();
@@ -515,6 +557,23 @@ impl FileWatcher {
     }
 }
 
+/// Removes the files cached in `file_cache_dir`. Subdirectories, like the export spool, are
+/// kept so batches waiting there outlive the daemon.
+pub fn remove_cached_files(file_cache_dir: &str) -> Result<()> {
+    let entries = match fs::read_dir(file_cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::Days;
@@ -571,4 +630,19 @@ mod tests {
 
         assert!(file_watcher.check_if_file_to_update(Some(&old_file_info), Some(&new_file_info)));
     }
+
+    #[test]
+    fn test_prepare_cache_directory_keeps_subdirectories() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache_dir = dir.path().join("cache");
+        fs::create_dir_all(cache_dir.join("spool"))?;
+        fs::write(cache_dir.join("spool/segment-0000000000.jsonl"), "{}\n")?;
+        fs::write(cache_dir.join("Xq3vT9aLm2Pz"), "cached")?;
+
+        FileWatcher::new().prepare_cache_directory(cache_dir.to_str().unwrap())?;
+
+        assert!(!cache_dir.join("Xq3vT9aLm2Pz").exists());
+        assert!(cache_dir.join("spool/segment-0000000000.jsonl").exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,195 @@
+// src/extracts/metrics/cpu.rs
+//! CPU time counters from `/proc/stat`, which are turned into shares between two readings, and
+//! the current core frequencies.
+use std::{collections::BTreeMap, fs};
+
+use crate::types::event::attributes::system_metrics::CpuCoreUsage;
+
+/// Clock ticks a CPU spent in the states we report.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct CpuTicks {
+    busy: u64,
+    idle: u64,
+    iowait: u64,
+    steal: u64,
+}
+
+impl CpuTicks {
+    fn total(&self) -> u64 {
+        self.busy + self.idle + self.iowait + self.steal
+    }
+
+    /// Percentages of busy, iowait and steal time from `previous` to these ticks.
+    fn shares_since(&self, previous: &CpuTicks) -> (f64, f64, f64) {
+        let total = self.total().saturating_sub(previous.total());
+        if total == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+        let share =
+            |now: u64, before: u64| now.saturating_sub(before) as f64 * 100.0 / total as f64;
+        (
+            share(self.busy, previous.busy),
+            share(self.iowait, previous.iowait),
+            share(self.steal, previous.steal),
+        )
+    }
+}
+
+/// One reading of `/proc/stat`.
+pub struct CpuTimes {
+    all: CpuTicks,
+    cores: BTreeMap<usize, CpuTicks>,
+}
+
+/// Shares of the whole machine and of each core.
+pub struct CpuShares {
+    pub iowait: f64,
+    pub steal: f64,
+    pub cores: Vec<CpuCoreUsage>,
+}
+
+impl CpuTimes {
+    pub fn read() -> Option<Self> {
+        parse_stat(&fs::read_to_string("/proc/stat").ok()?)
+    }
+
+    /// Cores that went offline in between are left out.
+    pub fn shares_since(&self, previous: &CpuTimes) -> CpuShares {
+        let frequencies = core_frequencies(self.cores.keys().copied());
+        let (_, iowait, steal) = self.all.shares_since(&previous.all);
+        let cores = self
+            .cores
+            .iter()
+            .filter_map(|(core, ticks)| {
+                let (utilization, iowait, steal) = ticks.shares_since(previous.cores.get(core)?);
+                Some(CpuCoreUsage {
+                    core: *core,
+                    utilization,
+                    iowait,
+                    steal,
+                    frequency_mhz: frequencies.get(core).copied(),
+                })
+            })
+            .collect();
+        CpuShares {
+            iowait,
+            steal,
+            cores,
+        }
+    }
+}
+
+fn parse_stat(content: &str) -> Option<CpuTimes> {
+    let mut all = None;
+    let mut cores = BTreeMap::new();
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(cpu) = fields.next().and_then(|name| name.strip_prefix("cpu")) else {
+            continue;
+        };
+        // user nice system idle iowait irq softirq steal, guest time is part of user already
+        let ticks: Vec<u64> = fields.take(8).filter_map(|v| v.parse().ok()).collect();
+        let [user, nice, system, idle, iowait, irq, softirq, steal] = ticks[..] else {
+            continue;
+        };
+        let ticks = CpuTicks {
+            busy: user + nice + system + irq + softirq,
+            idle,
+            iowait,
+            steal,
+        };
+        if cpu.is_empty() {
+            all = Some(ticks);
+        } else if let Ok(core) = cpu.parse() {
+            cores.insert(core, ticks);
+        }
+    }
+    Some(CpuTimes { all: all?, cores })
+}
+
+/// Current frequencies of `cores` in MHz, from cpufreq or else `/proc/cpuinfo`.
+fn core_frequencies(cores: impl Iterator<Item = usize>) -> BTreeMap<usize, f64> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let from_cpuinfo = parse_cpuinfo_frequencies(&cpuinfo);
+    cores
+        .filter_map(|core| {
+            let path = format!("/sys/devices/system/cpu/cpu{core}/cpufreq/scaling_cur_freq");
+            let from_cpufreq = fs::read_to_string(path)
+                .ok()
+                .and_then(|khz| khz.trim().parse::<f64>().ok())
+                .map(|khz| khz / 1000.0);
+            Some((
+                core,
+                from_cpufreq.or_else(|| from_cpuinfo.get(&core).copied())?,
+            ))
+        })
+        .collect()
+}
+
+fn parse_cpuinfo_frequencies(cpuinfo: &str) -> BTreeMap<usize, f64> {
+    let mut frequencies = BTreeMap::new();
+    let mut core = None;
+    for line in cpuinfo.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "processor" => core = value.trim().parse().ok(),
+            "cpu MHz" => {
+                if let (Some(core), Ok(mhz)) = (core, value.trim().parse()) {
+                    frequencies.insert(core, mhz);
+                }
+            }
+            _ => {}
+        }
+    }
+    frequencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shares_between_readings() {
+        let before = parse_stat(
+            "cpu  1000 0 200 3000 100 0 0 50 0 0
+cpu0 900 0 100 900 50 0 0 25 0 0
+cpu1 100 0 100 2100 50 0 0 25 0 0
+intr 2483501 0 0
+ctxt 2846251
+",
+        )
+        .unwrap();
+        // cpu0 busy, cpu1 waiting for IO and stolen from
+        let after = parse_stat(
+            "cpu  1100 0 200 3000 160 0 0 90 0 0
+cpu0 1000 0 100 900 50 0 0 25 0 0
+cpu1 100 0 100 2100 110 0 0 65 0 0
+cpu2 5 0 5 90 0 0 0 0 0 0
+",
+        )
+        .unwrap();
+
+        let shares = after.shares_since(&before);
+        assert_eq!(shares.iowait, 30.0);
+        assert_eq!(shares.steal, 20.0);
+        // cpu2 came online in between
+        assert_eq!(shares.cores.len(), 2);
+        assert_eq!(shares.cores[0].utilization, 100.0);
+        assert_eq!(shares.cores[1].utilization, 0.0);
+        assert_eq!(shares.cores[1].iowait, 60.0);
+        assert_eq!(shares.cores[1].steal, 40.0);
+    }
+
+    #[test]
+    fn test_parse_cpuinfo_frequencies() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: AMD EPYC\ncpu MHz\t\t: 2000.000\n\nprocessor\t: 1\ncpu MHz\t\t: 3499.998\n";
+        assert_eq!(
+            parse_cpuinfo_frequencies(cpuinfo),
+            BTreeMap::from([(0, 2000.0), (1, 3499.998)])
+        );
+        // arm64 has no frequencies in cpuinfo
+        assert!(parse_cpuinfo_frequencies("processor\t: 0\nBogoMIPS\t: 50.00\n").is_empty());
+    }
+}
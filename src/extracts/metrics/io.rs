@@ -0,0 +1,369 @@
+// src/extracts/metrics/io.rs
+//! Counters of block devices, NFS mounts and network interfaces, which are turned into rates
+//! between two readings.
+use std::{collections::HashMap, fs, time::Instant};
+
+use crate::types::event::attributes::system_metrics::{DeviceIo, NetworkInterfaceIo};
+
+/// `/proc/diskstats` counts 512 byte sectors, whatever the sector size of the device.
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct DeviceCounters {
+    read_bytes: u64,
+    write_bytes: u64,
+    reads: u64,
+    writes: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct InterfaceCounters {
+    rx_bytes: u64,
+    tx_bytes: u64,
+    rx_errors: u64,
+    tx_errors: u64,
+    rx_dropped: u64,
+    tx_dropped: u64,
+}
+
+/// One reading of all counters.
+pub struct IoCounters {
+    taken_at: Instant,
+    devices: HashMap<String, DeviceCounters>,
+    interfaces: HashMap<String, InterfaceCounters>,
+    mount_points: HashMap<String, Vec<String>>,
+}
+
+impl IoCounters {
+    pub fn read() -> Self {
+        let read = |path: &str| fs::read_to_string(path).unwrap_or_default();
+
+        let mut devices = parse_diskstats(&read("/proc/diskstats"));
+        devices.extend(parse_nfs_mountstats(&read("/proc/self/mountstats")));
+        IoCounters {
+            taken_at: Instant::now(),
+            devices,
+            interfaces: parse_net_dev(&read("/proc/net/dev")),
+            mount_points: parse_mount_points(&read("/proc/self/mounts")),
+        }
+    }
+
+    /// Rates of the devices present in both readings.
+    pub fn device_rates_since(&self, previous: &IoCounters) -> HashMap<String, DeviceIo> {
+        let Some(rate) = self.rate_since(previous) else {
+            return HashMap::new();
+        };
+        self.devices
+            .iter()
+            .filter_map(|(name, now)| {
+                let before = previous.devices.get(name)?;
+                let io = DeviceIo {
+                    mount_points: self.mount_points.get(name).cloned().unwrap_or_default(),
+                    read_bytes_per_sec: rate(now.read_bytes, before.read_bytes),
+                    write_bytes_per_sec: rate(now.write_bytes, before.write_bytes),
+                    read_ops_per_sec: rate(now.reads, before.reads),
+                    write_ops_per_sec: rate(now.writes, before.writes),
+                };
+                Some((name.clone(), io))
+            })
+            .collect()
+    }
+
+    /// Rates of the interfaces present in both readings.
+    pub fn network_rates_since(
+        &self,
+        previous: &IoCounters,
+    ) -> HashMap<String, NetworkInterfaceIo> {
+        let Some(rate) = self.rate_since(previous) else {
+            return HashMap::new();
+        };
+        self.interfaces
+            .iter()
+            .filter_map(|(name, now)| {
+                let before = previous.interfaces.get(name)?;
+                let io = NetworkInterfaceIo {
+                    rx_bytes_per_sec: rate(now.rx_bytes, before.rx_bytes),
+                    tx_bytes_per_sec: rate(now.tx_bytes, before.tx_bytes),
+                    rx_errors: now.rx_errors.saturating_sub(before.rx_errors),
+                    tx_errors: now.tx_errors.saturating_sub(before.tx_errors),
+                    rx_dropped: now.rx_dropped.saturating_sub(before.rx_dropped),
+                    tx_dropped: now.tx_dropped.saturating_sub(before.tx_dropped),
+                };
+                Some((name.clone(), io))
+            })
+            .collect()
+    }
+
+    /// Per second change of a counter. Counters that went backwards, because the device was
+    /// replaced or the counter wrapped, count as unchanged.
+    fn rate_since(&self, previous: &IoCounters) -> Option<impl Fn(u64, u64) -> f64> {
+        let seconds = self
+            .taken_at
+            .checked_duration_since(previous.taken_at)?
+            .as_secs_f64();
+        (seconds > 0.0)
+            .then_some(move |now: u64, before: u64| now.saturating_sub(before) as f64 / seconds)
+    }
+}
+
+/// Devices that never did any IO, like unused loop devices, are left out.
+fn parse_diskstats(content: &str) -> HashMap<String, DeviceCounters> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let name = *fields.get(2)?;
+            let counter = |index: usize| fields.get(index)?.parse::<u64>().ok();
+            let counters = DeviceCounters {
+                reads: counter(3)?,
+                read_bytes: counter(5)? * SECTOR_SIZE,
+                writes: counter(7)?,
+                write_bytes: counter(9)? * SECTOR_SIZE,
+            };
+            (counters.reads + counters.writes > 0).then(|| (name.to_string(), counters))
+        })
+        .collect()
+}
+
+/// NFS mounts by export. The byte counts are what went over the wire, which can be less than
+/// what was read thanks to the page cache.
+fn parse_nfs_mountstats(content: &str) -> HashMap<String, DeviceCounters> {
+    let mut exports: HashMap<String, DeviceCounters> = HashMap::new();
+    let mut current = None;
+    for line in content.lines() {
+        if let Some(mount) = line.strip_prefix("device ") {
+            // device <export> mounted on <mount point> with fstype <type> ...
+            let fields: Vec<&str> = mount.split_whitespace().collect();
+            let is_nfs = fields
+                .iter()
+                .position(|field| *field == "fstype")
+                .and_then(|index| fields.get(index + 1))
+                .is_some_and(|fstype| fstype.starts_with("nfs"));
+            // the same export mounted twice shares its statistics, count them once
+            current = match fields.first() {
+                Some(export) if is_nfs && !exports.contains_key(*export) => {
+                    exports.insert(export.to_string(), DeviceCounters::default());
+                    Some(export.to_string())
+                }
+                _ => None,
+            };
+            continue;
+        }
+        let Some(counters) = current.as_ref().and_then(|export| exports.get_mut(export)) else {
+            continue;
+        };
+
+        let line = line.trim();
+        let Some((key, values)) = line.split_once(':') else {
+            continue;
+        };
+        let values: Vec<u64> = values
+            .split_whitespace()
+            .filter_map(|value| value.parse().ok())
+            .collect();
+        match key {
+            // normal, direct and server reads and writes, then pages
+            "bytes" if values.len() >= 6 => {
+                counters.read_bytes = values[4];
+                counters.write_bytes = values[5];
+            }
+            "READ" => counters.reads = values.first().copied().unwrap_or_default(),
+            "WRITE" => counters.writes = values.first().copied().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    exports
+}
+
+fn parse_net_dev(content: &str) -> HashMap<String, InterfaceCounters> {
+    content
+        .lines()
+        // two header lines
+        .skip(2)
+        .filter_map(|line| {
+            let (name, counters) = line.split_once(':')?;
+            let name = name.trim();
+            if name == "lo" {
+                return None;
+            }
+            let fields: Vec<u64> = counters
+                .split_whitespace()
+                .filter_map(|value| value.parse().ok())
+                .collect();
+            let counters = InterfaceCounters {
+                rx_bytes: *fields.first()?,
+                rx_errors: *fields.get(2)?,
+                rx_dropped: *fields.get(3)?,
+                tx_bytes: *fields.get(8)?,
+                tx_errors: *fields.get(10)?,
+                tx_dropped: *fields.get(11)?,
+            };
+            Some((name.to_string(), counters))
+        })
+        .collect()
+}
+
+/// Mount points by the name the device has in `/proc/diskstats`, or by export for NFS.
+fn parse_mount_points(mounts: &str) -> HashMap<String, Vec<String>> {
+    let mut mount_points: HashMap<String, Vec<String>> = HashMap::new();
+    for line in mounts.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(device), Some(mount_point), Some(fstype)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+
+        let name = if device.starts_with("/dev/") {
+            // `/dev/mapper/*` and `/dev/disk/by-*/*` link to the name the kernel uses
+            let path = fs::canonicalize(device).unwrap_or_else(|_| device.into());
+            match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            }
+        } else if fstype.starts_with("nfs") {
+            device.to_string()
+        } else {
+            continue;
+        };
+        mount_points
+            .entry(name)
+            .or_default()
+            .push(unescape_mount_point(mount_point));
+    }
+    mount_points
+}
+
+/// Spaces and other separators are octal escaped in `/proc/self/mounts`.
+fn unescape_mount_point(mount_point: &str) -> String {
+    let mut unescaped = Vec::with_capacity(mount_point.len());
+    let mut bytes = mount_point.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'\\' {
+            let octal: String = bytes.by_ref().take(3).map(char::from).collect();
+            match u8::from_str_radix(&octal, 8) {
+                Ok(byte) => unescaped.push(byte),
+                Err(_) => unescaped.extend(format!("\\{octal}").bytes()),
+            }
+        } else {
+            unescaped.push(byte);
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const MOUNTSTATS: &str = "device proc mounted on /proc with fstype proc
+device fileserver:/references mounted on /mnt/references with fstype nfs4 statvers=1.1
+\topts:\trw,vers=4.2,rsize=1048576,wsize=1048576
+\tevents:\t1 2 3
+\tbytes:\t4000000 1000 0 0 3000000 1000 700 1
+\tRPC iostats version: 1.1  p/v: 100003/4 (nfs)
+\tper-op statistics
+\t        NULL: 0 0 0 0 0 0 0 0
+\t        READ: 30 30 0 4800 3000000 10 200 210 0
+\t       WRITE: 1 1 0 1200 100 1 2 3 0
+device fileserver:/references mounted on /scratch/references with fstype nfs4 statvers=1.1
+\tbytes:\t999 999 0 0 999 999 0 0
+";
+
+    #[test]
+    fn test_parse_counters() {
+        let diskstats = "   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+ 259       0 nvme0n1 1000 10 80000 500 200 5 16000 300 0 700 800 0 0 0 0 0 0
+ 259       1 nvme0n1p1 900 10 72000 450 200 5 16000 300 0 650 750 0 0 0 0 0 0
+";
+        let devices = parse_diskstats(diskstats);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(
+            devices["nvme0n1"],
+            DeviceCounters {
+                read_bytes: 80000 * 512,
+                write_bytes: 16000 * 512,
+                reads: 1000,
+                writes: 200,
+            }
+        );
+
+        let exports = parse_nfs_mountstats(MOUNTSTATS);
+        assert_eq!(exports.len(), 1);
+        assert_eq!(
+            exports["fileserver:/references"],
+            DeviceCounters {
+                read_bytes: 3000000,
+                write_bytes: 1000,
+                reads: 30,
+                writes: 1,
+            }
+        );
+
+        let net_dev = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 115135407   52102    0    0    0     0          0         0 115135407   52102    0    0    0     0       0          0
+  eth0: 2000000    1500    3    4    0     0          0         0   500000     900    1    2    0     0       0          0
+";
+        let interfaces = parse_net_dev(net_dev);
+        assert_eq!(interfaces.len(), 1);
+        assert_eq!(interfaces["eth0"].rx_errors, 3);
+        assert_eq!(interfaces["eth0"].tx_bytes, 500000);
+        assert_eq!(interfaces["eth0"].tx_dropped, 2);
+
+        let mounts = "/dev/tracer-test-nvme0n1p1 / ext4 rw 0 0
+fileserver:/references /mnt/references nfs4 rw 0 0
+fileserver:/references /scratch/my\\040references nfs4 rw 0 0
+tmpfs /tmp tmpfs rw 0 0
+";
+        let mount_points = parse_mount_points(mounts);
+        assert_eq!(mount_points["tracer-test-nvme0n1p1"], vec!["/"]);
+        assert_eq!(
+            mount_points["fileserver:/references"],
+            vec!["/mnt/references", "/scratch/my references"]
+        );
+        assert!(!mount_points.contains_key("tmpfs"));
+    }
+
+    #[test]
+    fn test_rates_between_readings() {
+        let now = Instant::now();
+        let reading = |taken_at, read_bytes, rx_bytes, rx_errors| IoCounters {
+            taken_at,
+            devices: HashMap::from([(
+                "fileserver:/references".to_string(),
+                DeviceCounters {
+                    read_bytes,
+                    ..Default::default()
+                },
+            )]),
+            interfaces: HashMap::from([(
+                "eth0".to_string(),
+                InterfaceCounters {
+                    rx_bytes,
+                    rx_errors,
+                    ..Default::default()
+                },
+            )]),
+            mount_points: HashMap::from([(
+                "fileserver:/references".to_string(),
+                vec!["/mnt/references".to_string()],
+            )]),
+        };
+        let previous = reading(now - Duration::from_secs(2), 1000, 5000, 1);
+        let current = reading(now, 5000, 4000, 3);
+
+        let devices = current.device_rates_since(&previous);
+        let references = &devices["fileserver:/references"];
+        assert_eq!(references.read_bytes_per_sec, 2000.0);
+        assert_eq!(references.mount_points, vec!["/mnt/references"]);
+
+        let interfaces = current.network_rates_since(&previous);
+        // the counter went backwards, e.g. the interface was recreated
+        assert_eq!(interfaces["eth0"].rx_bytes_per_sec, 0.0);
+        assert_eq!(interfaces["eth0"].rx_errors, 2);
+
+        assert!(previous.device_rates_since(&current).is_empty());
+    }
+}
@@ -6,13 +6,25 @@ use sysinfo::{Disks, System};
 
 use crate::{
     events::recorder::{EventRecorder, EventType},
+    extracts::procfs,
     types::event::{
-        attributes::system_metrics::{DiskStatistic, SystemMetric},
+        attributes::system_metrics::{DiskStatistic, SystemMetric, SystemPressure},
         attributes::EventAttributes,
     },
 };
 
-pub struct SystemMetricsCollector;
+mod cpu;
+mod io;
+
+use cpu::CpuTimes;
+use io::IoCounters;
+
+pub struct SystemMetricsCollector {
+    /// The previous reading, the IO rates are computed against
+    last_io: Option<IoCounters>,
+    /// The previous reading, the CPU shares are computed against
+    last_cpu: Option<CpuTimes>,
+}
 
 impl Default for SystemMetricsCollector {
     fn default() -> Self {
@@ -22,7 +34,10 @@ impl Default for SystemMetricsCollector {
 
 impl SystemMetricsCollector {
     pub fn new() -> Self {
-        SystemMetricsCollector
+        SystemMetricsCollector {
+            last_io: None,
+            last_cpu: None,
+        }
     }
 
     pub fn gather_disk_data() -> HashMap<String, DiskStatistic> {
@@ -53,6 +68,14 @@ impl SystemMetricsCollector {
         d_stats
     }
 
+    pub fn gather_pressure() -> SystemPressure {
+        SystemPressure {
+            cpu: procfs::pressure("cpu"),
+            memory: procfs::pressure("memory"),
+            io: procfs::pressure("io"),
+        }
+    }
+
     pub fn gather_metrics_object_attributes(system: &mut System) -> SystemMetric {
         let used_memory = system.used_memory();
         let total_memory = system.total_memory();
@@ -62,6 +85,8 @@ impl SystemMetricsCollector {
         let cpu_usage = system.global_cpu_info().cpu_usage();
 
         let d_stats = Self::gather_disk_data();
+        let load_average = System::load_average();
+        let scheduler = procfs::scheduler_stats().unwrap_or_default();
 
         SystemMetric {
             events_name: "global_system_metrics".to_string(),
@@ -72,13 +97,53 @@ impl SystemMetricsCollector {
             system_memory_swap_total: system.total_swap(),
             system_memory_swap_used: system.used_swap(),
             system_cpu_utilization: cpu_usage,
+            // shares and rates need a previous sample, `collect_metrics` adds them
+            system_cpu_iowait: 0.0,
+            system_cpu_steal: 0.0,
+            system_cpu_cores: vec![],
             system_disk_io: d_stats,
+            system_device_io: HashMap::new(),
+            system_network_io: HashMap::new(),
+            system_pressure: Self::gather_pressure(),
+            system_load_average_1m: load_average.one,
+            system_load_average_5m: load_average.five,
+            system_load_average_15m: load_average.fifteen,
+            system_context_switches_total: scheduler.context_switches,
+            system_procs_running: scheduler.procs_running,
+            system_procs_blocked: scheduler.procs_blocked,
+            system_major_faults_total: procfs::major_faults().unwrap_or_default(),
+        }
+    }
+
+    /// Throughput since the previous call, nothing on the first one.
+    fn add_io_rates(&mut self, metric: &mut SystemMetric) {
+        let io = IoCounters::read();
+        if let Some(last_io) = &self.last_io {
+            metric.system_device_io = io.device_rates_since(last_io);
+            metric.system_network_io = io.network_rates_since(last_io);
+        }
+        self.last_io = Some(io);
+    }
+
+    /// Shares since the previous call, nothing on the first one.
+    fn add_cpu_shares(&mut self, metric: &mut SystemMetric) {
+        let Some(cpu) = CpuTimes::read() else {
+            return;
+        };
+        if let Some(last_cpu) = &self.last_cpu {
+            let shares = cpu.shares_since(last_cpu);
+            metric.system_cpu_iowait = shares.iowait;
+            metric.system_cpu_steal = shares.steal;
+            metric.system_cpu_cores = shares.cores;
         }
+        self.last_cpu = Some(cpu);
     }
 
-    pub fn collect_metrics(&self, system: &mut System, logs: &mut EventRecorder) -> Result<()> {
-        let attributes =
-            EventAttributes::SystemMetric(Self::gather_metrics_object_attributes(system));
+    pub fn collect_metrics(&mut self, system: &mut System, logs: &mut EventRecorder) -> Result<()> {
+        let mut metric = Self::gather_metrics_object_attributes(system);
+        self.add_io_rates(&mut metric);
+        self.add_cpu_shares(&mut metric);
+        let attributes = EventAttributes::SystemMetric(metric);
 
         logs.record_event(
             EventType::MetricEvent,
@@ -101,7 +166,7 @@ mod tests {
     fn test_collect_metrics() {
         let mut system = System::new_all();
         let mut logs = EventRecorder::default();
-        let collector = SystemMetricsCollector::new();
+        let mut collector = SystemMetricsCollector::new();
 
         collector.collect_metrics(&mut system, &mut logs).unwrap();
 
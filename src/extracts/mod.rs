@@ -1,5 +1,11 @@
+pub mod cgroups;
+pub mod containers;
+pub mod ebpf;
 pub mod file_watcher;
 pub mod metrics;
+pub mod proc_connector;
+pub mod process_exit;
 pub mod process_watcher;
+pub mod procfs;
 pub mod stdout;
 pub mod syslog;
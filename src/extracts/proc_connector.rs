@@ -0,0 +1,443 @@
+// src/extracts/proc_connector.rs
+//! Exec, fork and exit notifications from the kernel's process events connector, for machines
+//! where eBPF isn't allowed. Needs `CAP_NET_ADMIN`.
+//!
+//! The notifications only carry PIDs, so the command line is read from `/proc` as soon as the
+//! exec arrives. A listener thread does that, well before the next poll.
+use anyhow::{bail, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Error,
+    mem::size_of,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::extracts::{
+    ebpf::{ProcessEvent, ProcessEventSource},
+    procfs,
+};
+
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+const PROC_EVENT_FORK: u32 = 0x0000_0001;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+const NLMSG_HEADER_SIZE: usize = size_of::<libc::nlmsghdr>();
+/// `struct cn_msg` without its payload
+const CN_MSG_HEADER_SIZE: usize = 20;
+/// Offset of the event data in `struct proc_event`, after `what`, `cpu` and `timestamp_ns`
+const PROC_EVENT_DATA_OFFSET: usize = 16;
+
+const RECEIVE_BUFFER_SIZE: usize = 8192;
+/// How long a receive blocks before checking whether the source was dropped
+const RECEIVE_TIMEOUT: libc::timeval = libc::timeval {
+    tv_sec: 1,
+    tv_usec: 0,
+};
+
+pub struct ProcConnectorSource {
+    events: Arc<Mutex<Vec<ProcessEvent>>>,
+    stop: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl ProcConnectorSource {
+    pub fn open() -> Result<Self> {
+        let socket = subscribe()?;
+        let events = Arc::new(Mutex::new(vec![]));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let listener = {
+            let events = events.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("proc-connector".to_string())
+                .spawn(move || listen(socket, &events, &stop))?
+        };
+
+        Ok(ProcConnectorSource {
+            events,
+            stop,
+            listener: Some(listener),
+        })
+    }
+}
+
+impl ProcessEventSource for ProcConnectorSource {
+    fn drain(&mut self) -> Result<Vec<ProcessEvent>> {
+        Ok(std::mem::take(&mut *self.events.lock().unwrap()))
+    }
+}
+
+impl Drop for ProcConnectorSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+    }
+}
+
+/// Opens the connector socket and asks the kernel to start sending process events.
+fn subscribe() -> Result<OwnedFd> {
+    // SAFETY: plain socket calls, the descriptor is owned right after it's created
+    let socket = unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_CONNECTOR,
+        );
+        if fd < 0 {
+            bail!(
+                "Failed to open the proc connector: {}",
+                Error::last_os_error()
+            );
+        }
+        OwnedFd::from_raw_fd(fd)
+    };
+
+    // SAFETY: the structs passed are valid for the sizes given
+    unsafe {
+        let mut address: libc::sockaddr_nl = std::mem::zeroed();
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = CN_IDX_PROC;
+        if libc::bind(
+            socket.as_raw_fd(),
+            &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+            size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        ) < 0
+        {
+            bail!(
+                "Failed to bind the proc connector: {}",
+                Error::last_os_error()
+            );
+        }
+
+        if libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &RECEIVE_TIMEOUT as *const libc::timeval as *const libc::c_void,
+            size_of::<libc::timeval>() as libc::socklen_t,
+        ) < 0
+        {
+            bail!(
+                "Failed to set the receive timeout: {}",
+                Error::last_os_error()
+            );
+        }
+    }
+
+    let message = listen_message();
+    // SAFETY: the buffer is valid for its length
+    let sent = unsafe {
+        libc::send(
+            socket.as_raw_fd(),
+            message.as_ptr() as *const libc::c_void,
+            message.len(),
+            0,
+        )
+    };
+    if sent < 0 {
+        bail!(
+            "Failed to subscribe to process events: {}",
+            Error::last_os_error()
+        );
+    }
+
+    Ok(socket)
+}
+
+fn listen_message() -> Vec<u8> {
+    let length = NLMSG_HEADER_SIZE + CN_MSG_HEADER_SIZE + size_of::<u32>();
+    let mut message = Vec::with_capacity(length);
+    // struct nlmsghdr
+    message.extend((length as u32).to_ne_bytes());
+    message.extend((libc::NLMSG_DONE as u16).to_ne_bytes());
+    message.extend(0u16.to_ne_bytes());
+    message.extend(0u32.to_ne_bytes());
+    message.extend(std::process::id().to_ne_bytes());
+    // struct cn_msg
+    message.extend(CN_IDX_PROC.to_ne_bytes());
+    message.extend(CN_VAL_PROC.to_ne_bytes());
+    message.extend(0u32.to_ne_bytes());
+    message.extend(0u32.to_ne_bytes());
+    message.extend((size_of::<u32>() as u16).to_ne_bytes());
+    message.extend(0u16.to_ne_bytes());
+    // enum proc_cn_mcast_op
+    message.extend(PROC_CN_MCAST_LISTEN.to_ne_bytes());
+    message
+}
+
+fn listen(socket: OwnedFd, events: &Mutex<Vec<ProcessEvent>>, stop: &AtomicBool) {
+    // parents from fork notifications, for processes gone before their stat could be read
+    let mut parents: HashMap<u32, u32> = HashMap::new();
+    let mut buffer = vec![0u8; RECEIVE_BUFFER_SIZE];
+
+    while !stop.load(Ordering::Relaxed) {
+        // SAFETY: the buffer is valid for its length
+        let received = unsafe {
+            libc::recv(
+                socket.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                0,
+            )
+        };
+        if received < 0 {
+            let err = Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EAGAIN | libc::EINTR) => continue,
+                // the kernel dropped notifications we were too slow for, polling covers them
+                Some(libc::ENOBUFS) => continue,
+                _ => {
+                    eprintln!("Failed to receive process events: {err}");
+                    return;
+                }
+            }
+        }
+
+        let notifications = parse_notifications(&buffer[..received as usize]);
+        let mut parsed = vec![];
+        for notification in notifications {
+            match notification {
+                Notification::Fork { parent, child } => {
+                    parents.insert(child, parent);
+                }
+                Notification::Exec { pid, timestamp_ns } => {
+                    let ppid = read_parent(pid).or_else(|| parents.get(&pid).copied());
+                    parsed.push(ProcessEvent::Exec {
+                        pid,
+                        ppid: ppid.unwrap_or_default(),
+                        name: read_name(pid),
+                        argv: read_argv(pid),
+                        timestamp: monotonic_to_utc(timestamp_ns),
+                    });
+                }
+                Notification::Exit {
+                    pid,
+                    wait_status,
+                    timestamp_ns,
+                } => {
+                    parents.remove(&pid);
+                    parsed.push(ProcessEvent::Exit {
+                        pid,
+                        wait_status,
+                        timestamp: monotonic_to_utc(timestamp_ns),
+                    });
+                }
+            }
+        }
+        events.lock().unwrap().extend(parsed);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Notification {
+    Fork {
+        parent: u32,
+        child: u32,
+    },
+    Exec {
+        pid: u32,
+        timestamp_ns: u64,
+    },
+    Exit {
+        pid: u32,
+        wait_status: i32,
+        timestamp_ns: u64,
+    },
+}
+
+/// Parses the netlink messages in one datagram. Events of threads are skipped, only processes
+/// are of interest.
+fn parse_notifications(datagram: &[u8]) -> Vec<Notification> {
+    let mut notifications = vec![];
+    let mut offset = 0;
+
+    while let Some(length) = read_u32(datagram, offset) {
+        let length = length as usize;
+        if length < NLMSG_HEADER_SIZE || offset + length > datagram.len() {
+            break;
+        }
+        // control messages like NLMSG_NOOP or NLMSG_ERROR can be shorter than a connector message
+        let event = datagram.get(offset + NLMSG_HEADER_SIZE + CN_MSG_HEADER_SIZE..offset + length);
+        if let Some(notification) = event.and_then(parse_proc_event) {
+            notifications.push(notification);
+        }
+        // messages are 4-byte aligned
+        offset += (length + 3) & !3;
+    }
+
+    notifications
+}
+
+/// Parses a `struct proc_event`.
+fn parse_proc_event(event: &[u8]) -> Option<Notification> {
+    let what = read_u32(event, 0)?;
+    let timestamp_ns = u64::from_ne_bytes(event.get(8..16)?.try_into().ok()?);
+    let data = |index: usize| read_u32(event, PROC_EVENT_DATA_OFFSET + index * 4);
+
+    match what {
+        PROC_EVENT_FORK => {
+            let (child_pid, child_tgid) = (data(2)?, data(3)?);
+            (child_pid == child_tgid).then_some(Notification::Fork {
+                parent: data(1)?,
+                child: child_tgid,
+            })
+        }
+        PROC_EVENT_EXEC => Some(Notification::Exec {
+            pid: data(1)?,
+            timestamp_ns,
+        }),
+        PROC_EVENT_EXIT => {
+            let (pid, tgid) = (data(0)?, data(1)?);
+            (pid == tgid).then_some(Notification::Exit {
+                pid: tgid,
+                wait_status: data(2)? as i32,
+                timestamp_ns,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_argv(pid: u32) -> Vec<String> {
+    fs::read(format!("/proc/{pid}/cmdline"))
+        .unwrap_or_default()
+        .split(|byte| *byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+fn read_name(pid: u32) -> String {
+    fs::read_to_string(format!("/proc/{pid}/comm"))
+        .map(|name| name.trim_end().to_string())
+        .unwrap_or_default()
+}
+
+fn read_parent(pid: u32) -> Option<u32> {
+    let stat = procfs::read_stat(pid)?;
+    procfs::stat_fields(&stat)?.get(1)?.parse().ok()
+}
+
+/// The event timestamps are from the monotonic clock.
+fn monotonic_to_utc(timestamp_ns: u64) -> DateTime<Utc> {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: the timespec is valid to write to
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    let now_ns = now.tv_sec * 1_000_000_000 + now.tv_nsec;
+    Utc::now() - TimeDelta::nanoseconds(now_ns - timestamp_ns as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn proc_event(what: u32, data: &[u32]) -> Vec<u8> {
+        let mut event = vec![];
+        event.extend(what.to_ne_bytes());
+        event.extend(0u32.to_ne_bytes());
+        event.extend(1234u64.to_ne_bytes());
+        for value in data {
+            event.extend(value.to_ne_bytes());
+        }
+
+        let length = NLMSG_HEADER_SIZE + CN_MSG_HEADER_SIZE + event.len();
+        let mut message = (length as u32).to_ne_bytes().to_vec();
+        message.resize(NLMSG_HEADER_SIZE + CN_MSG_HEADER_SIZE, 0);
+        message.extend(event);
+        message
+    }
+
+    #[test]
+    fn test_parse_notifications() {
+        let mut datagram = proc_event(PROC_EVENT_FORK, &[1, 1, 42, 42]);
+        datagram.extend(proc_event(PROC_EVENT_EXEC, &[42, 42]));
+        // a thread exiting, not the process
+        datagram.extend(proc_event(PROC_EVENT_EXIT, &[43, 42, 0, 17]));
+        datagram.extend(proc_event(PROC_EVENT_EXIT, &[42, 42, 3 << 8, 17]));
+
+        assert_eq!(
+            parse_notifications(&datagram),
+            vec![
+                Notification::Fork {
+                    parent: 1,
+                    child: 42
+                },
+                Notification::Exec {
+                    pid: 42,
+                    timestamp_ns: 1234
+                },
+                Notification::Exit {
+                    pid: 42,
+                    wait_status: 3 << 8,
+                    timestamp_ns: 1234
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_short_messages_are_skipped() {
+        // a bare netlink header, like NLMSG_NOOP
+        let mut datagram = (NLMSG_HEADER_SIZE as u32).to_ne_bytes().to_vec();
+        datagram.resize(NLMSG_HEADER_SIZE, 0);
+        datagram.extend(proc_event(PROC_EVENT_EXEC, &[42, 42]));
+        assert_eq!(
+            parse_notifications(&datagram),
+            vec![Notification::Exec {
+                pid: 42,
+                timestamp_ns: 1234
+            }]
+        );
+
+        // truncated in the middle of the connector header
+        let mut datagram = ((NLMSG_HEADER_SIZE + 4) as u32).to_ne_bytes().to_vec();
+        datagram.resize(NLMSG_HEADER_SIZE + 4, 0);
+        assert!(parse_notifications(&datagram).is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn test_receives_exec_and_exit() -> Result<()> {
+        let mut source = ProcConnectorSource::open()?;
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()?;
+        let pid = child.id();
+        child.wait()?;
+        std::thread::sleep(Duration::from_millis(200));
+
+        let events = source.drain()?;
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, ProcessEvent::Exec { pid: p, .. } if *p == pid)));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ProcessEvent::Exit { pid: p, wait_status, .. } if *p == pid && *wait_status == 3 << 8
+        )));
+        Ok(())
+    }
+}
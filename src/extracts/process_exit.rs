@@ -0,0 +1,273 @@
+// src/extracts/process_exit.rs
+//! How tracked processes ended: exit code or terminating signal, and whether the OOM killer was
+//! behind it.
+//!
+//! The daemon isn't the parent of the tools it tracks, so it can't wait for them. Instead the
+//! exit status is read from `/proc/<pid>/stat` while the process is a zombie waiting for its
+//! parent, and OOM kills are picked up from the kernel log. A process reaped before a poll sees
+//! it as a zombie has no exit status, unless an exit event reported it.
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    os::unix::fs::OpenOptionsExt,
+};
+
+use crate::extracts::procfs;
+
+const KMSG_PATH: &str = "/dev/kmsg";
+const VMSTAT_PATH: &str = "/proc/vmstat";
+/// Position of `exit_code` in `/proc/<pid>/stat`, counted from the state field.
+const STAT_EXIT_CODE_INDEX: usize = 49;
+const CAP_SYS_PTRACE: u32 = 19;
+
+/// Whether the daemon may read the exit code of other users' processes. Computed once, the
+/// capabilities of the daemon don't change.
+static HAS_CAP_SYS_PTRACE: Lazy<bool> = Lazy::new(|| {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| parse_effective_capabilities(&status))
+        .is_some_and(|caps| caps & (1 << CAP_SYS_PTRACE) != 0)
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExitStatus {
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+}
+
+impl ExitStatus {
+    /// Decodes a `waitpid` status word.
+    pub fn from_wait_status(status: i32) -> Self {
+        let signal = status & 0x7f;
+        if signal == 0 {
+            ExitStatus {
+                exit_code: Some((status >> 8) & 0xff),
+                signal: None,
+            }
+        } else {
+            ExitStatus {
+                exit_code: None,
+                signal: Some(signal),
+            }
+        }
+    }
+}
+
+/// The exit status of `pid` if it is a zombie, `None` while it runs or once it's reaped.
+///
+/// The kernel reports an exit code of 0 to readers that fail the ptrace access check, so `None`
+/// is also returned for processes of other users unless the daemon has `CAP_SYS_PTRACE`.
+pub fn read_zombie_exit_status(pid: u32) -> Option<ExitStatus> {
+    let stat = procfs::read_stat(pid)?;
+    if !*HAS_CAP_SYS_PTRACE {
+        let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+        // SAFETY: geteuid can't fail
+        let euid = unsafe { libc::geteuid() };
+        if !parse_uids(&status)?.iter().all(|uid| *uid == euid) {
+            return None;
+        }
+    }
+    parse_zombie_exit_status(&stat)
+}
+
+/// Real, effective and saved user IDs from `/proc/<pid>/status`.
+fn parse_uids(status: &str) -> Option<[u32; 3]> {
+    let mut uids = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))?
+        .split_whitespace()
+        .map(|uid| uid.parse().ok());
+    Some([uids.next()??, uids.next()??, uids.next()??])
+}
+
+fn parse_effective_capabilities(status: &str) -> Option<u64> {
+    let caps = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(caps.trim(), 16).ok()
+}
+
+fn parse_zombie_exit_status(stat: &str) -> Option<ExitStatus> {
+    let fields = procfs::stat_fields(stat)?;
+    if *fields.first()? != "Z" {
+        return None;
+    }
+    let status = fields.get(STAT_EXIT_CODE_INDEX)?.parse().ok()?;
+    Some(ExitStatus::from_wait_status(status))
+}
+
+/// Collects the PIDs the OOM killer killed, from the kernel log. The log is only read when the
+/// `oom_kill` counter in `/proc/vmstat` moved, so polling it is cheap.
+pub struct OomKillMonitor {
+    kmsg: Option<File>,
+    oom_kills: u64,
+    killed: HashSet<u32>,
+}
+
+impl Default for OomKillMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OomKillMonitor {
+    pub fn new() -> Self {
+        // reading the kernel log needs privileges, without it OOM kills simply go undetected
+        let kmsg = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(KMSG_PATH)
+            .and_then(|mut kmsg| {
+                // only kills from now on are of interest
+                kmsg.seek(SeekFrom::End(0))?;
+                Ok(kmsg)
+            })
+            .ok();
+
+        OomKillMonitor {
+            kmsg,
+            oom_kills: read_oom_kill_count().unwrap_or_default(),
+            killed: HashSet::new(),
+        }
+    }
+
+    pub fn poll(&mut self) {
+        let Some(oom_kills) = read_oom_kill_count() else {
+            return;
+        };
+        if oom_kills == self.oom_kills {
+            return;
+        }
+        self.oom_kills = oom_kills;
+
+        let Some(kmsg) = self.kmsg.as_mut() else {
+            return;
+        };
+        // every read returns one record, until there's nothing left
+        let mut record = vec![0; 8192];
+        loop {
+            match kmsg.read(&mut record) {
+                Ok(0) => break,
+                Ok(len) => {
+                    let line = String::from_utf8_lossy(&record[..len]);
+                    if let Some(pid) = parse_oom_killed_pid(&line) {
+                        self.killed.insert(pid);
+                    }
+                }
+                // EPIPE: records were overwritten before we got to them, keep reading
+                Err(err) if err.raw_os_error() == Some(libc::EPIPE) => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Whether `pid` was killed by the OOM killer. Each kill is only reported once.
+    pub fn take(&mut self, pid: u32) -> bool {
+        self.killed.remove(&pid)
+    }
+
+    /// Forgets the kills of all PIDs `keep` returns false for.
+    pub fn retain(&mut self, keep: impl Fn(u32) -> bool) {
+        self.killed.retain(|pid| keep(*pid));
+    }
+}
+
+fn read_oom_kill_count() -> Option<u64> {
+    fs::read_to_string(VMSTAT_PATH)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Matches `Out of memory: Killed process 1234 (bwa) ...` and
+/// `Memory cgroup out of memory: Killed process 1234 (bwa) ...`.
+fn parse_oom_killed_pid(line: &str) -> Option<u32> {
+    let (_, rest) = line.split_once("Killed process ")?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat_line(comm: &str, state: &str, exit_code: i32) -> String {
+        let mut fields = vec!["0"; 48].join(" ");
+        fields.push_str(&format!(" {exit_code}"));
+        format!("1234 ({comm}) {state} {fields}")
+    }
+
+    #[test]
+    fn test_exit_status_of_zombies() {
+        assert_eq!(
+            parse_zombie_exit_status(&stat_line("bwa mem (1)", "Z", 1 << 8)),
+            Some(ExitStatus {
+                exit_code: Some(1),
+                signal: None
+            })
+        );
+        assert_eq!(
+            parse_zombie_exit_status(&stat_line("bwa", "Z", 9)),
+            Some(ExitStatus {
+                exit_code: None,
+                signal: Some(9)
+            })
+        );
+        assert_eq!(parse_zombie_exit_status(&stat_line("bwa", "R", 0)), None);
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        let status = "Name:\tbwa\nUid:\t1000\t1000\t1000\t1000\nGid:\t100\t100\t100\t100\nCapEff:\t00000000a80425fb\n";
+        assert_eq!(parse_uids(status), Some([1000, 1000, 1000]));
+        let caps = parse_effective_capabilities(status).unwrap();
+        assert_eq!(caps & (1 << CAP_SYS_PTRACE), 0);
+        assert_eq!(
+            parse_effective_capabilities("CapEff:\t000001ffffffffff\n").unwrap()
+                & (1 << CAP_SYS_PTRACE),
+            1 << CAP_SYS_PTRACE
+        );
+        assert_eq!(parse_uids("Name:\tbwa\n"), None);
+    }
+
+    #[test]
+    fn test_reads_exit_code_of_unreaped_child() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+
+        // the child stays a zombie until it's waited for
+        let mut status = None;
+        for _ in 0..200 {
+            status = read_zombie_exit_status(child.id());
+            if status.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        child.wait().unwrap();
+
+        assert_eq!(status.unwrap().exit_code, Some(3));
+        assert_eq!(read_zombie_exit_status(child.id()), None);
+    }
+
+    #[test]
+    fn test_parse_oom_killed_pid() {
+        assert_eq!(
+            parse_oom_killed_pid(
+                "3,1234,5678,-;Out of memory: Killed process 4321 (bwa) total-vm:1024kB"
+            ),
+            Some(4321)
+        );
+        assert_eq!(
+            parse_oom_killed_pid("6,99,100,-;oom-kill:constraint=CONSTRAINT_NONE,pid=4321"),
+            None
+        );
+    }
+}
@@ -3,18 +3,24 @@ use crate::config_manager::target_process::{
     targets_list::DATA_SAMPLES_EXT, Target, TargetMatchable,
 };
 use crate::events::recorder::{EventRecorder, EventType};
+use crate::extracts::containers::ContainerResolver;
+use crate::extracts::ebpf::{ProcessEvent, ProcessEventSource};
 use crate::extracts::file_watcher::FileWatcher;
+use crate::extracts::process_exit::{read_zombie_exit_status, ExitStatus, OomKillMonitor};
+use crate::extracts::procfs::{self, FileAccess};
 use crate::types::event::attributes::process::InputFile;
 use crate::types::event::attributes::process::ProcessProperties;
-use crate::types::event::attributes::process::{CompletedProcess, DataSetsProcessed};
+use crate::types::event::attributes::process::{
+    CompletedProcess, DataSetsProcessed, ProcessSubtree, ResourceUsage, SubtreeCommand,
+};
 use crate::types::event::attributes::EventAttributes;
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use itertools::Itertools;
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
 use std::collections::{hash_map::Entry::Vacant, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::Path;
 use std::time::Duration;
 use sysinfo::ProcessStatus;
@@ -24,8 +30,16 @@ pub struct ProcessWatcher {
     targets: Vec<Target>,
     seen: HashMap<Pid, Proc>,
     process_tree: HashMap<Pid, ProcessTreeNode>,
+    /// Child PIDs by parent, from the last built process tree
+    process_children: HashMap<Pid, Vec<Pid>>,
     // We wanna track unique datasamples we come across when monitoring process args
     datasamples_tracker: HashSet<String>,
+    oom_kill_monitor: OomKillMonitor,
+    /// Exec and exit events streamed from the kernel, on top of polling
+    event_source: Option<Box<dyn ProcessEventSource>>,
+    containers: ContainerResolver,
+    /// Whether to read the extra figures in `ProcessProperties::details`
+    process_details: bool,
 }
 
 enum ProcLastUpdate {
@@ -36,11 +50,90 @@ enum ProcLastUpdate {
 pub struct Proc {
     name: String,
     start_time: DateTime<Utc>,
+    /// Last poll the process was still running in, the best bound on when it ended.
+    last_seen: DateTime<Utc>,
+    /// Exact end from an exit event, when there's an event source
+    ended_at: Option<DateTime<Utc>>,
+    resource_usage: ResourceUsage,
+    /// Set for targets merged with their parents, whose descendants count towards their usage
+    subtree: Option<SubtreeUsage>,
     last_update: ProcLastUpdate,
     just_started: bool,
+    exit_status: Option<ExitStatus>,
+    files: OpenFiles,
+}
+
+/// Reading the fds costs a few syscalls per file, so they are sampled less often than processes
+/// are polled.
+const OPEN_FILES_SAMPLE_INTERVAL: TimeDelta = TimeDelta::seconds(1);
+
+/// Files a process, or the subtree of a merged target, was seen with open while it ran.
+#[derive(Default)]
+struct OpenFiles {
+    inputs: BTreeSet<String>,
+    outputs: BTreeSet<String>,
+    sampled_at: Option<DateTime<Utc>>,
+}
+
+impl OpenFiles {
+    fn sample(&mut self, pids: impl IntoIterator<Item = Pid>, now: DateTime<Utc>) {
+        if self
+            .sampled_at
+            .is_some_and(|sampled_at| now - sampled_at < OPEN_FILES_SAMPLE_INTERVAL)
+        {
+            return;
+        }
+        self.sampled_at = Some(now);
+        for pid in pids {
+            for (path, access) in procfs::open_files(pid.as_u32()) {
+                match access {
+                    FileAccess::Read => self.inputs.insert(path),
+                    FileAccess::Write => self.outputs.insert(path),
+                };
+            }
+        }
+    }
+}
+
+/// Lifetime usage of the descendants of a merged target.
+#[derive(Default)]
+struct SubtreeUsage {
+    /// Last readings of every descendant seen, kept after they exit so their CPU time and IO
+    /// still count
+    descendants: HashMap<Pid, ResourceUsage>,
+    /// Peaks of the memory summed over the whole subtree
+    peak_memory_usage: u64,
+    peak_memory_virtual: u64,
+}
+
+impl SubtreeUsage {
+    fn observe(&mut self, process: &Process, descendants: &[Pid], system: &System) {
+        let mut memory = process.memory();
+        let mut memory_virtual = process.virtual_memory();
+        for pid in descendants {
+            let Some(descendant) = system.process(*pid) else {
+                continue;
+            };
+            memory += descendant.memory();
+            memory_virtual += descendant.virtual_memory();
+            observe_resource_usage(self.descendants.entry(*pid).or_default(), pid, descendant);
+        }
+        self.peak_memory_usage = self.peak_memory_usage.max(memory);
+        self.peak_memory_virtual = self.peak_memory_virtual.max(memory_virtual);
+    }
+
+    fn add_to(&self, usage: &mut ResourceUsage) {
+        for descendant in self.descendants.values() {
+            usage.cpu_time_sec += descendant.cpu_time_sec;
+            usage.disk_usage_read_total += descendant.disk_usage_read_total;
+            usage.disk_usage_write_total += descendant.disk_usage_write_total;
+        }
+        usage.peak_memory_usage = usage.peak_memory_usage.max(self.peak_memory_usage);
+        usage.peak_memory_virtual = usage.peak_memory_virtual.max(self.peak_memory_virtual);
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ShortLivedProcessLog {
     pub command: String,
     pub timestamp: String,
@@ -78,7 +171,41 @@ impl ProcessWatcher {
             targets,
             seen: HashMap::new(),
             process_tree: HashMap::new(),
+            process_children: HashMap::new(),
             datasamples_tracker: HashSet::new(),
+            oom_kill_monitor: OomKillMonitor::new(),
+            event_source: None,
+            containers: ContainerResolver::new(None),
+            process_details: false,
+        }
+    }
+
+    pub fn with_process_details(self, process_details: bool) -> Self {
+        ProcessWatcher {
+            process_details,
+            ..self
+        }
+    }
+
+    pub fn with_container_runtime_socket(self, runtime_socket: Option<String>) -> Self {
+        ProcessWatcher {
+            containers: ContainerResolver::new(runtime_socket),
+            ..self
+        }
+    }
+
+    /// The image of the container `pid` runs in, only looked up when a target matches on it.
+    fn image_to_match(&mut self, pid: Pid) -> Option<String> {
+        if !self.targets.iter().any(Target::needs_image) {
+            return None;
+        }
+        self.containers.resolve(pid.as_u32()).container?.image
+    }
+
+    pub fn with_event_source(self, event_source: Option<Box<dyn ProcessEventSource>>) -> Self {
+        ProcessWatcher {
+            event_source,
+            ..self
         }
     }
 
@@ -88,16 +215,20 @@ impl ProcessWatcher {
         event_logger: &mut EventRecorder,
         file_watcher: &FileWatcher,
     ) -> Result<()> {
+        self.poll_process_events(system, event_logger)?;
+
         for (pid, proc) in system.processes().iter() {
             if !self.seen.contains_key(pid) {
+                let image = self.image_to_match(*pid);
                 let target = self.targets.iter().find(|target| {
-                    target.matches(
+                    target.matches_in_image(
                         proc.name(),
                         &proc.cmd().join(" "),
                         proc.exe()
                             .unwrap_or_else(|| Path::new(""))
                             .to_str()
                             .unwrap(),
+                        image.as_deref(),
                     )
                 });
                 if let Some(target) = target {
@@ -131,6 +262,113 @@ impl ProcessWatcher {
             file_watcher,
         )?;
 
+        self.containers
+            .retain(|pid| system.process(Pid::from_u32(pid)).is_some());
+
+        Ok(())
+    }
+
+    /// Handles the events since the last poll. Tools already gone before polling could see them
+    /// are recorded from their exec event, and exit events give the exact end and exit status.
+    fn poll_process_events(
+        &mut self,
+        system: &System,
+        event_logger: &mut EventRecorder,
+    ) -> Result<()> {
+        let Some(event_source) = self.event_source.as_mut() else {
+            return Ok(());
+        };
+
+        for event in event_source.drain()? {
+            match event {
+                ProcessEvent::Exec {
+                    pid,
+                    ppid,
+                    name,
+                    argv,
+                    timestamp,
+                } => {
+                    let pid = Pid::from_u32(pid);
+                    // polling picks up the ones still running, with more details
+                    if self.seen.contains_key(&pid) || system.process(pid).is_some() {
+                        continue;
+                    }
+                    let bin_path = argv.first().cloned().unwrap_or_default();
+                    let cmd = argv.join(" ");
+                    let placement = self.containers.resolve(pid.as_u32());
+                    let image = placement
+                        .container
+                        .as_ref()
+                        .and_then(|container| container.image.as_deref());
+                    let Some(target) = self
+                        .targets
+                        .iter()
+                        .find(|target| target.matches_in_image(&name, &cmd, &bin_path, image))
+                    else {
+                        continue;
+                    };
+                    let display_name = target
+                        .get_display_name_object()
+                        .get_display_name(&name, &argv);
+
+                    let properties = ProcessProperties {
+                        tool_name: display_name.clone(),
+                        tool_pid: pid.to_string(),
+                        tool_parent_pid: ppid.to_string(),
+                        tool_binary_path: bin_path,
+                        tool_cmd: cmd,
+                        start_timestamp: timestamp.to_rfc3339(),
+                        process_cpu_utilization: 0.0,
+                        process_memory_usage: 0,
+                        process_memory_virtual: 0,
+                        process_run_time: 0,
+                        process_disk_usage_read_last_interval: 0,
+                        process_disk_usage_write_last_interval: 0,
+                        process_disk_usage_read_total: 0,
+                        process_disk_usage_write_total: 0,
+                        process_status: "Unknown".to_string(),
+                        input_files: None,
+                        subtree: None,
+                        cgroup: placement.cgroup,
+                        container: placement.container,
+                        details: None,
+                    };
+                    event_logger.record_event(
+                        EventType::ToolExecution,
+                        format!("[{}] Tool process: {}", timestamp, &display_name),
+                        Some(EventAttributes::Process(properties)),
+                        Some(timestamp),
+                    );
+
+                    self.seen.insert(
+                        pid,
+                        Proc {
+                            name,
+                            start_time: timestamp,
+                            last_seen: timestamp,
+                            ended_at: None,
+                            resource_usage: ResourceUsage::default(),
+                            subtree: None,
+                            last_update: ProcLastUpdate::RefreshesRemaining(2),
+                            just_started: true,
+                            exit_status: None,
+                            files: OpenFiles::default(),
+                        },
+                    );
+                }
+                ProcessEvent::Exit {
+                    pid,
+                    wait_status,
+                    timestamp,
+                } => {
+                    if let Some(proc) = self.seen.get_mut(&Pid::from_u32(pid)) {
+                        proc.exit_status = Some(ExitStatus::from_wait_status(wait_status));
+                        proc.ended_at = Some(timestamp);
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -148,7 +386,7 @@ impl ProcessWatcher {
                             self.seen.get_mut(pid).unwrap().last_update =
                                 ProcLastUpdate::RefreshesRemaining(refresh_count - 1);
                         } else {
-                            self.add_process_metrics(proc, event_logger, None)?;
+                            self.add_process_metrics(proc, system, event_logger, None)?;
                             self.seen.get_mut(pid).unwrap().last_update =
                                 ProcLastUpdate::Some(Utc::now());
                         }
@@ -156,7 +394,7 @@ impl ProcessWatcher {
                     }
                     if let ProcLastUpdate::Some(last_update) = p.last_update {
                         if last_update + process_metrics_send_interval < Utc::now() {
-                            self.add_process_metrics(proc, event_logger, None)?;
+                            self.add_process_metrics(proc, system, event_logger, None)?;
                             self.seen.get_mut(pid).unwrap().last_update =
                                 ProcLastUpdate::Some(Utc::now());
                         }
@@ -179,14 +417,39 @@ impl ProcessWatcher {
         system: &mut System,
         event_logger: &mut EventRecorder,
     ) -> Result<()> {
+        self.oom_kill_monitor.poll();
+
+        let now = Utc::now();
         let mut to_remove = vec![];
-        for (pid, proc) in self.seen.iter() {
-            if !system.processes().contains_key(pid) {
-                self.log_completed_process(pid, proc, event_logger)?;
+        for (pid, proc) in self.seen.iter_mut() {
+            if let Some(process) = system.process(*pid) {
+                proc.last_seen = now;
+                observe_resource_usage(&mut proc.resource_usage, pid, process);
+                let mut pids = vec![*pid];
+                if let Some(subtree) = proc.subtree.as_mut() {
+                    let descendants = descendants(&self.process_children, *pid);
+                    subtree.observe(process, &descendants, system);
+                    pids.extend(descendants);
+                }
+                proc.files.sample(pids, now);
+                // the exit status can only be read in the short window before the parent reaps
+                // the process
+                if proc.exit_status.is_none() {
+                    proc.exit_status = read_zombie_exit_status(pid.as_u32());
+                }
+            } else {
                 to_remove.push(*pid);
             }
         }
 
+        for pid in &to_remove {
+            let oom_killed = self.oom_kill_monitor.take(pid.as_u32());
+            self.log_completed_process(pid, &self.seen[pid], oom_killed, event_logger)?;
+        }
+        // kills of processes we don't track are of no interest
+        self.oom_kill_monitor
+            .retain(|pid| self.seen.contains_key(&Pid::from_u32(pid)));
+
         for pid in to_remove {
             self.seen.remove(&pid);
         }
@@ -198,28 +461,38 @@ impl ProcessWatcher {
         let mut nodes: HashMap<Pid, ProcessTreeNode> = HashMap::new();
 
         for (pid, proc) in system_processes {
-            let properties = Self::gather_process_data(pid, proc, None);
+            // reading `/proc` for every process on each poll is too costly, the precise start is
+            // only needed for tracked processes, which already have it
+            let start_time = self
+                .seen
+                .get(pid)
+                .map(|seen| seen.start_time)
+                .unwrap_or_else(|| coarse_start_time(proc));
+            let properties = Self::gather_process_data(pid, proc, None, start_time);
             let node = ProcessTreeNode {
                 properties,
                 children: vec![],
                 parent_id: proc.parent(),
-                start_time: DateTime::from_timestamp(proc.start_time() as i64, 0).unwrap(),
+                start_time,
             };
 
             nodes.insert(*pid, node);
         }
 
+        let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
         for (pid, proc) in system_processes {
             let parent = proc.parent();
             if let Some(parent) = parent {
                 let node = nodes.get(pid).unwrap().clone();
                 if let Some(parent_node) = nodes.get_mut(&parent) {
                     parent_node.children.push(node.clone());
+                    children.entry(parent).or_default().push(*pid);
                 }
             }
         }
 
-        self.process_tree = nodes
+        self.process_tree = nodes;
+        self.process_children = children;
     }
 
     pub fn get_parent_processes(
@@ -274,10 +547,19 @@ impl ProcessWatcher {
             let mut valid_processes = vec![];
 
             for (pid, node) in nodes {
-                if target.matches(
+                let image = if target.needs_image() {
+                    self.containers
+                        .resolve(pid.as_u32())
+                        .container
+                        .and_then(|container| container.image)
+                } else {
+                    None
+                };
+                if target.matches_in_image(
                     &node.properties.tool_name,
                     &node.properties.tool_cmd,
                     &node.properties.tool_binary_path,
+                    image.as_deref(),
                 ) {
                     valid_processes.push(*pid);
                 }
@@ -314,9 +596,8 @@ impl ProcessWatcher {
         pid: &Pid,
         proc: &Process,
         display_name: Option<String>,
+        start_time: DateTime<Utc>,
     ) -> ProcessProperties {
-        let start_time = Utc::now();
-
         ProcessProperties {
             tool_name: display_name.unwrap_or(proc.name().to_owned()),
             tool_pid: pid.to_string(),
@@ -340,6 +621,10 @@ impl ProcessWatcher {
             process_memory_virtual: proc.virtual_memory(),
             process_status: process_status_to_string(&proc.status()),
             input_files: None,
+            subtree: None,
+            cgroup: None,
+            container: None,
+            details: None,
         }
     }
 
@@ -348,6 +633,10 @@ impl ProcessWatcher {
         short_lived_process: ShortLivedProcessLog,
         event_logger: &mut EventRecorder,
     ) -> Result<()> {
+        let start_time =
+            DateTime::parse_from_rfc3339(&short_lived_process.properties.start_timestamp)
+                .map(|start_time| start_time.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
         let properties = EventAttributes::Process(short_lived_process.properties.clone());
         event_logger.record_event(
             EventType::ToolExecution,
@@ -356,18 +645,26 @@ impl ProcessWatcher {
                 short_lived_process.timestamp, short_lived_process.command
             ),
             Some(properties),
-            None,
+            Some(start_time),
         );
 
-        if let Vacant(v) = self
-            .seen
-            .entry(short_lived_process.properties.tool_pid.parse().unwrap())
-        {
+        // the pid is empty when the CLI couldn't find the process anymore
+        let Ok(pid) = short_lived_process.properties.tool_pid.parse() else {
+            return Ok(());
+        };
+
+        if let Vacant(v) = self.seen.entry(pid) {
             v.insert(Proc {
                 name: short_lived_process.command,
-                start_time: Utc::now(),
+                start_time,
+                last_seen: Utc::now(),
+                ended_at: None,
+                resource_usage: ResourceUsage::default(),
+                subtree: None,
                 last_update: ProcLastUpdate::RefreshesRemaining(2),
                 just_started: true,
+                exit_status: None,
+                files: OpenFiles::default(),
             });
         }
 
@@ -380,7 +677,12 @@ impl ProcessWatcher {
             ShortLivedProcessLog {
                 command: command.to_string(),
                 timestamp: chrono::Utc::now().to_rfc3339(),
-                properties: ProcessWatcher::gather_process_data(&process.pid(), process, None),
+                properties: ProcessWatcher::gather_process_data(
+                    &process.pid(),
+                    process,
+                    None,
+                    ProcessWatcher::process_start_time(&process.pid(), process),
+                ),
             }
         } else {
             ShortLivedProcessLog {
@@ -403,6 +705,10 @@ impl ProcessWatcher {
                     process_disk_usage_write_total: 0,
                     process_status: "Unknown".to_string(),
                     input_files: None,
+                    subtree: None,
+                    cgroup: None,
+                    container: None,
+                    details: None,
                 },
             }
         }
@@ -417,13 +723,22 @@ impl ProcessWatcher {
         target: Option<&Target>,
         file_watcher: &FileWatcher,
     ) -> Result<()> {
+        let start_time = Self::process_start_time(&pid, proc);
         self.seen.insert(
             pid,
             Proc {
                 name: proc.name().to_string(),
-                start_time: Utc::now(),
+                start_time,
+                last_seen: Utc::now(),
+                ended_at: None,
+                resource_usage: ResourceUsage::default(),
+                subtree: target
+                    .filter(|target| target.should_be_merged_with_parents())
+                    .map(|_| SubtreeUsage::default()),
                 last_update: ProcLastUpdate::RefreshesRemaining(2),
                 just_started: true,
+                exit_status: None,
+                files: OpenFiles::default(),
             },
         );
 
@@ -432,8 +747,6 @@ impl ProcessWatcher {
             return Ok(());
         };
 
-        let start_time = Utc::now();
-
         let display_name = if let Some(target) = target {
             let name = target
                 .get_display_name_object()
@@ -444,7 +757,18 @@ impl ProcessWatcher {
             proc.name().to_owned()
         };
 
-        let mut properties = Self::gather_process_data(&pid, p, Some(display_name.clone()));
+        let mut properties =
+            Self::gather_process_data(&pid, p, Some(display_name.clone()), start_time);
+        let placement = self.containers.resolve(pid.as_u32());
+        properties.cgroup = placement.cgroup;
+        properties.container = placement.container;
+        if self.process_details {
+            properties.details = procfs::process_details(pid.as_u32());
+        }
+        // short lived tools may be gone by the next poll
+        if let Some(seen) = self.seen.get_mut(&pid) {
+            seen.files.sample([pid], Utc::now());
+        }
 
         let cmd_arguments = p.cmd();
 
@@ -486,7 +810,7 @@ impl ProcessWatcher {
             EventType::ToolExecution,
             format!("[{}] Tool process: {}", start_time, &display_name),
             Some(EventAttributes::Process(properties)),
-            None,
+            Some(start_time),
         );
 
         self.log_datasets_in_process(event_logger, cmd_arguments);
@@ -497,6 +821,7 @@ impl ProcessWatcher {
     fn add_process_metrics(
         &mut self,
         proc: &Process,
+        system: &System,
         event_logger: &mut EventRecorder,
         target: Option<&Target>,
     ) -> Result<()> {
@@ -511,22 +836,67 @@ impl ProcessWatcher {
             proc.name().to_owned()
         };
 
-        let properties = EventAttributes::Process(Self::gather_process_data(
-            &pid,
-            proc,
-            Some(display_name.clone()),
-        ));
+        let process_start = match self.seen.get(&pid) {
+            Some(seen) => seen.start_time,
+            None => Self::process_start_time(&pid, proc),
+        };
+        let mut properties =
+            Self::gather_process_data(&pid, proc, Some(display_name.clone()), process_start);
+        let placement = self.containers.resolve(pid.as_u32());
+        properties.cgroup = placement.cgroup;
+        properties.container = placement.container;
+        if self.process_details {
+            properties.details = procfs::process_details(pid.as_u32());
+        }
+        if self.seen.get(&pid).is_some_and(|p| p.subtree.is_some()) {
+            self.add_subtree_usage(&mut properties, pid, system);
+        }
 
         event_logger.record_event(
             EventType::ToolMetricEvent,
             format!("[{}] Tool metric event: {}", start_time, &display_name),
-            Some(properties),
+            Some(EventAttributes::Process(properties)),
             None,
         );
 
         Ok(())
     }
 
+    /// Adds the usage of the live descendants of a merged target to its own, with a breakdown by
+    /// command.
+    fn add_subtree_usage(&self, properties: &mut ProcessProperties, pid: Pid, system: &System) {
+        let mut subtree = ProcessSubtree::default();
+        let mut commands: BTreeMap<&str, SubtreeCommand> = BTreeMap::new();
+
+        for descendant in descendants(&self.process_children, pid) {
+            let Some(process) = system.process(descendant) else {
+                continue;
+            };
+            let disk_usage = process.disk_usage();
+            subtree.process_count += 1;
+            properties.process_cpu_utilization += process.cpu_usage();
+            properties.process_memory_usage += process.memory();
+            properties.process_memory_virtual += process.virtual_memory();
+            properties.process_disk_usage_read_last_interval += disk_usage.read_bytes;
+            properties.process_disk_usage_write_last_interval += disk_usage.written_bytes;
+            properties.process_disk_usage_read_total += disk_usage.total_read_bytes;
+            properties.process_disk_usage_write_total += disk_usage.total_written_bytes;
+
+            let command = commands
+                .entry(process.name())
+                .or_insert_with(|| SubtreeCommand {
+                    command: process.name().to_string(),
+                    ..Default::default()
+                });
+            command.process_count += 1;
+            command.cpu_utilization += process.cpu_usage();
+            command.memory_usage += process.memory();
+        }
+
+        subtree.commands = commands.into_values().collect();
+        properties.subtree = Some(subtree);
+    }
+
     pub fn get_earliest_process_time(&self) -> DateTime<Utc> {
         let mut earliest = Utc::now();
 
@@ -592,6 +962,12 @@ impl ProcessWatcher {
         }
     }
 
+    /// When the kernel started the process. sysinfo rounds to whole seconds, so `/proc` is
+    /// preferred when it's readable.
+    pub fn process_start_time(pid: &Pid, proc: &Process) -> DateTime<Utc> {
+        procfs::process_start_time(pid.as_u32()).unwrap_or_else(|| coarse_start_time(proc))
+    }
+
     pub fn is_process_alive(&self, system: &System, pid: Pid) -> bool {
         system.process(pid).is_some()
     }
@@ -600,22 +976,54 @@ impl ProcessWatcher {
         &self,
         pid: &Pid,
         proc: &Proc,
+        oom_killed: bool,
         event_logger: &mut EventRecorder,
     ) -> Result<()> {
-        // NOTE: to avoid handling casting from u128 to u64, moving to as_secs from as_millis
-        let duration_sec = (Utc::now() - proc.start_time).to_std()?.as_secs();
+        // without an exit event, the process ended somewhere between the last poll it was seen in
+        // and this one
+        let end_time = proc.ended_at.unwrap_or(proc.last_seen);
+        let duration = (end_time - proc.start_time).max(TimeDelta::zero());
+        let duration_sec = duration.num_seconds() as u64;
+        let exit_status = proc.exit_status.unwrap_or_default();
+
+        let mut resource_usage = proc.resource_usage.clone();
+        if let Some(subtree) = &proc.subtree {
+            subtree.add_to(&mut resource_usage);
+        }
+        if let Some(duration_us) = duration.num_microseconds().filter(|us| *us > 0) {
+            resource_usage.average_cpu_utilization =
+                resource_usage.cpu_time_sec * 100.0 / (duration_us as f64 / 1e6);
+        }
 
         let properties = CompletedProcess {
             tool_name: proc.name.clone(),
             tool_pid: pid.to_string(),
             duration_sec,
+            exit_code: exit_status.exit_code,
+            // the OOM killer always sends SIGKILL, even if we missed the zombie
+            signal: exit_status.signal.or(oom_killed.then_some(libc::SIGKILL)),
+            oom_killed,
+            resource_usage,
+            input_paths: proc.files.inputs.iter().cloned().collect(),
+            output_paths: proc.files.outputs.iter().cloned().collect(),
+        };
+
+        let outcome = match (
+            properties.oom_killed,
+            properties.signal,
+            properties.exit_code,
+        ) {
+            (true, _, _) => " (killed by the OOM killer)".to_string(),
+            (false, Some(signal), _) => format!(" (killed by signal {signal})"),
+            (false, None, Some(code)) if code != 0 => format!(" with code {code}"),
+            _ => String::new(),
         };
 
         event_logger.record_event(
             EventType::FinishedToolExecution,
-            format!("[{}] {} exited", Utc::now(), &proc.name),
+            format!("[{}] {} exited{outcome}", end_time, &proc.name),
             Some(EventAttributes::CompletedProcess(properties)),
-            None,
+            Some(end_time),
         );
 
         Ok(())
@@ -654,11 +1062,74 @@ impl ProcessWatcher {
     pub fn is_empty(&self) -> bool {
         self.seen.is_empty()
     }
+
+    pub fn tracked_process_count(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// The names of the tracked tools by the cgroup they run in.
+    pub fn tracked_cgroups(&mut self) -> BTreeMap<String, Vec<String>> {
+        let mut cgroups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (pid, proc) in self.seen.iter().sorted_by_key(|(pid, _)| **pid) {
+            if let Some(cgroup) = self.containers.resolve(pid.as_u32()).cgroup {
+                let names = cgroups.entry(cgroup).or_default();
+                if !names.contains(&proc.name) {
+                    names.push(proc.name.clone());
+                }
+            }
+        }
+        cgroups
+    }
+}
+
+/// Start time from sysinfo, rounded down to whole seconds.
+fn coarse_start_time(proc: &Process) -> DateTime<Utc> {
+    DateTime::from_timestamp(proc.start_time() as i64, 0).unwrap_or_else(Utc::now)
+}
+
+/// All descendants of `pid`, parents before their children.
+fn descendants(children: &HashMap<Pid, Vec<Pid>>, pid: Pid) -> Vec<Pid> {
+    let mut result = vec![];
+    let mut stack = vec![pid];
+    while let Some(pid) = stack.pop() {
+        for child in children.get(&pid).into_iter().flatten() {
+            result.push(*child);
+            stack.push(*child);
+        }
+    }
+    result
+}
+
+/// Folds the current readings of a tracked process into its lifetime totals. Once the process is a
+/// zombie most readings are zero, taking the maximum keeps the last real ones.
+fn observe_resource_usage(usage: &mut ResourceUsage, pid: &Pid, process: &Process) {
+    let (peak_memory, peak_virtual) =
+        procfs::process_memory_peaks(pid.as_u32()).unwrap_or_default();
+    usage.peak_memory_usage = usage
+        .peak_memory_usage
+        .max(peak_memory)
+        .max(process.memory());
+    usage.peak_memory_virtual = usage
+        .peak_memory_virtual
+        .max(peak_virtual)
+        .max(process.virtual_memory());
+
+    if let Some(cpu_time_sec) = procfs::process_cpu_time(pid.as_u32()) {
+        usage.cpu_time_sec = usage.cpu_time_sec.max(cpu_time_sec);
+    }
+
+    let disk_usage = process.disk_usage();
+    usage.disk_usage_read_total = usage.disk_usage_read_total.max(disk_usage.total_read_bytes);
+    usage.disk_usage_write_total = usage
+        .disk_usage_write_total
+        .max(disk_usage.total_written_bytes);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config_manager::target_process::target_matching::TargetMatch;
+    use crate::extracts::ebpf::ReplaySource;
 
     #[test]
     fn test_get_parent_processes() {
@@ -694,6 +1165,10 @@ mod tests {
                 process_disk_usage_write_total: 0,
                 process_status: "test".to_string(),
                 input_files: None,
+                subtree: None,
+                cgroup: None,
+                container: None,
+                details: None,
             };
 
             let node = ProcessTreeNode {
@@ -734,6 +1209,139 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_merged_target_includes_descendants() -> Result<()> {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 2 & sleep 2 & wait"])
+            .spawn()?;
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let system = System::new_all();
+        let mut watcher = ProcessWatcher::new(vec![]);
+        watcher.build_process_trees(system.processes());
+
+        let pid = Pid::from_u32(child.id());
+        let mut properties = ProcessWatcher::gather_process_data(
+            &pid,
+            system.process(pid).unwrap(),
+            None,
+            Utc::now(),
+        );
+        let own_memory = properties.process_memory_usage;
+        watcher.add_subtree_usage(&mut properties, pid, &system);
+        child.wait()?;
+
+        let subtree = properties.subtree.unwrap();
+        assert_eq!(subtree.process_count, 2);
+        assert_eq!(subtree.commands.len(), 1);
+        assert_eq!(subtree.commands[0].command, "sleep");
+        assert_eq!(subtree.commands[0].process_count, 2);
+        assert_eq!(
+            properties.process_memory_usage,
+            own_memory + subtree.commands[0].memory_usage
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_details_only_when_enabled() -> Result<()> {
+        let system = System::new_all();
+        let process = system.process(Pid::from_u32(std::process::id())).unwrap();
+
+        for enabled in [false, true] {
+            let mut watcher = ProcessWatcher::new(vec![]).with_process_details(enabled);
+            let mut event_logger = EventRecorder::default();
+            watcher.add_process_metrics(process, &system, &mut event_logger, None)?;
+
+            let Some(EventAttributes::Process(properties)) =
+                &event_logger.get_events()[0].attributes
+            else {
+                panic!("expected process properties");
+            };
+            assert_eq!(properties.details.is_some(), enabled);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_completed_process_ends_at_last_seen_poll() -> Result<()> {
+        let mut watcher = ProcessWatcher::new(vec![]);
+        let start_time = Utc::now() - chrono::Duration::seconds(10);
+        let last_seen = start_time + chrono::Duration::milliseconds(4500);
+        let proc = Proc {
+            name: "bwa".to_string(),
+            start_time,
+            last_seen,
+            ended_at: None,
+            resource_usage: ResourceUsage {
+                peak_memory_usage: 4096,
+                cpu_time_sec: 2.25,
+                ..Default::default()
+            },
+            subtree: None,
+            last_update: ProcLastUpdate::RefreshesRemaining(2),
+            just_started: false,
+            exit_status: None,
+            files: OpenFiles::default(),
+        };
+        watcher.seen.insert(42.into(), proc);
+
+        let mut event_logger = EventRecorder::default();
+        let mut system = System::new();
+        watcher.remove_completed_processes(&mut system, &mut event_logger)?;
+
+        let event = &event_logger.get_events()[0];
+        assert_eq!(event.timestamp, last_seen);
+        let Some(EventAttributes::CompletedProcess(completed)) = &event.attributes else {
+            panic!("expected a completed process");
+        };
+        assert_eq!(completed.duration_sec, 4);
+        assert_eq!(completed.resource_usage.peak_memory_usage, 4096);
+        assert_eq!(completed.resource_usage.average_cpu_utilization, 50.0);
+        assert!(watcher.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replayed_short_lived_tool() -> Result<()> {
+        let start_time = Utc::now() - chrono::Duration::seconds(5);
+        let end_time = start_time + chrono::Duration::microseconds(400);
+        // a PID that can't exist, so polling never sees it
+        let pid = u32::MAX - 1;
+        let events = vec![
+            ProcessEvent::Exec {
+                pid,
+                ppid: 1,
+                name: "bwa".to_string(),
+                argv: vec!["bwa".to_string(), "index".to_string()],
+                timestamp: start_time,
+            },
+            ProcessEvent::Exit {
+                pid,
+                wait_status: 2 << 8,
+                timestamp: end_time,
+            },
+        ];
+        let target = Target::new(TargetMatch::ProcessName("bwa".to_string()));
+        let mut watcher = ProcessWatcher::new(vec![target])
+            .with_event_source(Some(Box::new(ReplaySource::new(events))));
+
+        let mut event_logger = EventRecorder::default();
+        let mut system = System::new();
+        watcher.poll_processes(&mut system, &mut event_logger, &FileWatcher::new())?;
+        watcher.remove_completed_processes(&mut system, &mut event_logger)?;
+
+        let events = event_logger.get_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, start_time);
+        assert_eq!(events[1].timestamp, end_time);
+        let Some(EventAttributes::CompletedProcess(completed)) = &events[1].attributes else {
+            panic!("expected a completed process");
+        };
+        assert_eq!(completed.exit_code, Some(2));
+        Ok(())
+    }
+
     #[test]
     fn test_count_dataset_matches_works() {
         let command: Vec<String> =
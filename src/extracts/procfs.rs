@@ -0,0 +1,461 @@
+// src/extracts/procfs.rs
+//! Small readers for `/proc`, for the figures sysinfo doesn't expose or only rounds to seconds.
+use chrono::{DateTime, TimeDelta, Utc};
+use once_cell::sync::Lazy;
+use std::fs;
+
+use crate::types::event::attributes::{
+    process::{ProcessDetails, ProcessIo},
+    system_metrics::PressureStall,
+};
+
+/// Positions of `utime`, `stime` and `starttime` in `/proc/<pid>/stat`, counted from the state
+/// field.
+const STAT_USER_TIME_INDEX: usize = 11;
+const STAT_SYSTEM_TIME_INDEX: usize = 12;
+const STAT_START_TIME_INDEX: usize = 19;
+/// Position of `delayacct_blkio_ticks`, counted from the state field.
+const STAT_BLKIO_DELAY_INDEX: usize = 39;
+
+/// Computed once: the boot time doesn't change, and deriving it again from a later uptime reading
+/// would only add jitter.
+static BOOT_TIME: Lazy<Option<DateTime<Utc>>> = Lazy::new(read_boot_time);
+
+pub fn read_stat(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{pid}/stat")).ok()
+}
+
+/// The fields of a `/proc/<pid>/stat` line following the command name, starting with the state.
+pub fn stat_fields(stat: &str) -> Option<Vec<&str>> {
+    // the command name may contain spaces and parentheses, the fields start after the last ')'
+    let (_, fields) = stat.rsplit_once(')')?;
+    Some(fields.split_whitespace().collect())
+}
+
+pub fn clock_ticks_per_sec() -> u64 {
+    // SAFETY: sysconf has no preconditions
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 {
+        ticks as u64
+    } else {
+        100
+    }
+}
+
+/// Boot time with sub-second precision. `btime` in `/proc/stat` is rounded to whole seconds,
+/// the uptime has centiseconds.
+pub fn boot_time() -> Option<DateTime<Utc>> {
+    *BOOT_TIME
+}
+
+fn read_boot_time() -> Option<DateTime<Utc>> {
+    let uptime = fs::read_to_string("/proc/uptime").ok()?;
+    let uptime_sec: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    Some(Utc::now() - TimeDelta::microseconds((uptime_sec * 1e6) as i64))
+}
+
+/// When the kernel started the process, with clock tick precision.
+pub fn process_start_time(pid: u32) -> Option<DateTime<Utc>> {
+    let start_ticks = parse_start_ticks(&read_stat(pid)?)?;
+    Some(boot_time()? + ticks_to_duration(start_ticks, clock_ticks_per_sec()))
+}
+
+fn parse_start_ticks(stat: &str) -> Option<u64> {
+    stat_fields(stat)?.get(STAT_START_TIME_INDEX)?.parse().ok()
+}
+
+/// User plus system CPU time the process used so far, in seconds. Still readable for zombies.
+pub fn process_cpu_time(pid: u32) -> Option<f64> {
+    let ticks = parse_cpu_ticks(&read_stat(pid)?)?;
+    Some(ticks as f64 / clock_ticks_per_sec() as f64)
+}
+
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    let fields = stat_fields(stat)?;
+    let user: u64 = fields.get(STAT_USER_TIME_INDEX)?.parse().ok()?;
+    let system: u64 = fields.get(STAT_SYSTEM_TIME_INDEX)?.parse().ok()?;
+    Some(user + system)
+}
+
+/// Peak resident and virtual memory in bytes (`VmHWM` and `VmPeak`). The kernel keeps these, so
+/// peaks between polls aren't missed.
+pub fn process_memory_peaks(pid: u32) -> Option<(u64, u64)> {
+    parse_memory_peaks(&fs::read_to_string(format!("/proc/{pid}/status")).ok()?)
+}
+
+fn parse_memory_peaks(status: &str) -> Option<(u64, u64)> {
+    let kilobytes = |key: &str| -> Option<u64> {
+        let line = status.lines().find_map(|line| line.strip_prefix(key))?;
+        Some(line.split_whitespace().next()?.parse::<u64>().ok()? * 1024)
+    };
+    Some((kilobytes("VmHWM:")?, kilobytes("VmPeak:")?))
+}
+
+/// Threads, fds, context switches, block IO wait and IO counters of the process. `None` once it
+/// exited.
+pub fn process_details(pid: u32) -> Option<ProcessDetails> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let count = |key: &str| -> u64 {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or_default()
+    };
+    let blkio_delay_ticks = read_stat(pid)
+        .and_then(|stat| {
+            stat_fields(&stat)?
+                .get(STAT_BLKIO_DELAY_INDEX)?
+                .parse()
+                .ok()
+        })
+        .unwrap_or(0u64);
+
+    Some(ProcessDetails {
+        threads: count("Threads:"),
+        open_fds: fs::read_dir(format!("/proc/{pid}/fd"))
+            .ok()
+            .map(|fds| fds.count() as u64),
+        fd_limit: fs::read_to_string(format!("/proc/{pid}/limits"))
+            .ok()
+            .and_then(|limits| parse_fd_limit(&limits)),
+        voluntary_context_switches: count("voluntary_ctxt_switches:"),
+        involuntary_context_switches: count("nonvoluntary_ctxt_switches:"),
+        blkio_delay_sec: blkio_delay_ticks as f64 / clock_ticks_per_sec() as f64,
+        io: fs::read_to_string(format!("/proc/{pid}/io"))
+            .ok()
+            .map(|io| parse_process_io(&io)),
+    })
+}
+
+/// The soft limit, `None` when unlimited.
+fn parse_fd_limit(limits: &str) -> Option<u64> {
+    limits
+        .lines()
+        .find_map(|line| line.strip_prefix("Max open files"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn parse_process_io(io: &str) -> ProcessIo {
+    let mut counters = ProcessIo::default();
+    for line in io.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let counter = match key {
+            "rchar" => &mut counters.read_chars,
+            "wchar" => &mut counters.write_chars,
+            "syscr" => &mut counters.read_syscalls,
+            "syscw" => &mut counters.write_syscalls,
+            "cancelled_write_bytes" => &mut counters.cancelled_write_bytes,
+            _ => continue,
+        };
+        *counter = value.trim().parse().unwrap_or_default();
+    }
+    counters
+}
+
+/// Scheduler counters from `/proc/stat`.
+#[derive(Debug, Default, PartialEq)]
+pub struct SchedulerStats {
+    pub context_switches: u64,
+    pub procs_running: u64,
+    pub procs_blocked: u64,
+}
+
+pub fn scheduler_stats() -> Option<SchedulerStats> {
+    Some(parse_scheduler_stats(
+        &fs::read_to_string("/proc/stat").ok()?,
+    ))
+}
+
+fn parse_scheduler_stats(stat: &str) -> SchedulerStats {
+    let mut stats = SchedulerStats::default();
+    for line in stat.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let field = match key {
+            "ctxt" => &mut stats.context_switches,
+            "procs_running" => &mut stats.procs_running,
+            "procs_blocked" => &mut stats.procs_blocked,
+            _ => continue,
+        };
+        *field = value.trim().parse().unwrap_or_default();
+    }
+    stats
+}
+
+/// Major page faults since boot, from `/proc/vmstat`.
+pub fn major_faults() -> Option<u64> {
+    let vmstat = fs::read_to_string("/proc/vmstat").ok()?;
+    vmstat
+        .lines()
+        .find_map(|line| line.strip_prefix("pgmajfault "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// `resource` is `cpu`, `memory` or `io`.
+pub fn pressure(resource: &str) -> Option<PressureStall> {
+    parse_pressure(&fs::read_to_string(format!("/proc/pressure/{resource}")).ok()?)
+}
+
+fn parse_pressure(content: &str) -> Option<PressureStall> {
+    let mut stall = PressureStall::default();
+    let mut has_some = false;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let (avg10, avg60, avg300, total) = match fields.next()? {
+            "some" => {
+                has_some = true;
+                (
+                    &mut stall.some_avg10,
+                    &mut stall.some_avg60,
+                    &mut stall.some_avg300,
+                    &mut stall.some_total_usec,
+                )
+            }
+            "full" => (
+                &mut stall.full_avg10,
+                &mut stall.full_avg60,
+                &mut stall.full_avg300,
+                &mut stall.full_total_usec,
+            ),
+            _ => continue,
+        };
+        for (key, value) in fields.filter_map(|field| field.split_once('=')) {
+            match key {
+                "avg10" => *avg10 = value.parse().ok()?,
+                "avg60" => *avg60 = value.parse().ok()?,
+                "avg300" => *avg300 = value.parse().ok()?,
+                "total" => *total = value.parse().ok()?,
+                _ => {}
+            }
+        }
+    }
+    has_some.then_some(stall)
+}
+
+/// Whether a file was opened for reading only, or with write access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileAccess {
+    Read,
+    Write,
+}
+
+/// Regular files the process has open, by the access mode of their fds. Pseudo filesystems and
+/// deleted files are left out. Empty when the fds aren't readable, they are only readable by
+/// the owner of the process.
+pub fn open_files(pid: u32) -> Vec<(String, FileAccess)> {
+    let Ok(fds) = fs::read_dir(format!("/proc/{pid}/fd")) else {
+        return vec![];
+    };
+    fds.flatten()
+        .filter_map(|fd| {
+            let path = fs::read_link(fd.path())
+                .ok()?
+                .into_os_string()
+                .into_string()
+                .ok()?;
+            if !is_data_file(&path) || !fs::metadata(fd.path()).ok()?.is_file() {
+                return None;
+            }
+            let fdinfo = format!("/proc/{pid}/fdinfo/{}", fd.file_name().to_string_lossy());
+            let flags = parse_fdinfo_flags(&fs::read_to_string(fdinfo).ok()?)?;
+            Some((path, access_from_flags(flags)))
+        })
+        .collect()
+}
+
+/// Sockets, pipes and anonymous inodes read as `socket:[123]` and the like, and deleted files get
+/// a ` (deleted)` suffix.
+fn is_data_file(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.ends_with(" (deleted)")
+        && !["/proc/", "/sys/", "/dev/"]
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+}
+
+/// The `flags` field of `/proc/<pid>/fdinfo/<fd>`, which is octal.
+fn parse_fdinfo_flags(fdinfo: &str) -> Option<u32> {
+    let flags = fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("flags:"))?;
+    u32::from_str_radix(flags.trim(), 8).ok()
+}
+
+fn access_from_flags(flags: u32) -> FileAccess {
+    if flags & libc::O_ACCMODE as u32 == libc::O_RDONLY as u32 {
+        FileAccess::Read
+    } else {
+        FileAccess::Write
+    }
+}
+
+pub fn ticks_to_duration(ticks: u64, ticks_per_sec: u64) -> TimeDelta {
+    TimeDelta::microseconds((ticks as i64).saturating_mul(1_000_000) / ticks_per_sec.max(1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stat_fields_with_odd_command_names() {
+        let stat = "42 (bwa) mem (x)) S 1 42 42 0 -1 4194304 100 0 0 0 5 6 0 0 20 0 1 0 1234 0";
+        let fields = stat_fields(stat).unwrap();
+        assert_eq!(fields[0], "S");
+        assert_eq!(parse_start_ticks(stat), Some(1234));
+        assert_eq!(parse_cpu_ticks(stat), Some(11));
+    }
+
+    #[test]
+    fn test_parse_memory_peaks() {
+        let status = "Name:\tbwa\nVmPeak:\t  20480 kB\nVmSize:\t  10240 kB\nVmHWM:\t    4096 kB\n";
+        assert_eq!(
+            parse_memory_peaks(status),
+            Some((4096 * 1024, 20480 * 1024))
+        );
+        // kernel threads have no memory lines
+        assert_eq!(parse_memory_peaks("Name:\tkthreadd\n"), None);
+    }
+
+    #[test]
+    fn test_details_of_own_process() {
+        let details = process_details(std::process::id()).unwrap();
+        assert!(details.threads >= 1);
+        // stdin, stdout and stderr at least
+        assert!(details.open_fds.unwrap() >= 3);
+        assert!(details.io.unwrap().read_syscalls > 0);
+
+        let limits = "Limit                     Soft Limit           Hard Limit           Units     \nMax cpu time              unlimited            unlimited            seconds   \nMax open files            1024                 1048576              files     \n";
+        assert_eq!(parse_fd_limit(limits), Some(1024));
+        assert_eq!(
+            parse_fd_limit(
+                "Max open files            unlimited            unlimited            files\n"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_process_io() {
+        let io = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\nread_bytes: 0\nwrite_bytes: 323932160\ncancelled_write_bytes: 4096\n";
+        assert_eq!(
+            parse_process_io(io),
+            ProcessIo {
+                read_chars: 323934931,
+                write_chars: 323929600,
+                read_syscalls: 632687,
+                write_syscalls: 632675,
+                cancelled_write_bytes: 4096,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_fdinfo_flags() {
+        let fdinfo = "pos:\t0\nflags:\t0100002\nmnt_id:\t29\nino:\t1234\n";
+        let flags = parse_fdinfo_flags(fdinfo).unwrap();
+        assert_eq!(flags, 0o100002);
+        assert_eq!(access_from_flags(flags), FileAccess::Write);
+        assert_eq!(access_from_flags(0o2100000), FileAccess::Read);
+        assert_eq!(access_from_flags(0o101), FileAccess::Write);
+        assert!(parse_fdinfo_flags("pos:\t0\n").is_none());
+
+        assert!(is_data_file("/data/sample_R1.fastq.gz"));
+        assert!(!is_data_file("/data/tmp.bam (deleted)"));
+        assert!(!is_data_file("socket:[4242]"));
+        assert!(!is_data_file("/dev/null"));
+    }
+
+    #[test]
+    fn test_open_files_of_child_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("reads.fastq");
+        let output = dir.path().join("trimmed.fastq");
+        fs::write(&input, "@read\nACGT\n+\nIIII\n").unwrap();
+
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(r#"exec 3<"$1" 4>"$2"; sleep 5"#)
+            .arg("sh")
+            .arg(&input)
+            .arg(&output)
+            .spawn()
+            .unwrap();
+        // wait for the shell to open both
+        let pid = child.id();
+        let mut files = vec![];
+        for _ in 0..100 {
+            files = open_files(pid);
+            if files.len() == 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            files,
+            vec![
+                (input.to_string_lossy().into_owned(), FileAccess::Read),
+                (output.to_string_lossy().into_owned(), FileAccess::Write),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_pressure() {
+        let io = "some avg10=0.19 avg60=1.81 avg300=1.77 total=121584594\nfull avg10=0.08 avg60=1.23 avg300=1.22 total=83518726\n";
+        let stall = parse_pressure(io).unwrap();
+        assert_eq!(stall.some_avg60, 1.81);
+        assert_eq!(stall.some_total_usec, 121584594);
+        assert_eq!(stall.full_avg10, 0.08);
+        assert_eq!(stall.full_total_usec, 83518726);
+
+        // the CPU has no full line before Linux 5.13
+        let cpu = parse_pressure("some avg10=1.65 avg60=4.54 avg300=5.25 total=511457477\n");
+        assert_eq!(cpu.unwrap().full_avg10, 0.0);
+        assert_eq!(parse_pressure(""), None);
+    }
+
+    #[test]
+    fn test_parse_scheduler_stats() {
+        let stat = "cpu  1 2 3 4\nintr 100 1 2\nctxt 2846251\nbtime 1700000000\nprocesses 9000\nprocs_running 2\nprocs_blocked 1\n";
+        assert_eq!(
+            parse_scheduler_stats(stat),
+            SchedulerStats {
+                context_switches: 2846251,
+                procs_running: 2,
+                procs_blocked: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_start_time_of_child_process() {
+        let before = Utc::now();
+        let mut child = std::process::Command::new("sleep")
+            .arg("1")
+            .spawn()
+            .unwrap();
+        let start_time = process_start_time(child.id());
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // one clock tick and the uptime resolution are the error margin
+        let start_time = start_time.unwrap();
+        let margin = TimeDelta::milliseconds(50);
+        assert!(start_time >= before - margin, "{start_time} < {before}");
+        assert!(start_time <= Utc::now() + margin);
+    }
+}
@@ -13,11 +13,10 @@ pub mod types;
 pub mod utils;
 use anyhow::{Context, Ok, Result};
 use daemonize::Daemonize;
-use exporters::db::AuroraClient;
+use exporters::fanout::ExporterFanOut;
 use types::cli::TracerCliInitArgs;
 
 use std::fs::File;
-use std::sync::Arc;
 
 use crate::config_manager::ConfigManager;
 use crate::tracer_client::TracerClient;
@@ -62,13 +61,14 @@ pub async fn run(
 ) -> Result<()> {
     let raw_config = ConfigManager::load_config();
 
-    // create the conn pool to aurora
-    let db_client = Arc::new(AuroraClient::new(&raw_config.db_url, None).await);
+    let exporter = ExporterFanOut::from_config(&raw_config)
+        .await
+        .context("Failed to set up exporters")?;
 
     let client = TracerClient::new(
         raw_config.clone(),
         workflow_directory_path,
-        db_client,
+        exporter,
         cli_config_args,
     )
     .await
@@ -93,12 +93,10 @@ pub async fn monitor_processes_with_tracer_client(tracer_client: &mut TracerClie
 mod tests {
     use crate::{
         config_manager::{Config, ConfigManager},
-        exporters::db::AuroraClient,
+        exporters::{fanout::ExporterFanOut, memory::MemoryExporter},
         types::cli::TracerCliInitArgs,
     };
 
-    use std::sync::Arc;
-
     use crate::{monitor_processes_with_tracer_client, TracerClient};
     use dotenv::dotenv;
 
@@ -119,12 +117,16 @@ mod tests {
 
         setup_env_vars(region);
 
-        let aurora_client = Arc::new(AuroraClient::new(&config.db_url, None).await);
+        let spool_dir = tempfile::tempdir().unwrap();
+        let mut exporter = ExporterFanOut::new();
+        exporter
+            .add_exporter(Box::new(MemoryExporter::new()), spool_dir.path(), 1024)
+            .unwrap();
 
         let mut tracer_client = TracerClient::new(
             config,
             pwd.to_str().unwrap().to_string(),
-            aurora_client,
+            exporter,
             TracerCliInitArgs::default(),
         )
         .await
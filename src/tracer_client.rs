@@ -2,11 +2,14 @@
 use crate::cloud_providers::aws::PricingClient;
 use crate::config_manager::{self, Config};
 use crate::events::{
+    lineage::LineageTracker,
     recorder::{EventRecorder, EventType},
     send_start_run_event,
 };
-use crate::exporters::db::AuroraClient;
+use crate::exporters::{fanout::ExporterFanOut, prometheus::PrometheusMetrics, EventBatch};
 use crate::extracts::{
+    cgroups::CgroupCollector,
+    ebpf::open_event_source,
     file_watcher::FileWatcher,
     metrics::SystemMetricsCollector,
     process_watcher::{ProcessWatcher, ShortLivedProcessLog},
@@ -14,19 +17,24 @@ use crate::extracts::{
     syslog::{run_syslog_lines_read_thread, SyslogWatcher},
 };
 use crate::types::cli::TracerCliInitArgs;
-use crate::types::event::attributes::EventAttributes;
+use crate::types::event::attributes::{lineage::RunLineage, EventAttributes};
 use crate::{monitor_processes_with_tracer_client, FILE_CACHE_DIR};
 use crate::{SOCKET_PATH, SYSLOG_FILE};
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use chrono::{DateTime, TimeDelta, Utc};
 use std::borrow::BorrowMut;
+use std::collections::VecDeque;
 use std::ops::Sub;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use sysinfo::{Pid, System};
 use tokio::sync::{Mutex, RwLock};
 
-use crate::daemon_communication::server::run_server;
+use crate::daemon_communication::{
+    metrics_server::run_metrics_server,
+    server::run_server,
+    structs::{ExportAttempt, InfoResponse, StatusResponse},
+};
 use config_manager::{INTERCEPTOR_STDERR_FILE, INTERCEPTOR_STDOUT_FILE};
 
 use tokio::time::sleep;
@@ -50,6 +58,8 @@ pub struct RunMetadata {
 
 const RUN_COMPLICATED_PROCESS_IDENTIFICATION: bool = false;
 const WAIT_FOR_PROCESS_BEFORE_NEW_RUN: bool = false;
+/// Lineages of ended runs kept for `tracer lineage`
+const RECENT_LINEAGES: usize = 16;
 
 pub type LinesBufferArc = Arc<RwLock<Vec<String>>>;
 
@@ -65,25 +75,32 @@ pub struct TracerClient {
     syslog_watcher: SyslogWatcher,
     stdout_watcher: StdoutWatcher,
     metrics_collector: SystemMetricsCollector,
+    cgroup_collector: CgroupCollector,
     file_watcher: FileWatcher,
     workflow_directory: String,
     current_run: Option<RunMetadata>,
     syslog_lines_buffer: LinesBufferArc,
     stdout_lines_buffer: LinesBufferArc,
     stderr_lines_buffer: LinesBufferArc,
-    pub db_client: Arc<AuroraClient>,
+    exporter: ExporterFanOut,
+    metrics: PrometheusMetrics,
     pipeline_name: String,
     pub pricing_client: PricingClient,
     initialization_id: Option<String>,
     config: Config,
     tags: Vec<String>,
+    started_at: DateTime<Utc>,
+    last_export_success: Option<ExportAttempt>,
+    last_export_failure: Option<ExportAttempt>,
+    lineage: LineageTracker,
+    recent_lineages: VecDeque<RunLineage>,
 }
 
 impl TracerClient {
     pub async fn new(
         config: Config,
         workflow_directory: String,
-        db_client: Arc<AuroraClient>,
+        exporter: ExporterFanOut,
         cli_args: TracerCliInitArgs,
     ) -> Result<TracerClient> {
         let service_url = config.service_url.clone();
@@ -120,14 +137,26 @@ impl TracerClient {
             syslog_lines_buffer: Arc::new(RwLock::new(Vec::new())),
             stdout_lines_buffer: Arc::new(RwLock::new(Vec::new())),
             stderr_lines_buffer: Arc::new(RwLock::new(Vec::new())),
-            process_watcher: ProcessWatcher::new(config.targets.clone()),
+            process_watcher: ProcessWatcher::new(config.targets.clone())
+                .with_event_source(open_event_source(
+                    config.process_events_replay_file.as_deref(),
+                ))
+                .with_container_runtime_socket(config.container_runtime_socket.clone())
+                .with_process_details(config.collect_process_details),
             metrics_collector: SystemMetricsCollector::new(),
-            db_client,
+            cgroup_collector: CgroupCollector::new(),
+            exporter,
+            metrics: PrometheusMetrics::new(),
             pipeline_name: cli_args.pipeline_name,
             pricing_client,
             initialization_id: cli_args.run_id,
             config,
             tags: cli_args.tags,
+            started_at: Utc::now(),
+            last_export_success: None,
+            last_export_failure: None,
+            lineage: LineageTracker::default(),
+            recent_lineages: VecDeque::new(),
         })
     }
 
@@ -163,9 +192,9 @@ impl TracerClient {
             self.pipeline_name
         );
         let run_name = if let Some(run) = &self.current_run {
-            &run.name
+            run.name.clone()
         } else {
-            "annoymous"
+            "annoymous".to_string()
         };
 
         println!("Submitting batched data for run_name {}", run_name);
@@ -174,10 +203,17 @@ impl TracerClient {
             self.metrics_collector
                 .collect_metrics(&mut self.system, &mut self.logs)
                 .context("Failed to collect metrics")?;
+            self.cgroup_collector
+                .collect_metrics(&self.process_watcher.tracked_cgroups(), &mut self.logs);
 
-            self.db_client
-                .batch_insert_events(run_name, self.logs.get_events())
-                .await?;
+            if self.current_run.is_some() {
+                self.lineage.observe(self.logs.get_events());
+            }
+            let batch = EventBatch {
+                job_id: run_name,
+                events: self.logs.get_events().to_vec(),
+            };
+            self.export_batch(batch).await;
 
             self.last_sent = Some(Instant::now());
             self.logs.clear();
@@ -188,6 +224,91 @@ impl TracerClient {
         }
     }
 
+    /// Sends `batch` to every exporter. Exporters that fail spool the batch for a later retry,
+    /// so a failed export never stops the daemon.
+    async fn export_batch(&mut self, batch: EventBatch) {
+        self.metrics.observe_batch(&batch);
+
+        let result = self.exporter.export(&batch).await;
+        if let Err(err) = &result {
+            eprintln!("{err:#}");
+        }
+        self.record_export_attempt(batch.events.len(), &result);
+        self.metrics
+            .set_export_failures(self.exporter.export_failures());
+
+        if let Err(err) = self.exporter.flush().await {
+            eprintln!("{err:#}");
+        }
+    }
+
+    fn record_export_attempt(&mut self, events: usize, result: &Result<()>) {
+        let attempt = ExportAttempt {
+            timestamp: Utc::now(),
+            events,
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+        };
+        if result.is_ok() {
+            self.last_export_success = Some(attempt);
+        } else {
+            self.last_export_failure = Some(attempt);
+        }
+    }
+
+    pub async fn get_status(&self) -> StatusResponse {
+        let run = self.current_run.as_ref().map(|run| InfoResponse {
+            run_name: run.name.clone(),
+            run_id: run.id.clone(),
+            pipeline_name: self.pipeline_name.clone(),
+        });
+
+        StatusResponse {
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: self.started_at,
+            uptime_sec: (Utc::now() - self.started_at).num_seconds().max(0) as u64,
+            run,
+            tracked_processes: self.process_watcher.tracked_process_count(),
+            pending_events: self.logs.count_by_type(),
+            last_export_success: self.last_export_success.clone(),
+            last_export_failure: self.last_export_failure.clone(),
+            spooled_batches: self.exporter.pending_batches(),
+            syslog_buffer_depth: self.syslog_lines_buffer.read().await.len(),
+            stdout_buffer_depth: self.stdout_lines_buffer.read().await.len(),
+            stderr_buffer_depth: self.stderr_lines_buffer.read().await.len(),
+            last_file_poll: self.file_watcher.get_last_poll_status().cloned(),
+        }
+    }
+
+    /// Registry behind the Prometheus endpoint, updated with every exported batch.
+    pub fn get_metrics(&self) -> PrometheusMetrics {
+        self.metrics.clone()
+    }
+
+    /// Lineage of the current run so far, or of one of the last ended runs. `run_id` defaults to
+    /// the current run.
+    pub fn get_lineage(&self, run_id: Option<&str>) -> Result<RunLineage> {
+        let current = self.current_run.as_ref();
+        let Some(run_id) = run_id.or(current.map(|run| run.id.as_str())) else {
+            bail!("No run in progress, pass the ID of an ended run");
+        };
+        if current.is_some_and(|run| run.id == run_id) {
+            // events not exported yet haven't been observed
+            let mut tracker = self.lineage.clone();
+            tracker.observe(self.logs.get_events());
+            return Ok(tracker.lineage());
+        }
+        self.recent_lineages
+            .iter()
+            .find(|lineage| lineage.run_id == run_id)
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "No lineage for run {run_id}, the daemon only keeps the last \
+                     {RECENT_LINEAGES} runs since it started"
+                )
+            })
+    }
+
     pub fn get_run_metadata(&self) -> Option<RunMetadata> {
         self.current_run.clone()
     }
@@ -250,6 +371,7 @@ impl TracerClient {
             name: result.run_name.clone(),
             id: result.run_id.clone(),
         });
+        self.lineage.start(&result.run_id);
         self.logs.update_run_details(
             Some(self.pipeline_name.clone()),
             Some(result.run_name),
@@ -270,6 +392,23 @@ impl TracerClient {
 
     pub async fn stop_run(&mut self) -> Result<()> {
         if self.current_run.is_some() {
+            self.lineage.observe(self.logs.get_events());
+            let lineage = self.lineage.finish();
+            self.logs.record_event(
+                EventType::RunLineage,
+                format!(
+                    "[CLI] Lineage of {} tools and {} files",
+                    lineage.tool_count(),
+                    lineage.file_count()
+                ),
+                Some(EventAttributes::RunLineage(lineage.clone())),
+                Some(Utc::now()),
+            );
+            if self.recent_lineages.len() == RECENT_LINEAGES {
+                self.recent_lineages.pop_front();
+            }
+            self.recent_lineages.push_back(lineage);
+
             self.logs.record_event(
                 EventType::FinishedRun,
                 "[CLI] Finishing pipeline run".to_owned(),
@@ -279,13 +418,11 @@ impl TracerClient {
             // clear events containing this run
             let run_metadata = self.current_run.as_ref().unwrap();
 
-            if let Err(err) = self
-                .db_client
-                .batch_insert_events(&run_metadata.name, self.logs.get_events())
-                .await
-            {
-                println!("Error outputing end run logs: {err}")
+            let batch = EventBatch {
+                job_id: run_metadata.name.clone(),
+                events: self.logs.get_events().to_vec(),
             };
+            self.export_batch(batch).await;
             self.logs.clear();
 
             self.logs.update_run_details(
@@ -401,12 +538,34 @@ impl TracerClient {
 
         let cancellation_token = CancellationToken::new();
 
-        tokio::spawn(run_server(
+        let server = tokio::spawn(run_server(
             tracer_client.clone(),
             SOCKET_PATH,
             cancellation_token.clone(),
             config.clone(),
         ));
+        tokio::spawn(async move {
+            match server.await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => eprintln!("Daemon socket server stopped: {err:?}"),
+                Err(err) => eprintln!("Daemon socket server crashed: {err}"),
+            }
+        });
+
+        if let Some(listen_addr) = config.read().await.metrics_listen_addr.clone() {
+            let metrics_server = tokio::spawn(run_metrics_server(
+                tracer_client.lock().await.get_metrics(),
+                listen_addr,
+                cancellation_token.clone(),
+            ));
+            tokio::spawn(async move {
+                match metrics_server.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => eprintln!("Metrics endpoint stopped: {err:?}"),
+                    Err(err) => eprintln!("Metrics endpoint crashed: {err}"),
+                }
+            });
+        }
 
         let syslog_lines_task = tokio::spawn(run_syslog_lines_read_thread(
             SYSLOG_FILE,
@@ -456,9 +615,10 @@ impl TracerClient {
         syslog_lines_task.abort();
         stdout_lines_task.abort();
 
-        // close the connection pool to aurora
         let guard = tracer_client.lock().await;
-        let _ = guard.db_client.close().await;
+        if let Err(err) = guard.exporter.close().await {
+            eprintln!("{err:#}");
+        }
 
         Ok(())
     }
@@ -469,11 +629,20 @@ mod tests {
     use super::*;
     use crate::config_manager::ConfigManager;
     use crate::events::recorder::EventType;
+    use crate::exporters::memory::MemoryExporter;
     use anyhow::Result;
-    use serde_json::Value;
-    use sqlx::types::Json;
+    use std::path::Path;
     use tempfile::tempdir;
 
+    fn memory_exporter(spool_dir: &Path) -> (ExporterFanOut, MemoryExporter) {
+        let memory_exporter = MemoryExporter::new();
+        let mut exporter = ExporterFanOut::new();
+        exporter
+            .add_exporter(Box::new(memory_exporter.clone()), spool_dir, 1024 * 1024)
+            .expect("Failed to add memory exporter");
+        (exporter, memory_exporter)
+    }
+
     #[tokio::test]
     async fn test_submit_batched_data() -> Result<()> {
         // Load the configuration
@@ -483,12 +652,11 @@ mod tests {
 
         let work_dir = temp_dir.path().to_str().unwrap();
 
-        // Create an instance of AuroraClient
-        let db_client = Arc::new(AuroraClient::new(&config.db_url, Some(1)).await);
+        let (exporter, memory_exporter) = memory_exporter(&temp_dir.path().join("spool"));
 
         let cli_config = TracerCliInitArgs::default();
 
-        let mut client = TracerClient::new(config, work_dir.to_string(), db_client, cli_config)
+        let mut client = TracerClient::new(config, work_dir.to_string(), exporter, cli_config)
             .await
             .expect("Failed to create tracerclient");
 
@@ -511,19 +679,95 @@ mod tests {
         let res = client.submit_batched_data().await;
         assert!(res.is_ok());
 
-        // Prepare the SQL query
-        let query = "SELECT data, job_id FROM batch_jobs_logs WHERE job_id = $1";
+        // Verify the batch reached the exporter
+        let batches = memory_exporter.batches();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].job_id, run_name);
+        assert!(batches[0]
+            .events
+            .iter()
+            .any(|event| event.message.contains(&run_name)));
 
-        let db_client = client.db_client.get_pool();
+        Ok(())
+    }
 
-        // Verify the row was inserted into the database
-        let result: (Json<Value>, String) = sqlx::query_as(query)
-            .bind(run_name.clone()) // Use the job_id for the query
-            .fetch_one(db_client) // Use the pool from the AuroraClient
-            .await?;
+    #[tokio::test]
+    async fn test_lineage_leaves_out_tools_seen_before_the_run() -> Result<()> {
+        let config = ConfigManager::load_default_config();
+        let temp_dir = tempdir()?;
+        let work_dir = temp_dir.path().to_str().unwrap();
+        let (exporter, memory_exporter) = memory_exporter(&temp_dir.path().join("spool"));
+
+        let mut client =
+            TracerClient::new(config, work_dir.to_string(), exporter, Default::default()).await?;
+
+        let before_run =
+            ProcessWatcher::gather_short_lived_process_data(&System::new(), "fastqc").properties;
+        client.logs.record_event(
+            EventType::ToolExecution,
+            "Tool process: fastqc".to_string(),
+            Some(EventAttributes::Process(before_run)),
+            None,
+        );
+        client.submit_batched_data().await?;
+
+        client.start_new_run(None).await?;
+        client.stop_run().await?;
+
+        let lineage = memory_exporter
+            .batches()
+            .iter()
+            .flat_map(|batch| batch.events.iter())
+            .find_map(|event| match &event.attributes {
+                Some(EventAttributes::RunLineage(lineage)) => Some(lineage.clone()),
+                _ => None,
+            })
+            .expect("no lineage exported at the end of the run");
+        assert!(lineage.nodes.is_empty());
+        Ok(())
+    }
 
-        // Check that the inserted data matches the expected data
-        assert_eq!(result.1, run_name.clone()); // Compare with the unique job ID
+    #[tokio::test]
+    async fn test_status_reports_pending_events_and_exports() -> Result<()> {
+        let config = ConfigManager::load_default_config();
+        let temp_dir = tempdir()?;
+        let work_dir = temp_dir.path().to_str().unwrap();
+        let (exporter, memory_exporter) = memory_exporter(&temp_dir.path().join("spool"));
+
+        let mut client =
+            TracerClient::new(config, work_dir.to_string(), exporter, Default::default()).await?;
+
+        for message in ["first", "second"] {
+            client
+                .logs
+                .record_event(EventType::TestEvent, message.to_string(), None, None);
+        }
+        client
+            .logs
+            .record_event(EventType::Alert, "disk almost full".to_string(), None, None);
+
+        let status = client.get_status().await;
+        assert_eq!(status.pending_events["test_event"], 2);
+        assert_eq!(status.pending_events["alert"], 1);
+        assert!(status.run.is_none());
+        assert!(status.last_export_success.is_none());
+
+        client.submit_batched_data().await?;
+        let status = client.get_status().await;
+        assert!(status.pending_events.is_empty());
+        let success = status.last_export_success.expect("no successful export");
+        assert_eq!(success.events, memory_exporter.batches()[0].events.len());
+        assert!(success.error.is_none());
+        assert!(status.last_export_failure.is_none());
+
+        memory_exporter.set_failing(true);
+        tokio::time::sleep(client.interval).await;
+        client.submit_batched_data().await?;
+        let status = client.get_status().await;
+        let failure = status.last_export_failure.expect("no failed export");
+        assert!(failure.error.is_some());
+        assert!(failure.timestamp >= success.timestamp);
+        assert_eq!(status.spooled_batches, 1);
 
         Ok(())
     }
@@ -538,8 +782,7 @@ mod tests {
         let work_dir = temp_dir.path().to_str().unwrap();
         let job_id = "job-1234";
 
-        // Create an instance of AuroraClient
-        let db_client = Arc::new(AuroraClient::new(&config.db_url, Some(1)).await);
+        let (exporter, _) = memory_exporter(&temp_dir.path().join("spool"));
 
         let tags = vec!["Hello".to_string(), "Test".to_string()];
 
@@ -549,7 +792,7 @@ mod tests {
             tags: tags.clone(),
         };
 
-        let mut client = TracerClient::new(config, work_dir.to_string(), db_client, cli_config)
+        let mut client = TracerClient::new(config, work_dir.to_string(), exporter, cli_config)
             .await
             .expect("Failed to create tracerclient");
 
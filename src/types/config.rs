@@ -1,6 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
 #[derive(Clone, Debug)]
 pub enum AwsConfig {
     Profile(String),
     RoleArn(String),
     Env,
 }
+
+/// One entry of the `exporters` list in the config file, every entry becomes its own sink.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExporterConfig {
+    Postgres {
+        /// Falls back to the top level `db_url`
+        db_url: Option<String>,
+        pool_size: Option<u32>,
+        /// Which tables events are written to, only the raw one by default
+        schema: Option<PostgresSchema>,
+        /// Rows per COPY or multi-row insert statement, 1000 by default
+        insert_chunk_size: Option<usize>,
+    },
+    /// Rotating NDJSON files, partitioned by pipeline and run
+    File {
+        dir: String,
+        gzip: Option<bool>,
+        max_segment_size_bytes: Option<u64>,
+        max_segment_age_sec: Option<u64>,
+    },
+    /// One Parquet file per run and event family
+    Parquet {
+        /// Defaults to the tracer export directory, `~/exports`
+        dir: Option<String>,
+    },
+    /// OTLP/HTTP, runs become traces and tool executions spans
+    Otlp {
+        /// Base URL of the collector, e.g. `http://localhost:4318`
+        endpoint: String,
+        protocol: Option<OtlpProtocol>,
+        headers: Option<HashMap<String, String>>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PostgresSchema {
+    /// Only the JSONB `batch_jobs_logs` table
+    #[default]
+    Raw,
+    /// Only the typed `runs`, `tool_executions`, ... tables
+    Normalized,
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OtlpProtocol {
+    #[default]
+    Protobuf,
+    Json,
+}
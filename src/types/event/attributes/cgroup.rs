@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CgroupScope {
+    /// The cgroup tracked tools run in
+    #[default]
+    Tool,
+    /// The closest cgroup all tracked tools of the run are under, e.g. a Slurm job or a pod
+    Run,
+}
+
+impl CgroupScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CgroupScope::Tool => "tool",
+            CgroupScope::Run => "run",
+        }
+    }
+}
+
+/// Accounting of a cgroup v2, as the kernel keeps it. Unlike per-process numbers it includes
+/// kernel memory and page cache, and the limits the cgroup runs into. Counters are totals since
+/// the cgroup was created.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CgroupUsage {
+    /// Path below the cgroup2 mount, e.g. `/system.slice/slurmstepd.scope/job_42`
+    pub cgroup: String,
+    pub scope: CgroupScope,
+    /// Names of the tracked tools in the cgroup, or below it for the run
+    pub tools: Vec<String>,
+    /// `None` without the memory controller
+    pub memory_current: Option<u64>,
+    /// `None` without the memory controller or before Linux 5.19
+    pub memory_peak: Option<u64>,
+    /// `None` when unlimited
+    pub memory_max: Option<u64>,
+    pub memory_anon: u64,
+    /// Page cache
+    pub memory_file: u64,
+    pub memory_kernel: u64,
+    /// Times the usage ran into `memory.max`
+    pub memory_max_events: u64,
+    pub memory_oom_events: u64,
+    pub memory_oom_kill_events: u64,
+    pub cpu_usage_usec: u64,
+    pub cpu_user_usec: u64,
+    pub cpu_system_usec: u64,
+    pub cpu_periods: u64,
+    pub cpu_throttled_periods: u64,
+    pub cpu_throttled_usec: u64,
+    pub io_read_bytes: u64,
+    pub io_write_bytes: u64,
+    pub io_read_ops: u64,
+    pub io_write_ops: u64,
+    /// The cgroup ran into its memory limit, or had a process OOM killed, since the last sample
+    pub memory_limit_hit: bool,
+    /// The cgroup got CPU-throttled since the last sample
+    pub cpu_throttled: bool,
+}
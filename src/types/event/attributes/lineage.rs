@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// Provenance graph of a run: the tool executions and the files they read and wrote. Edges go
+/// from input files to the tools reading them and from tools to the files they wrote, so a file
+/// links the tool that wrote it to the tools that read it afterwards.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RunLineage {
+    pub run_id: String,
+    pub nodes: Vec<LineageNode>,
+    pub edges: Vec<LineageEdge>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineageNodeKind {
+    Tool,
+    File,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LineageNode {
+    /// `tool:<pid>`, with `#<n>` appended for later executions reusing a pid, or `file:<path>`
+    pub id: String,
+    pub kind: LineageNodeKind,
+    /// Tool name, or the file path
+    pub label: String,
+    /// Only set for tools
+    #[serde(default)]
+    pub tool_pid: Option<String>,
+    #[serde(default)]
+    pub tool_cmd: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LineageEdge {
+    pub from: String,
+    pub to: String,
+}
+
+impl RunLineage {
+    pub fn tool_count(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| node.kind == LineageNodeKind::Tool)
+            .count()
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.nodes.len() - self.tool_count()
+    }
+}
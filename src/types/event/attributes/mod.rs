@@ -1,11 +1,17 @@
+use cgroup::CgroupUsage;
+use lineage::RunLineage;
 use process::{CompletedProcess, DataSetsProcessed, ProcessProperties};
 use syslog::SyslogProperties;
 use system_metrics::{SystemMetric, SystemProperties};
 
+pub mod cgroup;
+pub mod lineage;
 pub mod process;
 pub mod syslog;
 pub mod system_metrics;
 
+// events are short-lived and mostly process or metric ones, boxing them isn't worth the churn
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")] // or "snake_case"
 pub enum EventAttributes {
@@ -15,6 +21,8 @@ pub enum EventAttributes {
     Syslog(SyslogProperties),
     SystemProperties(SystemProperties),
     ProcessDatasetStats(DataSetsProcessed),
+    CgroupUsage(CgroupUsage),
+    RunLineage(RunLineage),
     // TODO: take out when done with demo
     Other(serde_json::Value),
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct InputFile {
@@ -27,6 +28,77 @@ pub struct ProcessProperties {
     pub process_disk_usage_write_total: u64,
     pub process_status: String,
     pub input_files: Option<Vec<InputFile>>,
+    /// Set for targets merged with their parents. The usage figures above then include all live
+    /// descendants.
+    #[serde(default)]
+    pub subtree: Option<ProcessSubtree>,
+    /// cgroup v2 path, or the most specific v1 one
+    #[serde(default)]
+    pub cgroup: Option<String>,
+    #[serde(default)]
+    pub container: Option<ContainerInfo>,
+    /// Only read when `collect_process_details` is enabled, it costs a few syscalls per process
+    #[serde(default)]
+    pub details: Option<ProcessDetails>,
+}
+
+/// Figures from `/proc/<pid>` that sysinfo doesn't expose.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ProcessDetails {
+    pub threads: u64,
+    /// `None` when the fds of the process can't be listed, they are only readable by its owner
+    pub open_fds: Option<u64>,
+    /// Soft limit on open files, `None` when unlimited
+    pub fd_limit: Option<u64>,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+    /// Time spent waiting for block IO. Needs delay accounting (`kernel.task_delayacct=1` or
+    /// the `delayacct` boot option), reads 0 without.
+    pub blkio_delay_sec: f64,
+    /// `None` when `/proc/<pid>/io` isn't readable, like the fds
+    pub io: Option<ProcessIo>,
+}
+
+/// `/proc/<pid>/io` counters besides the disk bytes sysinfo reports.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ProcessIo {
+    /// Bytes passed to read and write calls, including page cache hits, pipes and sockets
+    pub read_chars: u64,
+    pub write_chars: u64,
+    pub read_syscalls: u64,
+    pub write_syscalls: u64,
+    /// Bytes written to the page cache but truncated or deleted before reaching the disk
+    pub cancelled_write_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ContainerInfo {
+    /// `docker`, `podman`, `containerd`, `crio`, `cri` when a Kubernetes runtime can't be told
+    /// apart, or `singularity`/`apptainer`
+    pub runtime: String,
+    /// Singularity and Apptainer containers have no ID
+    pub id: Option<String>,
+    /// The image name, or the image file for Singularity and Apptainer. Only known for other
+    /// runtimes when their API socket is configured.
+    pub image: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ProcessSubtree {
+    /// Live descendants, not counting the process itself
+    pub process_count: u64,
+    pub commands: Vec<SubtreeCommand>,
+}
+
+/// Usage of the descendants running one command
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SubtreeCommand {
+    pub command: String,
+    pub process_count: u64,
+    pub cpu_utilization: f32,
+    pub memory_usage: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +106,45 @@ pub struct CompletedProcess {
     pub tool_name: String,
     pub tool_pid: String,
     pub duration_sec: u64,
+    /// Set when the process exited normally, `None` if it was killed or the status was missed
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the process
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(default)]
+    pub oom_killed: bool,
+    #[serde(flatten)]
+    pub resource_usage: ResourceUsage,
+    /// Regular files the tool, or its subtree when merged, had open read-only while it ran.
+    /// Sampled about once a second, so files held open only briefly can be missed.
+    #[serde(default)]
+    pub input_paths: Vec<String>,
+    /// Regular files it had open for writing
+    #[serde(default)]
+    pub output_paths: Vec<String>,
+}
+
+/// Resource usage over the whole lifetime of a process, folded from every poll it was seen in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceUsage {
+    /// Peak resident memory in bytes, as tracked by the kernel where available
+    pub peak_memory_usage: u64,
+    pub peak_memory_virtual: u64,
+    /// User plus system CPU time
+    pub cpu_time_sec: f64,
+    /// CPU time over the duration, 100 for one fully used core
+    pub average_cpu_utilization: f64,
+    pub disk_usage_read_total: u64,
+    pub disk_usage_write_total: u64,
+}
+
+impl CompletedProcess {
+    /// Whether the process is known to have failed: a non-zero exit code, a signal or an OOM kill.
+    pub fn failed(&self) -> bool {
+        self.exit_code.is_some_and(|code| code != 0) || self.signal.is_some() || self.oom_killed
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
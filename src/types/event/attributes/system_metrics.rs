@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::types::event::aws_metadata::AwsInstanceMetaData;
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct DiskStatistic {
     pub disk_total_space: u64,
     pub disk_used_space: u64,
@@ -10,7 +10,77 @@ pub struct DiskStatistic {
     pub disk_utilization: f64,
 }
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// How a CPU core spent its time since the previous sample, in percent.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CpuCoreUsage {
+    pub core: usize,
+    /// Running user, system and interrupt code
+    pub utilization: f64,
+    /// Idle with IO outstanding
+    pub iowait: f64,
+    /// Wanted to run but the hypervisor ran another guest
+    pub steal: f64,
+    /// Current frequency, `None` when the kernel doesn't expose it, like in many VMs
+    pub frequency_mhz: Option<f64>,
+}
+
+/// Throughput of a block device or NFS export, averaged since the previous sample.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DeviceIo {
+    /// Where the device is mounted, empty when it isn't
+    pub mount_points: Vec<String>,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_ops_per_sec: f64,
+    pub write_ops_per_sec: f64,
+}
+
+/// Throughput of a network interface, averaged since the previous sample.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NetworkInterfaceIo {
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    /// Since the previous sample
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+/// Pressure stall information of a resource: the share of wall time tasks were stalled waiting
+/// for it, in percent averaged over 10, 60 and 300 seconds, and the total stall time.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PressureStall {
+    /// At least one task stalled
+    pub some_avg10: f64,
+    pub some_avg60: f64,
+    pub some_avg300: f64,
+    pub some_total_usec: u64,
+    /// All non-idle tasks stalled at once, zero for the CPU before Linux 5.13
+    pub full_avg10: f64,
+    pub full_avg60: f64,
+    pub full_avg300: f64,
+    pub full_total_usec: u64,
+}
+
+/// `None` for kernels built or booted without PSI.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SystemPressure {
+    pub cpu: Option<PressureStall>,
+    pub memory: Option<PressureStall>,
+    pub io: Option<PressureStall>,
+}
+
+impl SystemPressure {
+    pub const RESOURCES: [&'static str; 3] = ["cpu", "memory", "io"];
+
+    /// In the order of [`SystemPressure::RESOURCES`].
+    pub fn stalls(&self) -> [Option<&PressureStall>; 3] {
+        [self.cpu.as_ref(), self.memory.as_ref(), self.io.as_ref()]
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct SystemMetric {
     pub events_name: String,
     pub system_memory_total: u64,
@@ -20,7 +90,45 @@ pub struct SystemMetric {
     pub system_memory_swap_total: u64,
     pub system_memory_swap_used: u64,
     pub system_cpu_utilization: f32,
+    /// Share of CPU time idle with IO outstanding since the previous sample, in percent
+    #[serde(default)]
+    pub system_cpu_iowait: f64,
+    /// Share of CPU time stolen by the hypervisor since the previous sample, in percent. Noisy
+    /// neighbours on shared instances show up here.
+    #[serde(default)]
+    pub system_cpu_steal: f64,
+    /// By core number. Empty in the first sample, like the IO rates.
+    #[serde(default)]
+    pub system_cpu_cores: Vec<CpuCoreUsage>,
+    /// Capacity of the disks by name, their throughput is in `system_device_io`
     pub system_disk_io: HashMap<String, DiskStatistic>,
+    /// By block device name, like `nvme0n1p1`, or NFS export, like `fileserver:/references`.
+    /// Empty in the first sample, there's nothing to compute the rates from yet.
+    #[serde(default)]
+    pub system_device_io: HashMap<String, DeviceIo>,
+    /// By interface name, without the loopback interface
+    #[serde(default)]
+    pub system_network_io: HashMap<String, NetworkInterfaceIo>,
+    #[serde(default)]
+    pub system_pressure: SystemPressure,
+    #[serde(default)]
+    pub system_load_average_1m: f64,
+    #[serde(default)]
+    pub system_load_average_5m: f64,
+    #[serde(default)]
+    pub system_load_average_15m: f64,
+    /// Since boot
+    #[serde(default)]
+    pub system_context_switches_total: u64,
+    /// Runnable tasks, the length of the run queues
+    #[serde(default)]
+    pub system_procs_running: u64,
+    /// Tasks waiting for IO
+    #[serde(default)]
+    pub system_procs_blocked: u64,
+    /// Page faults that had to read from disk, since boot
+    #[serde(default)]
+    pub system_major_faults_total: u64,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]